@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Writes to the pty waiting for output and, for those that got it, how long it took
+#[derive(Default)]
+struct Echo {
+    pending: Vec<Instant>,
+    latencies: Vec<f64>,
+}
+
+/// When the pty last saw output from, and input consumed by, the child.
+/// Updated by the pty task and read from anywhere.
+pub struct Activity {
+    start: Instant,
+    // ms since start
+    last_output: AtomicU64,
+    last_input: AtomicU64,
+    output_bytes: AtomicU64,
+    // bytes of input queued for the pty task and not taken yet, and taken but not written to the pty yet
+    queued_input: AtomicU64,
+    unwritten_input: AtomicU64,
+    echo: Option<Mutex<Echo>>,
+}
+
+impl Activity {
+    /// Measuring echo latencies if `measure_echo`
+    pub fn new(measure_echo: bool) -> Self {
+        Activity {
+            start: Instant::now(),
+            last_output: AtomicU64::new(0),
+            last_input: AtomicU64::new(0),
+            output_bytes: AtomicU64::new(0),
+            queued_input: AtomicU64::new(0),
+            unwritten_input: AtomicU64::new(0),
+            echo: measure_echo.then(Mutex::default),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    pub fn output(&self, bytes: usize) {
+        self.last_output.store(self.now(), Ordering::Relaxed);
+        self.output_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(echo) = &self.echo {
+            let echo = &mut *echo.lock().unwrap();
+            let now = Instant::now();
+            let answered = echo.pending.drain(..).map(|t| (now - t).as_secs_f64() * 1000.0);
+            echo.latencies.extend(answered);
+        }
+    }
+
+    pub fn input(&self) {
+        self.last_input.store(self.now(), Ordering::Relaxed);
+        if let Some(echo) = &self.echo {
+            echo.lock().unwrap().pending.push(Instant::now());
+        }
+    }
+
+    /// Input queued for the child, counted before it is queued so the pty task can't take it first
+    pub fn queued(&self, bytes: usize) {
+        self.queued_input.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Input counted by `queued` that couldn't be queued after all
+    pub fn unqueued(&self, bytes: usize) {
+        let left = |queued: u64| Some(queued.saturating_sub(bytes as u64));
+        _ = self.queued_input.fetch_update(Ordering::Relaxed, Ordering::Relaxed, left);
+    }
+
+    /// Input taken from the queue by the pty task, `unwritten` bytes of what it took left to write to the pty
+    pub fn taken(&self, bytes: usize, unwritten: usize) {
+        self.unqueued(bytes);
+        self.unwritten(unwritten);
+    }
+
+    pub fn unwritten(&self, bytes: usize) {
+        self.unwritten_input.store(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Bytes of input not written to the pty yet, queued or taken by the pty task
+    pub fn pending_input(&self) -> u64 {
+        self.queued_input.load(Ordering::Relaxed).saturating_add(self.unwritten_input.load(Ordering::Relaxed))
+    }
+
+    /// Ms from each write to the pty to the first output after it, for the writes that got output
+    #[cfg(feature = "python")]
+    pub fn echo_latencies(&self) -> Option<Vec<f64>> {
+        self.echo.as_ref().map(|echo| echo.lock().unwrap().latencies.clone())
+    }
+
+    #[cfg(feature = "python")]
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn since_output(&self) -> Duration {
+        Duration::from_millis(self.now() - self.last_output.load(Ordering::Relaxed))
+    }
+
+    pub fn since_input(&self) -> Duration {
+        Duration::from_millis(self.now() - self.last_input.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,39 @@
+use crate::lines::diff_lines;
+use ndarray::Array2;
+
+/// When each cell of the snapshots of a Terminal last changed, counting snapshots
+#[derive(Default)]
+pub struct CellAges {
+    snapshots: u64,
+    // number of the snapshot in which each cell last changed
+    changed: Option<Array2<u64>>,
+}
+
+impl CellAges {
+    /// Account for a new snapshot, all cells changed if there was no previous one
+    /// or it had a different size
+    pub fn update(&mut self, previous: Option<&[avt::Line]>, lines: &[avt::Line]) {
+        self.snapshots += 1;
+        let shape = (lines.len(), lines.first().map(|l| l.len()).unwrap_or(0));
+        match (previous, &mut self.changed) {
+            (Some(previous), Some(changed)) if changed.dim() == shape => {
+                let diff = diff_lines(previous, lines);
+                for (c, &d) in changed.iter_mut().zip(diff.iter()) {
+                    if d {
+                        *c = self.snapshots;
+                    }
+                }
+            }
+            _ => self.changed = Some(Array2::from_elem(shape, self.snapshots)),
+        }
+    }
+
+    pub fn snapshots(&self) -> u64 {
+        self.snapshots
+    }
+
+    /// Snapshots since each cell last changed, 0 if it changed in the last one
+    pub fn ages(&self) -> Option<Array2<u64>> {
+        self.changed.as_ref().map(|changed| changed.mapv(|c| self.snapshots - c))
+    }
+}
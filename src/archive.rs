@@ -0,0 +1,427 @@
+// Snapshots are archived as plain data, the character, colors and attributes of each cell, and loaded back by
+// drawing those cells on a fresh screen, so that the emulator rebuilds the lines as it would from a child's output.
+
+#[cfg(feature = "python")]
+use crate::color::truecolor_from_avt;
+use crate::cursor::Cursor;
+#[cfg(feature = "python")]
+use crate::cursor::SHAPES;
+use crate::geometry;
+use crate::lines::attrs_from_lines;
+use crate::lines::continued_from_lines;
+use crate::lines::ATTR_SGR;
+use crate::palette::Palette;
+use crate::screen::Screen;
+use crate::snapshot::Snapshot;
+
+#[cfg(feature = "python")]
+use ndarray::{Array1, Array2, Array3};
+#[cfg(feature = "python")]
+use numpy::{Element, PyArray, PyReadonlyArrayDyn};
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+use rgb::RGB8;
+#[cfg(feature = "python")]
+use std::path::PathBuf;
+
+/// Version of the layout of archived snapshots
+pub const ARCHIVE_VERSION: u32 = 1;
+
+// what draws a cell: its `ATTR_*` bits, foreground and background
+type CellPen = (u16, Option<avt::Color>, Option<avt::Color>);
+
+/// The contents of a snapshot as plain data, cells in row-major order, from which it can be rebuilt.
+/// Links, underline styles and line sizes are left out.
+#[derive(Clone, Debug)]
+pub struct Archive {
+    pub cols: usize,
+    pub rows: usize,
+    pub chars: Vec<char>,
+    pub foreground: Vec<Option<avt::Color>>,
+    pub background: Vec<Option<avt::Color>>,
+    /// `ATTR_*` bits of each cell
+    pub attrs: Vec<u16>,
+    /// Whether each row is a soft-wrapped continuation of the one before
+    pub continued: Vec<bool>,
+    pub cursor: Cursor,
+    pub alt_screen: bool,
+    pub timestamp: f64,
+    /// The 256 indexed colors
+    pub palette: Vec<RGB8>,
+    pub default_foreground: Option<RGB8>,
+    pub default_background: Option<RGB8>,
+}
+
+// the SGR sequence setting `pen` from scratch
+fn sgr((attrs, foreground, background): CellPen) -> String {
+    fn color(color: avt::Color, base: u8) -> String {
+        match color {
+            avt::Color::Indexed(i) => format!("{};5;{}", base, i),
+            avt::Color::RGB(c) => format!("{};2;{};{};{}", base, c.r, c.g, c.b),
+        }
+    }
+
+    let mut params = vec!["0".to_string()];
+    params.extend(ATTR_SGR.iter().filter(|(bit, _)| attrs & bit != 0).map(|(_, sgr)| sgr.to_string()));
+    params.extend(foreground.map(|c| color(c, 38)));
+    params.extend(background.map(|c| color(c, 48)));
+    format!("\x1b[{}m", params.join(";"))
+}
+
+impl Archive {
+    pub fn new(snapshot: &Snapshot) -> Self {
+        let cells = || snapshot.lines.iter().flat_map(|l| l.cells());
+        let palette = &snapshot.palette;
+        Archive {
+            cols: snapshot.lines.first().map_or(0, |l| l.len()),
+            rows: snapshot.lines.len(),
+            chars: cells().map(|c| c.char()).collect(),
+            foreground: cells().map(|c| c.pen().foreground()).collect(),
+            background: cells().map(|c| c.pen().background()).collect(),
+            attrs: attrs_from_lines(&snapshot.lines, &snapshot.extended).iter().copied().collect(),
+            continued: continued_from_lines(&snapshot.lines).to_vec(),
+            cursor: snapshot.cursor.clone(),
+            alt_screen: snapshot.alt_screen,
+            timestamp: snapshot.timestamp,
+            palette: palette.colors.to_vec(),
+            default_foreground: palette.foreground,
+            default_background: palette.background,
+        }
+    }
+
+    // the sequences drawing the cells from the top left corner, continued rows wrapping into place
+    fn draw(&self) -> String {
+        let mut s = "".to_string();
+        let mut pen = None;
+        for row in 0..self.rows {
+            if !self.continued[row] {
+                s.push_str(&format!("\x1b[{};1H", row + 1));
+            }
+            for i in row * self.cols..(row + 1) * self.cols {
+                let p = (self.attrs[i], self.foreground[i], self.background[i]);
+                if pen != Some(p) {
+                    s.push_str(&sgr(p));
+                    pen = Some(p);
+                }
+                let c = self.chars[i];
+                s.push(if c.is_control() { ' ' } else { c });
+            }
+        }
+        s
+    }
+
+    /// The snapshot with the archived cells, cursor and colors, or what is wrong with them
+    pub fn snapshot(&self) -> Result<Snapshot, String> {
+        geometry::check(self.cols, self.rows)?;
+        let cells = self.cols * self.rows;
+        let lengths = [self.chars.len(), self.foreground.len(), self.background.len(), self.attrs.len()];
+        if lengths.iter().any(|&n| n != cells) || self.continued.len() != self.rows {
+            return Err(format!("the cells don't make up {}x{}", self.cols, self.rows));
+        }
+        if self.cursor.row >= self.rows || self.cursor.col >= self.cols {
+            return Err(format!("cursor at {},{} outside {}x{}", self.cursor.row, self.cursor.col, self.cols, self.rows));
+        }
+        if self.palette.len() != 256 {
+            return Err(format!("{} palette colors rather than 256", self.palette.len()));
+        }
+        let mut screen = Screen::new(self.cols, self.rows);
+        screen.feed_str(&self.draw());
+        let mut snapshot = screen.snapshot();
+        snapshot.cursor = self.cursor.clone();
+        snapshot.alt_screen = self.alt_screen;
+        snapshot.timestamp = self.timestamp;
+        snapshot.palette = Palette::configured(&self.palette, self.default_foreground, self.default_background);
+        Ok(snapshot)
+    }
+
+    /// The truecolor (3 x _rows_ x _cols_) of the cells' foregrounds or backgrounds, with a mask True for the default
+    /// color and the palette index (-1 for RGB colors and the default)
+    #[cfg(feature = "python")]
+    pub fn color_matrices(&self, foreground: bool) -> (Array3<u8>, Array2<bool>, Array2<i16>) {
+        let colors = if foreground { &self.foreground } else { &self.background };
+        let shape = [self.rows, self.cols];
+        let palette = Palette::configured(&self.palette, self.default_foreground, self.default_background);
+        let truecolor: Vec<_> = colors
+            .iter()
+            .map(|c| c.map(|c| truecolor_from_avt(c, &palette)).map_or([0; 3], |c| [c.r, c.g, c.b]))
+            .collect();
+        let planes = (0..3).flat_map(|plane| truecolor.iter().map(move |c| c[plane])).collect();
+        let mask = colors.iter().map(|c| c.is_none()).collect();
+        let index = colors
+            .iter()
+            .map(|c| match c {
+                Some(avt::Color::Indexed(i)) => i16::from(*i),
+                _ => -1,
+            })
+            .collect();
+        (
+            Array3::from_shape_vec([3, self.rows, self.cols], planes).unwrap(),
+            Array2::from_shape_vec(shape, mask).unwrap(),
+            Array2::from_shape_vec(shape, index).unwrap(),
+        )
+    }
+
+    /// The colors of cells from the matrices of `color_matrices`: None where masked, indexed where there is an index
+    #[cfg(feature = "python")]
+    pub fn colors_from(truecolor: &[u8], mask: &[bool], index: &[i16]) -> Vec<Option<avt::Color>> {
+        let plane = mask.len();
+        (0..plane)
+            .map(|i| match (mask[i], index[i]) {
+                (true, _) => None,
+                (false, 0..=255) => Some(avt::Color::Indexed(index[i] as u8)),
+                (false, _) => Some(avt::Color::rgb(truecolor[i], truecolor[plane + i], truecolor[2 * plane + i])),
+            })
+            .collect()
+    }
+}
+
+// the cells of the array under `key` in row-major order, which must have `shape`
+#[cfg(feature = "python")]
+fn cells_of<T: Element + Copy>(npz: &Bound<'_, PyAny>, key: &str, shape: &[usize]) -> PyResult<Vec<T>> {
+    let array = npz.get_item(key)?.extract::<PyReadonlyArrayDyn<T>>()?;
+    let array = array.as_array();
+    if array.shape() != shape {
+        return Err(PyValueError::new_err(format!("{} has shape {:?} rather than {:?}", key, array.shape(), shape)));
+    }
+    Ok(array.iter().copied().collect())
+}
+
+// the scalar under `key`
+#[cfg(feature = "python")]
+fn item_of<'py, T: FromPyObject<'py>>(npz: &Bound<'py, PyAny>, key: &str) -> PyResult<T> {
+    npz.get_item(key)?.call_method0("item")?.extract()
+}
+
+// an RGB triplet, empty for none
+#[cfg(feature = "python")]
+fn rgb_array(color: Option<RGB8>) -> Array1<u8> {
+    color.map(|c| vec![c.r, c.g, c.b]).unwrap_or_default().into()
+}
+
+#[cfg(feature = "python")]
+fn rgb_of(npz: &Bound<'_, PyAny>, key: &str) -> PyResult<Option<RGB8>> {
+    let rgb = npz.get_item(key)?.extract::<Vec<u8>>()?;
+    match rgb.as_slice() {
+        [] => Ok(None),
+        &[r, g, b] => Ok(Some(RGB8 { r, g, b })),
+        _ => Err(PyValueError::new_err(format!("{} is not an RGB color", key))),
+    }
+}
+
+#[cfg(feature = "python")]
+impl Archive {
+    /// Writes the archive to `path` as a compressed NumPy .npz file
+    pub fn save_npz(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        let arrays = PyDict::new(py);
+        let shape = [self.rows, self.cols];
+        let chars = self.chars.iter().map(|&c| c as u32).collect();
+        arrays.set_item("version", ARCHIVE_VERSION)?;
+        arrays.set_item("chars", PyArray::from_owned_array(py, Array2::from_shape_vec(shape, chars).unwrap()))?;
+        for (name, foreground) in [("fg", true), ("bg", false)] {
+            let (truecolor, mask, index) = self.color_matrices(foreground);
+            arrays.set_item(name, PyArray::from_owned_array(py, truecolor))?;
+            arrays.set_item(format!("{}_mask", name), PyArray::from_owned_array(py, mask))?;
+            arrays.set_item(format!("{}_index", name), PyArray::from_owned_array(py, index))?;
+        }
+        let attrs = Array2::from_shape_vec(shape, self.attrs.clone()).unwrap();
+        arrays.set_item("attrs", PyArray::from_owned_array(py, attrs))?;
+        arrays.set_item("continued", PyArray::from_vec(py, self.continued.clone()))?;
+        arrays.set_item("cursor_row", self.cursor.row)?;
+        arrays.set_item("cursor_col", self.cursor.col)?;
+        arrays.set_item("cursor_visible", self.cursor.visible)?;
+        arrays.set_item("cursor_shape", self.cursor.shape)?;
+        arrays.set_item("cursor_blinking", self.cursor.blinking)?;
+        arrays.set_item("alt_screen", self.alt_screen)?;
+        arrays.set_item("timestamp", self.timestamp)?;
+        let palette = self.palette.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+        let palette = Array2::from_shape_vec([self.palette.len(), 3], palette).unwrap();
+        arrays.set_item("palette", PyArray::from_owned_array(py, palette))?;
+        arrays.set_item("default_fg", PyArray::from_owned_array(py, rgb_array(self.default_foreground)))?;
+        arrays.set_item("default_bg", PyArray::from_owned_array(py, rgb_array(self.default_background)))?;
+        py.import("numpy")?.call_method("savez_compressed", (path,), Some(&arrays))?;
+        Ok(())
+    }
+
+    /// Reads an archive written by `save_npz`
+    pub fn load_npz(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        let npz = py.import("numpy")?.call_method1("load", (path,))?;
+        let version: u32 = item_of(&npz, "version")?;
+        if version != ARCHIVE_VERSION {
+            return Err(PyValueError::new_err(format!("unknown snapshot archive version {}", version)));
+        }
+        let shape = npz.get_item("chars")?.getattr("shape")?.extract::<Vec<usize>>()?;
+        let &[rows, cols] = shape.as_slice() else {
+            return Err(PyValueError::new_err("chars is not a matrix"));
+        };
+        let chars = cells_of::<u32>(&npz, "chars", &shape)?
+            .into_iter()
+            .map(|c| char::from_u32(c).ok_or_else(|| PyValueError::new_err(format!("invalid character {:#x}", c))))
+            .collect::<PyResult<_>>()?;
+        let planes = [3, rows, cols];
+        let colors = |name: &str| -> PyResult<Vec<Option<avt::Color>>> {
+            Ok(Archive::colors_from(
+                &cells_of::<u8>(&npz, name, &planes)?,
+                &cells_of::<bool>(&npz, &format!("{}_mask", name), &shape)?,
+                &cells_of::<i16>(&npz, &format!("{}_index", name), &shape)?,
+            ))
+        };
+        let shape_name: String = item_of(&npz, "cursor_shape")?;
+        let Some(&cursor_shape) = SHAPES.iter().find(|&&s| s == shape_name) else {
+            return Err(PyValueError::new_err(format!("unknown cursor shape {:?}", shape_name)));
+        };
+        let palette = cells_of::<u8>(&npz, "palette", &[256, 3])?;
+        let archive = Archive {
+            cols,
+            rows,
+            chars,
+            foreground: colors("fg")?,
+            background: colors("bg")?,
+            attrs: cells_of(&npz, "attrs", &shape)?,
+            continued: cells_of(&npz, "continued", &[rows])?,
+            cursor: Cursor {
+                row: item_of(&npz, "cursor_row")?,
+                col: item_of(&npz, "cursor_col")?,
+                visible: item_of(&npz, "cursor_visible")?,
+                shape: cursor_shape,
+                blinking: item_of(&npz, "cursor_blinking")?,
+            },
+            alt_screen: item_of(&npz, "alt_screen")?,
+            timestamp: item_of(&npz, "timestamp")?,
+            palette: palette.chunks(3).map(|c| RGB8 { r: c[0], g: c[1], b: c[2] }).collect(),
+            default_foreground: rgb_of(&npz, "default_fg")?,
+            default_background: rgb_of(&npz, "default_bg")?,
+        };
+        npz.call_method0("close")?;
+        Ok(archive)
+    }
+}
+
+/// Loads a snapshot saved with `Terminal.save_snapshot()` from the .npz file at `path`
+#[cfg(feature = "python")]
+#[pyfunction]
+pub fn load_snapshot(py: Python<'_>, path: PathBuf) -> PyResult<Snapshot> {
+    Archive::load_npz(py, path)?.snapshot().map_err(PyValueError::new_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::text_from_lines;
+
+    fn snapshot(cols: usize, rows: usize, output: &str) -> Snapshot {
+        let mut screen = Screen::new(cols, rows);
+        screen.feed_str(output);
+        screen.snapshot()
+    }
+
+    fn assert_rebuilt(snapshot: &Snapshot) -> Snapshot {
+        let rebuilt = Archive::new(snapshot).snapshot().unwrap();
+        assert!(rebuilt.lines == snapshot.lines, "{:?}\n{:?}", rebuilt.lines, snapshot.lines);
+        assert_eq!(
+            attrs_from_lines(&rebuilt.lines, &rebuilt.extended),
+            attrs_from_lines(&snapshot.lines, &snapshot.extended)
+        );
+        assert_eq!(continued_from_lines(&rebuilt.lines), continued_from_lines(&snapshot.lines));
+        assert_eq!(rebuilt.cursor, snapshot.cursor);
+        assert_eq!(rebuilt.alt_screen, snapshot.alt_screen);
+        assert_eq!(rebuilt.timestamp, snapshot.timestamp);
+        assert_eq!(rebuilt.screen_hash(), snapshot.screen_hash());
+        rebuilt
+    }
+
+    #[test]
+    fn rebuilds_chars_colors_and_attrs() {
+        assert_rebuilt(&snapshot(
+            20,
+            4,
+            "\x1b[1;31mbold red\x1b[0m \x1b[3;4;38;5;200mitalic\x1b[0m\r\n\
+             \x1b[48;2;1;2;3m rgb \x1b[7minverse\x1b[0;2;9m faint\x1b[0m\r\n\
+             \x1b[5mblink\x1b[0;8mhidden\x1b[0m\x1b[3;7H",
+        ));
+    }
+
+    #[test]
+    fn rebuilds_continued_rows() {
+        let snapshot = snapshot(10, 3, "0123456789abcdefghij\r\nshort\x1b[2;3H");
+        assert_eq!(continued_from_lines(&snapshot.lines).to_vec(), [false, true, false]);
+        assert_rebuilt(&snapshot);
+    }
+
+    #[test]
+    fn rebuilds_a_wrapped_last_row() {
+        // the last row full, wrapped into from the row above, with the cursor pending a wrap at its end
+        let snapshot = snapshot(5, 3, "top\r\n0123456789");
+        assert_eq!(continued_from_lines(&snapshot.lines).to_vec(), [false, false, true]);
+        let rebuilt = assert_rebuilt(&snapshot);
+        assert_eq!(text_from_lines(&rebuilt.lines), "top  \n01234\n56789");
+    }
+
+    #[test]
+    fn rebuilds_wide_characters() {
+        let rebuilt = assert_rebuilt(&snapshot(8, 2, "a宽b\x1b[1;32m漢字\x1b[0m"));
+        assert!(text_from_lines(&rebuilt.lines).starts_with("a宽"));
+    }
+
+    #[test]
+    fn keeps_the_palette() {
+        let mut snapshot = snapshot(4, 1, "\x1b[31mred");
+        snapshot.palette = Palette::configured(&[RGB8 { r: 1, g: 2, b: 3 }; 16], Some(RGB8 { r: 9, g: 9, b: 9 }), None);
+        let rebuilt = assert_rebuilt(&snapshot);
+        assert_eq!(rebuilt.palette.colors, snapshot.palette.colors);
+        assert_eq!(rebuilt.palette.foreground, Some(RGB8 { r: 9, g: 9, b: 9 }));
+        assert_eq!(rebuilt.palette.background, None);
+    }
+
+    #[test]
+    fn replaces_control_characters_with_spaces() {
+        let mut archive = Archive::new(&snapshot(4, 1, "abcd"));
+        archive.chars[1] = '\x07';
+        archive.chars[2] = '\x1b';
+        let rebuilt = archive.snapshot().unwrap();
+        assert_eq!(text_from_lines(&rebuilt.lines), "a  d");
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn color_matrices_give_back_the_colors() {
+        let archive = Archive::new(&snapshot(6, 1, "\x1b[31;48;2;10;20;30ma\x1b[38;2;1;2;3;49mb\x1b[0mc"));
+        for foreground in [true, false] {
+            let (truecolor, mask, index) = archive.color_matrices(foreground);
+            let colors = Archive::colors_from(
+                truecolor.as_slice().unwrap(),
+                mask.as_slice().unwrap(),
+                index.as_slice().unwrap(),
+            );
+            assert_eq!(colors, if foreground { &archive.foreground } else { &archive.background }.clone());
+        }
+    }
+
+    #[test]
+    fn rejects_inconsistent_archives() {
+        let archive = Archive::new(&snapshot(4, 2, "ab"));
+
+        let mut short = archive.clone();
+        short.chars.pop();
+        assert!(short.snapshot().err().unwrap().contains("don't make up 4x2"));
+
+        let mut continued = archive.clone();
+        continued.continued.push(false);
+        assert!(continued.snapshot().is_err());
+
+        let mut cursor = archive.clone();
+        cursor.cursor.row = 2;
+        assert!(cursor.snapshot().err().unwrap().contains("cursor"));
+
+        let mut palette = archive.clone();
+        palette.palette.pop();
+        assert_eq!(palette.snapshot().err().unwrap(), "255 palette colors rather than 256");
+
+        let mut empty = archive;
+        (empty.cols, empty.rows) = (0, 0);
+        assert!(empty.snapshot().is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use crate::escapes::Event;
+use crate::modes::{Modes, REVERSE_VIDEO};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// bells whose time is kept, older ones are only counted beyond this
+const MAX_TIMES: usize = 10000;
+
+/// Whether the event rings the bell, given the modes before it is applied: Some(false) for BEL, Some(true)
+/// for a visual bell, reverse video (DEC mode 5) switched off after being on as in terminfo's `flash`
+pub fn rings(event: &Event, modes: &Modes) -> Option<bool> {
+    match event {
+        Event::Bell => Some(false),
+        Event::Csi { prefix: Some('?'), intermediates, action: 'l', .. }
+            if intermediates.is_empty() && modes.is_set(REVERSE_VIDEO) && event.params().contains(&REVERSE_VIDEO) =>
+        {
+            Some(true)
+        }
+        _ => None,
+    }
+}
+
+/// The bells rung by the child
+#[derive(Default)]
+pub struct Bells {
+    pub audible: u64,
+    pub visual: u64,
+    /// When each rang as `(timestamp, visual)`, timestamps in seconds since the epoch
+    pub times: VecDeque<(f64, bool)>,
+}
+
+/// Updated by the term task, read by the Terminal
+pub type SharedBells = Arc<Mutex<Bells>>;
+
+impl Bells {
+    pub fn ring(&mut self, visual: bool) {
+        if visual {
+            self.visual += 1;
+        } else {
+            self.audible += 1;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        if self.times.len() == MAX_TIMES {
+            self.times.pop_front();
+        }
+        self.times.push_back((timestamp, visual));
+    }
+}
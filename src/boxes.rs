@@ -0,0 +1,86 @@
+use crate::geometry::Bounds;
+use crate::lines::chars_from_lines;
+use ndarray::Array2;
+
+// directions a box-drawing character connects to
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+// ASCII `+`, which can be a corner or a junction
+const PLUS: u8 = 16;
+
+// light, heavy, double, rounded and dashed lines, mixed weights and ASCII
+fn connections(c: char) -> u8 {
+    match c {
+        '─' | '━' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '═' | '-' => LEFT | RIGHT,
+        '│' | '┃' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '║' | '|' => UP | DOWN,
+        '┌' | '┍' | '┎' | '┏' | '╒' | '╓' | '╔' | '╭' => DOWN | RIGHT,
+        '┐' | '┑' | '┒' | '┓' | '╕' | '╖' | '╗' | '╮' => DOWN | LEFT,
+        '└' | '┕' | '┖' | '┗' | '╘' | '╙' | '╚' | '╰' => UP | RIGHT,
+        '┘' | '┙' | '┚' | '┛' | '╛' | '╜' | '╝' | '╯' => UP | LEFT,
+        '├'..='┣' | '╞' | '╟' | '╠' => UP | DOWN | RIGHT,
+        '┤'..='┫' | '╡' | '╢' | '╣' => UP | DOWN | LEFT,
+        '┬'..='┳' | '╤' | '╥' | '╦' => DOWN | LEFT | RIGHT,
+        '┴'..='┻' | '╧' | '╨' | '╩' => UP | LEFT | RIGHT,
+        '┼'..='╋' | '╪' | '╫' | '╬' => UP | DOWN | LEFT | RIGHT,
+        '+' => UP | DOWN | LEFT | RIGHT | PLUS,
+        _ => 0,
+    }
+}
+
+/// Rectangles drawn with box-drawing characters, e.g. dialogs and panes, found in a chars matrix.
+/// Corners are plain corner characters unless `panes`, when junctions can be corners too, so a box
+/// split by lines is found as its parts rather than as a whole (ASCII `+` can always be either).
+/// From each top left corner the smallest closed rectangle is taken. Sorted by top, then left.
+pub fn find_boxes(chars: &Array2<u32>, panes: bool) -> Vec<Bounds> {
+    let (rows, cols) = chars.dim();
+    let at = |row: usize, col: usize| connections(char::from_u32(chars[[row, col]]).unwrap_or(' '));
+    let has = |c: u8, directions: u8| c & directions == directions;
+    let corner = |c: u8, directions: u8| match panes {
+        true => has(c, directions),
+        false => c == directions || c & PLUS != 0,
+    };
+
+    let closes = |top: usize, left: usize, right: usize| {
+        for bottom in top + 1..rows {
+            let (l, r) = (at(bottom, left), at(bottom, right));
+            if corner(l, UP | RIGHT)
+                && corner(r, UP | LEFT)
+                && (left + 1..right).all(|col| has(at(bottom, col), LEFT | RIGHT))
+            {
+                return Some(bottom);
+            }
+            if !(has(l, UP | DOWN) && has(r, UP | DOWN)) {
+                break;
+            }
+        }
+        None
+    };
+
+    let mut boxes = vec![];
+    for top in 0..rows {
+        for left in 0..cols {
+            if !corner(at(top, left), DOWN | RIGHT) {
+                continue;
+            }
+            for right in left + 1..cols {
+                let c = at(top, right);
+                if corner(c, DOWN | LEFT) {
+                    if let Some(bottom) = closes(top, left, right) {
+                        boxes.push((top, left, bottom, right));
+                        break;
+                    }
+                }
+                if !has(c, LEFT | RIGHT) {
+                    break;
+                }
+            }
+        }
+    }
+    boxes
+}
+
+pub fn boxes_from_lines(lines: &[avt::Line], panes: bool) -> Vec<Bounds> {
+    find_boxes(&chars_from_lines(lines), panes)
+}
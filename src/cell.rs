@@ -0,0 +1,64 @@
+use crate::color::{background, foreground, truecolor_from_avt};
+use crate::lines::{cell_attrs, graphemes_from_lines};
+use crate::palette::Palette;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// What a single cell holds and how it is drawn, as of a snapshot
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cell {
+    /// The grapheme cluster starting in the cell, empty in the cells of the rest of a cluster
+    pub char: String,
+    /// RGB foreground, None for the default color
+    pub fg: Option<(u8, u8, u8)>,
+    /// RGB background, None for the default color
+    pub bg: Option<(u8, u8, u8)>,
+    /// `ATTR_*` bits, as in `attrs_from_lines`
+    pub attrs: u16,
+}
+
+impl Cell {
+    /// The cell at `row` and `col` of `lines`, None if out of range.
+    /// With `effective` the colors are those actually seen, see `color::foreground`.
+    pub fn new(
+        lines: &[avt::Line],
+        extended: &[avt::Line],
+        (row, col): (usize, usize),
+        effective: bool,
+        palette: &Palette,
+    ) -> Option<Self> {
+        let line = lines.get(row)?;
+        let pen = line.cells().get(col)?.pen();
+        let ext = extended.get(row).and_then(|l| l.cells().get(col)).map(|e| *e.pen()).unwrap_or_default();
+        let rgb = |c: Option<avt::Color>| c.map(|c| truecolor_from_avt(c, palette)).map(|c| (c.r, c.g, c.b));
+        Some(Cell {
+            char: graphemes_from_lines(std::slice::from_ref(line)).swap_remove(0).swap_remove(col),
+            fg: rgb(foreground(effective, palette)(pen)),
+            bg: rgb(background(effective, palette)(pen)),
+            attrs: cell_attrs(pen, &ext),
+        })
+    }
+}
+
+#[cfg(feature = "python")]
+fn rgb_repr(color: Option<(u8, u8, u8)>) -> String {
+    match color {
+        Some((r, g, b)) => format!("({}, {}, {})", r, g, b),
+        None => "None".to_string(),
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Cell {
+    pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "Cell(char={}, fg={}, bg={}, attrs={})",
+            self.char.clone().into_pyobject(py)?.repr()?,
+            rgb_repr(self.fg),
+            rgb_repr(self.bg),
+            self.attrs,
+        ))
+    }
+}
@@ -0,0 +1,49 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// What the child has copied with OSC 52, by selection: `c` (clipboard), `p` (primary),
+/// `q` (secondary), `s` (select) or `0` to `7` (cut buffers)
+#[derive(Default)]
+pub struct Clipboard {
+    selections: HashMap<char, Vec<u8>>,
+}
+
+/// The selections OSC 52 names
+#[cfg(feature = "python")]
+pub const SELECTIONS: &str = "cpqs01234567";
+
+/// Updated by the term task, read by the Terminal
+pub type SharedClipboard = Arc<Mutex<Clipboard>>;
+
+impl Clipboard {
+    /// Takes an OSC string, which may set selections, returning the reply to a query
+    pub fn apply(&mut self, osc: &str) -> Option<String> {
+        let rest = osc.strip_prefix("52;")?;
+        let (given, data) = rest.split_once(';')?;
+        // "s 0" when none are given, as in xterm
+        let selections = if given.is_empty() { "s0" } else { given };
+        if data == "?" {
+            // the first of the selections that has something, like xterm
+            let data = selections.chars().find_map(|s| self.get(s)).unwrap_or_default();
+            return Some(format!("\x1b]52;{};{}\x1b\\", given, STANDARD.encode(data)));
+        }
+        // like xterm, invalid data clears the selections
+        let data = STANDARD.decode(data).unwrap_or_default();
+        for selection in selections.chars() {
+            self.selections.insert(selection, data.clone());
+        }
+        None
+    }
+
+    /// Sets a selection as if the user had copied `data` to it, for the child to read with a query
+    #[cfg(feature = "python")]
+    pub fn set(&mut self, selection: char, data: Vec<u8>) {
+        self.selections.insert(selection, data);
+    }
+
+    pub fn get(&self, selection: char) -> Option<&[u8]> {
+        self.selections.get(&selection).map(|d| d.as_slice())
+    }
+}
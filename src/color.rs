@@ -1,3 +1,6 @@
+use crate::palette::Palette;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
 use rgb::RGB8;
 
 
@@ -7,35 +10,109 @@ pub struct TrueColor {
     pub b: u8,
 }
 
-pub fn truecolor_from_avt(color: avt::Color) -> TrueColor {
+pub fn truecolor_from_avt(color: avt::Color, palette: &Palette) -> TrueColor {
     match color {
-        avt::Color::RGB(rgb8) => {
-            return TrueColor {
-                r: rgb8.r,
-                g: rgb8.g,
-                b: rgb8.b,
-            }
-        }
+        avt::Color::RGB(rgb8) => TrueColor {
+            r: rgb8.r,
+            g: rgb8.g,
+            b: rgb8.b,
+        },
         avt::Color::Indexed(u8) => {
-            let rgb8 = PALETTE[usize::from(u8)];
-            return TrueColor {
+            let rgb8 = palette.colors[usize::from(u8)];
+            TrueColor {
                 r: rgb8.r,
                 g: rgb8.g,
                 b: rgb8.b,
-            };
+            }
         }
     }
 }
 
-pub fn indexedcolor_from_avt(color: avt::Color) -> u8 {
+/// Packed rgb32 value standing for the default color, outside the 0x00RRGGBB range
+#[cfg(feature = "python")]
+pub const DEFAULT_RGB32: u32 = 0xFFFFFFFF;
+
+#[cfg(feature = "python")]
+pub fn rgb32_from_avt(color: avt::Color, palette: &Palette) -> u32 {
+    let c = truecolor_from_avt(color, palette);
+    (u32::from(c.r) << 16) | (u32::from(c.g) << 8) | u32::from(c.b)
+}
+
+/// Colors substituted for the default foreground and background in effective colors,
+/// unless the child has redefined them
+pub const DEFAULT_FG_INDEX: u8 = 7;
+pub const DEFAULT_BG_INDEX: u8 = 0;
+pub const DEFAULT_FG: avt::Color = avt::Color::Indexed(DEFAULT_FG_INDEX);
+pub const DEFAULT_BG: avt::Color = avt::Color::Indexed(DEFAULT_BG_INDEX);
+
+fn bright(color: avt::Color) -> avt::Color {
     match color {
-        avt::Color::RGB(_) => {
-            eprintln!(
-                "warning, truecolor to indexedcolor conversion, not implemented, always returning 0, could find nearest"
-            );
-            0
-        }
-        avt::Color::Indexed(u8) => u8,
+        avt::Color::Indexed(c) if c < 8 => avt::Color::Indexed(c + 8),
+        c => c,
+    }
+}
+
+/// The foreground to convert, with `effective` as seen: defaults substituted,
+/// bold as bright and inverse video applied
+pub fn foreground(effective: bool, palette: &Palette) -> impl Fn(&avt::Pen) -> Option<avt::Color> + '_ {
+    move |pen| match effective {
+        true => Some(effective_colors(pen, palette).0),
+        false => pen.foreground(),
+    }
+}
+
+/// The background to convert, see `foreground`
+pub fn background(effective: bool, palette: &Palette) -> impl Fn(&avt::Pen) -> Option<avt::Color> + '_ {
+    move |pen| match effective {
+        true => Some(effective_colors(pen, palette).1),
+        false => pen.background(),
+    }
+}
+
+/// The color the default foreground is seen as, `DEFAULT_FG` unless configured or redefined by the child
+pub fn default_foreground(palette: &Palette) -> avt::Color {
+    palette.foreground.map(avt::Color::RGB).unwrap_or(DEFAULT_FG)
+}
+
+/// The color the default background is seen as, see `default_foreground`
+pub fn default_background(palette: &Palette) -> avt::Color {
+    palette.background.map(avt::Color::RGB).unwrap_or(DEFAULT_BG)
+}
+
+fn effective_colors(pen: &avt::Pen, palette: &Palette) -> (avt::Color, avt::Color) {
+    let mut fg = pen.foreground().unwrap_or(default_foreground(palette));
+    let bg = pen.background().unwrap_or(default_background(palette));
+    if pen.is_bold() {
+        fg = bright(fg);
+    }
+    if pen.is_inverse() {
+        (bg, fg)
+    } else {
+        (fg, bg)
+    }
+}
+
+/// The entry of the 256-color cube or grayscale ramp (16 to 255, which themes don't change)
+/// nearest to a truecolor, indexed colors as they are
+#[cfg(feature = "python")]
+pub fn nearest_indexed(color: avt::Color) -> avt::Color {
+    let avt::Color::RGB(rgb) = color else {
+        return color;
+    };
+    let distance = |c: &RGB8| {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(c.r, rgb.r) + d(c.g, rgb.g) + d(c.b, rgb.b)
+    };
+    let (index, _) = PALETTE.iter().enumerate().skip(16).min_by_key(|(_, c)| distance(c)).unwrap();
+    avt::Color::Indexed(index as u8)
+}
+
+/// The index of an indexed color, None for a truecolor one, which has none
+#[cfg(feature = "python")]
+pub fn indexedcolor_from_avt(color: avt::Color) -> Option<u8> {
+    match color {
+        avt::Color::RGB(_) => None,
+        avt::Color::Indexed(u8) => Some(u8),
     }
 }
 
@@ -298,3 +375,38 @@ pub static PALETTE: [RGB8; 256] = [
     RGB8{r: 228, g: 228, b: 228},
     RGB8{r: 238, g: 238, b: 238},
 ];
+
+/// A color argument from Python: a palette index, or an (r, g, b) tuple
+#[cfg_attr(feature = "python", derive(FromPyObject))]
+#[derive(Clone, Copy)]
+pub enum ColorArg {
+    Indexed(u8),
+    Rgb([u8; 3]),
+}
+
+impl From<ColorArg> for avt::Color {
+    fn from(color: ColorArg) -> Self {
+        match color {
+            ColorArg::Indexed(index) => avt::Color::Indexed(index),
+            ColorArg::Rgb([r, g, b]) => avt::Color::RGB(RGB8::new(r, g, b)),
+        }
+    }
+}
+
+/// One color argument or a list of them
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum ColorArgs {
+    One(ColorArg),
+    Many(Vec<ColorArg>),
+}
+
+#[cfg(feature = "python")]
+impl ColorArgs {
+    pub fn colors(&self) -> Vec<avt::Color> {
+        match self {
+            ColorArgs::One(color) => vec![(*color).into()],
+            ColorArgs::Many(colors) => colors.iter().map(|&c| c.into()).collect(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+#[cfg(feature = "python")]
+use crate::procfs;
+use nix::libc;
+#[cfg(feature = "python")]
+use nix::unistd::Pid;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use std::fs;
+use std::os::fd::{AsRawFd, OwnedFd};
+#[cfg(feature = "python")]
+use std::os::unix::fs::MetadataExt;
+
+/// Whether the child is set up like in a real terminal, see `Terminal.controlling_terminal()`
+#[cfg(feature = "python")]
+#[pyclass(get_all)]
+#[derive(Clone, Debug)]
+pub struct ControllingTerminal {
+    /// Whether the child is the leader of its session (it called setsid)
+    pub session_leader: bool,
+    /// Whether the child has a controlling terminal at all
+    pub has_controlling_terminal: bool,
+    /// Whether its controlling terminal is the pty (it got it with TIOCSCTTY)
+    pub pty_is_controlling: bool,
+    /// Whether the child's process group is in the foreground of the terminal
+    pub foreground: bool,
+    /// Path of the pty's slave device, e.g. /dev/pts/3
+    pub pty: Option<String>,
+}
+
+// the number of the slave device of a pty master
+fn pty_number(master: &OwnedFd) -> Option<u32> {
+    let mut n: libc::c_uint = 0;
+    if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCGPTN, &mut n) } < 0 {
+        return None;
+    }
+    Some(n)
+}
+
+/// Path of the slave device of a pty master, e.g. /dev/pts/3
+pub fn pty_path(master: &OwnedFd) -> Option<String> {
+    pty_number(master).map(|n| format!("/dev/pts/{}", n))
+}
+
+#[cfg(feature = "python")]
+impl ControllingTerminal {
+    /// Checks the child against the pty it was started in, from /proc
+    pub fn check(child: Pid, master: &OwnedFd) -> Option<Self> {
+        let stat = procfs::stat(child)?;
+        let pty = pty_path(master);
+        let rdev = pty.as_ref().and_then(|p| fs::metadata(p).ok()).map(|m| m.rdev());
+        Some(ControllingTerminal {
+            session_leader: stat.session == child.as_raw(),
+            has_controlling_terminal: stat.tty_nr != 0,
+            pty_is_controlling: stat.tty_nr != 0 && rdev == Some(stat.tty_nr),
+            foreground: stat.tpgid == stat.pgrp,
+            pty,
+        })
+    }
+
+    /// Descriptions of the checks that failed
+    pub fn problems(&self) -> Vec<&'static str> {
+        [
+            (self.session_leader, "the child is not a session leader"),
+            (self.has_controlling_terminal, "the child has no controlling terminal"),
+            (self.pty_is_controlling || !self.has_controlling_terminal, "the controlling terminal of the child is not the pty"),
+            (self.foreground, "the child is not in the foreground process group"),
+        ]
+        .into_iter()
+        .filter(|(ok, _)| !ok)
+        .map(|(_, problem)| problem)
+        .collect()
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ControllingTerminal {
+    /// Whether all checks passed
+    #[getter]
+    pub fn ok(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "ControllingTerminal(session_leader={}, has_controlling_terminal={}, pty_is_controlling={}, foreground={}, pty={})",
+            if self.session_leader { "True" } else { "False" },
+            if self.has_controlling_terminal { "True" } else { "False" },
+            if self.pty_is_controlling { "True" } else { "False" },
+            if self.foreground { "True" } else { "False" },
+            self.pty.as_ref().map(|p| format!("'{}'", p)).unwrap_or("None".to_string()),
+        )
+    }
+}
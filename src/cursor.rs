@@ -0,0 +1,70 @@
+use crate::escapes::Event;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// The style set with DECSCUSR (`CSI Ps SP q`) if the event sets it, 0 being the terminal's default
+pub fn cursor_style(event: &Event) -> Option<u16> {
+    match event {
+        Event::Csi { prefix: None, intermediates, action: 'q', .. } if intermediates == " " => {
+            Some(event.params().first().copied().unwrap_or(0))
+        }
+        _ => None,
+    }
+}
+
+/// The shapes a cursor can have
+#[cfg(any(feature = "python", feature = "json"))]
+pub const SHAPES: [&str; 3] = ["block", "underline", "bar"];
+
+/// Where the cursor is and how it looks, as of a snapshot
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor {
+    /// 0-based row
+    pub row: usize,
+    /// 0-based column, the last one while a wrap is pending
+    pub col: usize,
+    /// Whether the cursor is shown (DEC mode 25)
+    pub visible: bool,
+    /// "block", "underline" or "bar", as set with DECSCUSR
+    pub shape: &'static str,
+    pub blinking: bool,
+}
+
+impl Cursor {
+    /// The cursor of `vt`, with a DECSCUSR `style`
+    pub fn new(vt: &avt::Vt, style: u16) -> Self {
+        let cursor = vt.cursor();
+        let (cols, _) = vt.size();
+        let (shape, blinking) = match style {
+            2 => ("block", false),
+            3 => ("underline", true),
+            4 => ("underline", false),
+            5 => ("bar", true),
+            6 => ("bar", false),
+            _ => ("block", true),
+        };
+        Cursor {
+            row: cursor.row,
+            col: cursor.col.min(cols.saturating_sub(1)),
+            visible: cursor.visible,
+            shape,
+            blinking,
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Cursor {
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Cursor(row={}, col={}, visible={}, shape='{}', blinking={})",
+            self.row,
+            self.col,
+            if self.visible { "True" } else { "False" },
+            self.shape,
+            if self.blinking { "True" } else { "False" },
+        )
+    }
+}
@@ -0,0 +1,56 @@
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use std::time::{Duration, Instant};
+
+/// A point in time shared by several waits, so that a sequence of them has one overall time budget
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl Deadline {
+    /// The deadline `timeout` from now
+    pub fn after(timeout: Duration) -> Self {
+        Deadline { at: Instant::now() + timeout }
+    }
+
+    /// What is left until the deadline, zero once it has passed
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// `timeout`, cut short to what is left until `deadline` if any
+    pub fn limit(timeout: Option<Duration>, deadline: Option<&Deadline>) -> Option<Duration> {
+        match (timeout, deadline.map(Deadline::remaining)) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Deadline {
+    /// A deadline `timeout` ms from now, to pass as `deadline` to the waits of a Terminal
+    #[new]
+    pub fn py_new(timeout: u64) -> Self {
+        Deadline::after(Duration::from_millis(timeout))
+    }
+
+    /// Ms left until the deadline, 0 once it has passed
+    #[getter(remaining)]
+    pub fn py_remaining(&self) -> u64 {
+        self.remaining().as_millis() as u64
+    }
+
+    /// Whether the deadline has passed
+    #[getter]
+    pub fn expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("Deadline(remaining={})", self.py_remaining())
+    }
+}
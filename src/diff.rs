@@ -0,0 +1,51 @@
+use crate::geometry::Bounds;
+use ndarray::{Array2, Axis};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// A summary of the cells that changed between two snapshots
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffStats {
+    /// How many cells changed
+    pub count: usize,
+    /// The rows with cells that changed, top to bottom
+    pub rows: Vec<usize>,
+    /// `(top, left, bottom, right)`, inclusive, of the cells that changed, None if none did
+    pub bounds: Option<Bounds>,
+}
+
+impl DiffStats {
+    /// The summary of a _rows_ x _cols_ matrix, True for cells that changed
+    pub fn new(changed: &Array2<bool>) -> Self {
+        let rows: Vec<usize> = changed
+            .axis_iter(Axis(0))
+            .enumerate()
+            .filter(|(_, row)| row.iter().any(|&c| c))
+            .map(|(row, _)| row)
+            .collect();
+        let cols: Vec<usize> = changed
+            .axis_iter(Axis(1))
+            .enumerate()
+            .filter(|(_, col)| col.iter().any(|&c| c))
+            .map(|(col, _)| col)
+            .collect();
+        let bounds = match (rows.first(), rows.last(), cols.first(), cols.last()) {
+            (Some(&top), Some(&bottom), Some(&left), Some(&right)) => Some((top, left, bottom, right)),
+            _ => None,
+        };
+        DiffStats { count: changed.iter().filter(|&&c| c).count(), rows, bounds }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl DiffStats {
+    pub fn __repr__(&self) -> String {
+        let bounds = match self.bounds {
+            Some(bounds) => format!("{:?}", bounds),
+            None => "None".to_string(),
+        };
+        format!("DiffStats(count={}, rows={:?}, bounds={})", self.count, self.rows, bounds)
+    }
+}
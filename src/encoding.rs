@@ -0,0 +1,115 @@
+// Legacy encodings for programs that don't speak UTF-8. There is no encoding library among
+// the dependencies, so tables are built once from the Python codec of the same name: every
+// byte, and every pair starting with a byte that doesn't decode on its own. This covers
+// single-byte (latin-1, cp1252, koi8-r...) and double-byte (shift_jis, euc-kr, gbk, big5...) encodings.
+
+use anyhow::{anyhow, Result};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyBytes;
+use std::char::REPLACEMENT_CHARACTER;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct Codec {
+    name: String,
+    single: Vec<Option<char>>,
+    double: HashMap<[u8; 2], char>,
+    // bytes starting a pair
+    lead: Vec<bool>,
+    encoded: HashMap<char, Vec<u8>>,
+}
+
+impl Codec {
+    /// The codec for an encoding name Python knows, None for UTF-8 which needs none
+    #[cfg(feature = "python")]
+    pub fn new(py: Python<'_>, name: &str) -> PyResult<Option<Codec>> {
+        let info = py.import("codecs")?.call_method1("lookup", (name,))?;
+        let name: String = info.getattr("name")?.extract()?;
+        if name == "utf-8" {
+            return Ok(None);
+        }
+
+        let decode = |bytes: &[u8]| -> Option<char> {
+            let text = PyBytes::new(py, bytes).call_method1("decode", (&name,)).ok()?;
+            let text: String = text.extract().ok()?;
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => None,
+            }
+        };
+        let single: Vec<Option<char>> = (0..=255).map(|b| decode(&[b])).collect();
+        let mut double = HashMap::new();
+        let mut lead = vec![false; 256];
+        for b in (0..=255u8).filter(|&b| single[b as usize].is_none()) {
+            for t in 0x21..=0xfe {
+                if let Some(c) = decode(&[b, t]) {
+                    double.insert([b, t], c);
+                    lead[b as usize] = true;
+                }
+            }
+        }
+
+        let mut encoded = HashMap::new();
+        for (b, c) in single.iter().enumerate() {
+            if let Some(c) = c {
+                encoded.entry(*c).or_insert_with(|| vec![b as u8]);
+            }
+        }
+        for (pair, c) in &double {
+            encoded.entry(*c).or_insert_with(|| pair.to_vec());
+        }
+
+        Ok(Some(Codec { name, single, double, lead, encoded }))
+    }
+
+    /// The canonical name of the encoding, e.g. "shift_jis"
+    #[cfg(feature = "recording")]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            let encoded = self.encoded
+                .get(&c)
+                .ok_or_else(|| anyhow!("{:?} can't be encoded in {}", c, self.name))?;
+            bytes.extend_from_slice(encoded);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Decodes output in chunks, which may split pairs between them
+pub struct Decoder {
+    codec: Arc<Codec>,
+    pending: Option<u8>,
+}
+
+impl Decoder {
+    pub fn new(codec: Arc<Codec>) -> Self {
+        Decoder { codec, pending: None }
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let mut text = String::with_capacity(bytes.len());
+        for &b in bytes {
+            if let Some(lead) = self.pending.take() {
+                if let Some(&c) = self.codec.double.get(&[lead, b]) {
+                    text.push(c);
+                    continue;
+                }
+                text.push(REPLACEMENT_CHARACTER);
+            }
+            if self.codec.lead[b as usize] {
+                self.pending = Some(b);
+            } else {
+                text.push(self.codec.single[b as usize].unwrap_or(REPLACEMENT_CHARACTER));
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,283 @@
+// Lightweight scanner for the escape sequences avt doesn't report back,
+// run over the same output the Vt is fed.
+
+const MAX_STRING_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Bell,
+    Esc {
+        intermediates: String,
+        action: char,
+    },
+    Csi {
+        prefix: Option<char>,
+        params: String,
+        intermediates: String,
+        action: char,
+    },
+    Osc(String),
+}
+
+impl Event {
+    /// Numeric CSI parameters, sub-parameters are dropped and missing ones read as 0
+    pub fn params(&self) -> Vec<u16> {
+        match self {
+            Event::Csi { params, .. } if !params.is_empty() => params
+                .split(';')
+                .map(|p| {
+                    p.split(':')
+                        .next()
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(0)
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    Csi,
+    Osc,
+    OscEscape,
+    String,
+    StringEscape,
+}
+
+#[derive(Clone)]
+pub struct Scanner {
+    state: State,
+    prefix: Option<char>,
+    params: String,
+    intermediates: String,
+    string: String,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Scanner {
+            state: State::Ground,
+            prefix: None,
+            params: String::new(),
+            intermediates: String::new(),
+            string: String::new(),
+        }
+    }
+
+    /// Whether the scanner is partway through an escape sequence or string
+    pub fn in_sequence(&self) -> bool {
+        self.state != State::Ground
+    }
+
+    /// Whether the character just fed, which gave `event`, is printed text: not part of an escape
+    /// sequence or string, nor a control character other than a newline or a tab
+    pub fn printed(&self, c: char, event: &Option<Event>) -> bool {
+        event.is_none() && self.state == State::Ground && (!c.is_control() || c == '\n' || c == '\t')
+    }
+
+    pub fn feed(&mut self, c: char) -> Option<Event> {
+        match (&self.state, c) {
+            (State::Osc, '\x07') => {
+                self.state = State::Ground;
+                return Some(Event::Osc(std::mem::take(&mut self.string)));
+            }
+            (State::String, '\x07') => {
+                self.state = State::Ground;
+            }
+            (State::OscEscape, '\\') => {
+                self.state = State::Ground;
+                return Some(Event::Osc(std::mem::take(&mut self.string)));
+            }
+            (State::StringEscape, '\\') => {
+                self.state = State::Ground;
+            }
+            (_, '\x1b') => {
+                self.state = match self.state {
+                    State::Osc => State::OscEscape,
+                    State::String => State::StringEscape,
+                    _ => State::Escape,
+                };
+                self.intermediates.clear();
+            }
+            (_, '\x18' | '\x1a') => {
+                self.state = State::Ground;
+            }
+            (State::Ground, '\x07') => {
+                return Some(Event::Bell);
+            }
+            (State::Ground, _) => {}
+            (State::Escape, '[') => {
+                self.state = State::Csi;
+                self.prefix = None;
+                self.params.clear();
+            }
+            (State::Escape, ']') => {
+                self.state = State::Osc;
+                self.string.clear();
+            }
+            (State::Escape, 'P' | 'X' | '^' | '_') => {
+                self.state = State::String;
+            }
+            (State::Escape | State::EscapeIntermediate, '\x20'..='\x2f') => {
+                self.state = State::EscapeIntermediate;
+                self.intermediates.push(c);
+            }
+            (State::Escape | State::EscapeIntermediate, '\x30'..='\x7e') => {
+                self.state = State::Ground;
+                return Some(Event::Esc {
+                    intermediates: std::mem::take(&mut self.intermediates),
+                    action: c,
+                });
+            }
+            (State::Escape | State::EscapeIntermediate, _) => {}
+            (State::Csi, '<' | '=' | '>' | '?') if self.params.is_empty() && self.prefix.is_none() => {
+                self.prefix = Some(c);
+            }
+            (State::Csi, '0'..='9' | ';' | ':') if self.intermediates.is_empty() => {
+                self.params.push(c);
+            }
+            (State::Csi, '\x20'..='\x2f') => {
+                self.intermediates.push(c);
+            }
+            (State::Csi, '\x40'..='\x7e') => {
+                self.state = State::Ground;
+                return Some(Event::Csi {
+                    prefix: self.prefix.take(),
+                    params: std::mem::take(&mut self.params),
+                    intermediates: std::mem::take(&mut self.intermediates),
+                    action: c,
+                });
+            }
+            (State::Csi, _) => {}
+            (State::Osc, _) => {
+                if self.string.len() < MAX_STRING_LEN {
+                    self.string.push(c);
+                }
+            }
+            (State::OscEscape | State::StringEscape, _) => {
+                // ESC not followed by `\`, abandon the string and start over as an escape
+                self.string.clear();
+                self.state = State::Escape;
+                return self.feed(c);
+            }
+            (State::String, _) => {}
+        }
+        None
+    }
+}
+
+/// The text printed by some output, see `Scanner::printed`
+pub fn printed_text(output: &str) -> String {
+    let mut scanner = Scanner::new();
+    output
+        .chars()
+        .filter(|&c| {
+            let event = scanner.feed(c);
+            scanner.printed(c, &event)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the events of `chunks` fed in turn to one scanner
+    fn events(chunks: &[&str]) -> Vec<Event> {
+        let mut scanner = Scanner::new();
+        chunks.iter().flat_map(|chunk| chunk.chars().filter_map(|c| scanner.feed(c)).collect::<Vec<_>>()).collect()
+    }
+
+    fn csi(prefix: Option<char>, params: &str, intermediates: &str, action: char) -> Event {
+        Event::Csi { prefix, params: params.into(), intermediates: intermediates.into(), action }
+    }
+
+    #[test]
+    fn scans_csi() {
+        assert_eq!(events(&["a\x1b[1;31mb"]), [csi(None, "1;31", "", 'm')]);
+        assert_eq!(events(&["\x1b[?1049h"]), [csi(Some('?'), "1049", "", 'h')]);
+        assert_eq!(events(&["\x1b[>4;2m"]), [csi(Some('>'), "4;2", "", 'm')]);
+        assert_eq!(events(&["\x1b[H"]), [csi(None, "", "", 'H')]);
+    }
+
+    #[test]
+    fn scans_csi_intermediates() {
+        assert_eq!(events(&["\x1b[5 q"]), [csi(None, "5", " ", 'q')]);
+        assert_eq!(events(&["\x1b[?2026$p"]), [csi(Some('?'), "2026", "$", 'p')]);
+        // parameters after an intermediate make the sequence invalid, and are dropped
+        assert_eq!(events(&["\x1b[1 2q"]), [csi(None, "1", " ", 'q')]);
+    }
+
+    #[test]
+    fn reads_params() {
+        assert_eq!(csi(None, "1;;3", "", 'm').params(), [1, 0, 3]);
+        assert_eq!(csi(None, "38:2:1:2:3;1", "", 'm').params(), [38, 1]);
+        assert_eq!(csi(None, "", "", 'm').params(), Vec::<u16>::new());
+        assert_eq!(Event::Bell.params(), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn scans_esc() {
+        assert_eq!(events(&["\x1b7"]), [Event::Esc { intermediates: "".into(), action: '7' }]);
+        assert_eq!(events(&["\x1b(B"]), [Event::Esc { intermediates: "(".into(), action: 'B' }]);
+        assert_eq!(events(&["\x1b#6"]), [Event::Esc { intermediates: "#".into(), action: '6' }]);
+    }
+
+    #[test]
+    fn scans_osc_ended_by_bel_or_st() {
+        assert_eq!(events(&["\x1b]0;title\x07"]), [Event::Osc("0;title".into())]);
+        assert_eq!(events(&["\x1b]2;title\x1b\\"]), [Event::Osc("2;title".into())]);
+        assert_eq!(events(&["\x1b]52;c;?\x07\x07"]), [Event::Osc("52;c;?".into()), Event::Bell]);
+    }
+
+    #[test]
+    fn abandons_a_string_on_another_escape() {
+        assert_eq!(events(&["\x1b]0;title\x1b[1m"]), [csi(None, "1", "", 'm')]);
+        // and on CAN or SUB, a CSI as well
+        assert_eq!(events(&["\x1b]0;title\x18\x1b[2J"]), [csi(None, "2", "", 'J')]);
+        assert_eq!(events(&["\x1b[12\x1ax"]), []);
+    }
+
+    #[test]
+    fn skips_dcs_and_other_strings() {
+        assert_eq!(events(&["\x1bPq#0;2;0;0;0\x1b\\\x07"]), [Event::Bell]);
+        assert_eq!(events(&["\x1b_apc\x07\x1b^pm\x1b\\"]), []);
+    }
+
+    #[test]
+    fn rings_only_outside_sequences() {
+        assert_eq!(events(&["\x07a\x07"]), [Event::Bell, Event::Bell]);
+        assert_eq!(events(&["\x1b]0;a\x07"]), [Event::Osc("0;a".into())]);
+    }
+
+    #[test]
+    fn scans_sequences_split_across_chunks() {
+        let output = "a\x1b[?25l\x1b]0;title\x1b\\b\x1b(B\x1b]52;c;?\x07\x1b[1;31mc\x07";
+        let whole = events(&[output]);
+        assert_eq!(whole.len(), 6);
+        for (i, _) in output.char_indices().skip(1) {
+            assert_eq!(events(&[&output[..i], &output[i..]]), whole, "split at {}", i);
+        }
+        let chars: Vec<String> = output.chars().map(String::from).collect();
+        assert_eq!(events(&chars.iter().map(String::as_str).collect::<Vec<_>>()), whole);
+    }
+
+    #[test]
+    fn tells_when_partway_through_a_sequence() {
+        let mut scanner = Scanner::new();
+        "\x1b[1".chars().for_each(|c| _ = scanner.feed(c));
+        assert!(scanner.in_sequence());
+        assert_eq!(scanner.feed('m'), Some(csi(None, "1", "", 'm')));
+        assert!(!scanner.in_sequence());
+    }
+
+    #[test]
+    fn keeps_printed_text() {
+        assert_eq!(printed_text("a\x1b[1mb\x07\x1b]0;t\x07c\r\n\td"), "abc\n\td");
+    }
+}
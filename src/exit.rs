@@ -0,0 +1,77 @@
+use crate::snapshot::Snapshot;
+use nix::sys::wait::WaitStatus;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+
+/// Exit status and final screen of each failure of the child, recorded by the term task
+pub type Failures = Arc<Mutex<Vec<(ExitStatus, Snapshot)>>>;
+
+/// How the child process exited
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug)]
+pub struct ExitStatus {
+    /// Exit code, None if the child was terminated by a signal
+    pub code: Option<i32>,
+    /// Number of the signal that terminated the child, None if it exited normally
+    pub signal: Option<i32>,
+    /// Name of the signal that terminated the child, e.g. `"SIGSEGV"`
+    pub signal_name: Option<String>,
+    /// Whether the child dumped core as it was terminated
+    pub core_dumped: bool,
+    /// Path of the core file, if the child was run with a `core_dir` and it could be found
+    pub core_file: Option<String>,
+}
+
+impl ExitStatus {
+    /// Non-zero exit or terminated by a signal
+    pub fn failed(&self) -> bool {
+        self.code != Some(0)
+    }
+}
+
+impl From<WaitStatus> for ExitStatus {
+    fn from(status: WaitStatus) -> Self {
+        match status {
+            WaitStatus::Exited(_, code) => ExitStatus {
+                code: Some(code),
+                signal: None,
+                signal_name: None,
+                core_dumped: false,
+                core_file: None,
+            },
+            WaitStatus::Signaled(_, signal, core_dumped) => ExitStatus {
+                code: None,
+                signal: Some(signal as i32),
+                signal_name: Some(signal.as_str().to_string()),
+                core_dumped,
+                core_file: None,
+            },
+            _ => ExitStatus {
+                code: None,
+                signal: None,
+                signal_name: None,
+                core_dumped: false,
+                core_file: None,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ExitStatus {
+    pub fn __repr__(&self) -> String {
+        match (self.code, self.signal) {
+            (Some(code), _) => format!("ExitStatus(code={})", code),
+            (_, Some(signal)) => format!(
+                "ExitStatus(signal={}, signal_name='{}', core_dumped={}{})",
+                signal,
+                self.signal_name.as_deref().unwrap_or_default(),
+                if self.core_dumped { "True" } else { "False" },
+                self.core_file.as_ref().map(|f| format!(", core_file='{}'", f)).unwrap_or_default(),
+            ),
+            _ => "ExitStatus()".to_string(),
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::color::{background, foreground, truecolor_from_avt, TrueColor};
+use crate::snapshot::Snapshot;
+use crate::theme::Theme;
+use anyhow::{anyhow, Result};
+use fontdue::{Font, FontSettings, Metrics};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+// tried in order when no font is given
+const FONT_PATHS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/dejavu-sans-mono-fonts/DejaVuSansMono.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationMono-Regular.ttf",
+    "/usr/share/fonts/liberation-mono/LiberationMono-Regular.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "/System/Library/Fonts/Monaco.ttf",
+];
+
+// how long the last frame of an animation is shown, in seconds
+const LAST_FRAME_DELAY: f64 = 1.0;
+
+/// An RGB image
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+impl Canvas {
+    fn fill(&mut self, x: usize, y: usize, w: usize, h: usize, c: &TrueColor) {
+        for row in y..(y + h).min(self.height) {
+            for col in x..(x + w).min(self.width) {
+                let i = 3 * (row * self.width + col);
+                self.rgb[i..i + 3].copy_from_slice(&[c.r, c.g, c.b]);
+            }
+        }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, coverage: u8, c: &TrueColor) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let i = 3 * (y as usize * self.width + x as usize);
+        let a = u16::from(coverage);
+        for (p, v) in self.rgb[i..i + 3].iter_mut().zip([c.r, c.g, c.b]) {
+            *p = ((u16::from(*p) * (255 - a) + u16::from(v) * a) / 255) as u8;
+        }
+    }
+}
+
+/// Draws snapshots as images with a monospace font, one cell per `cell_width` x `cell_height` pixels
+pub struct Rasterizer {
+    theme: Theme,
+    font: Font,
+    cell_width: usize,
+    cell_height: usize,
+    // from the top of a cell
+    baseline: f32,
+    glyphs: HashMap<char, (Metrics, Vec<u8>)>,
+}
+
+impl Rasterizer {
+    /// With the font and cell geometry of the theme
+    pub fn new(theme: &Theme) -> Result<Self> {
+        let data = match &theme.font {
+            Some(path) => fs::read(path)?,
+            None => FONT_PATHS
+                .iter()
+                .find_map(|path| fs::read(path).ok())
+                .ok_or_else(|| anyhow!("no monospace font found, pass one"))?,
+        };
+        let size = theme.font_size;
+        let font = Font::from_bytes(data, FontSettings::default()).map_err(|e| anyhow!(e))?;
+        let line = font
+            .horizontal_line_metrics(size)
+            .ok_or_else(|| anyhow!("font has no horizontal metrics"))?;
+        let cell_width = theme
+            .cell_width
+            .unwrap_or_else(|| font.metrics('M', size).advance_width.ceil() as usize);
+        let cell_height = theme
+            .cell_height
+            .unwrap_or_else(|| (line.new_line_size * theme.line_spacing).ceil() as usize);
+        // centered vertically
+        let baseline = line.ascent + (cell_height as f32 - line.new_line_size) / 2.0;
+        Ok(Rasterizer {
+            theme: theme.clone(),
+            font,
+            cell_width: cell_width.max(1),
+            cell_height: cell_height.max(1),
+            baseline,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    /// Size in pixels of an image of `cols` x `rows` cells
+    pub fn size(&self, cols: usize, rows: usize) -> (usize, usize) {
+        (cols * self.cell_width, rows * self.cell_height)
+    }
+
+    /// Draws the snapshot as seen (see effective colors), in the colors of the theme
+    /// except for those the child redefined, on a canvas of at least its size
+    pub fn draw(&mut self, snapshot: &Snapshot, canvas: &mut Canvas) {
+        let palette = &self.theme.apply(&snapshot.palette);
+        let fg = foreground(true, palette);
+        let bg = background(true, palette);
+        let default_bg = truecolor_from_avt(bg(&avt::Pen::default()).unwrap(), palette);
+        canvas.fill(0, 0, canvas.width, canvas.height, &default_bg);
+
+        for (row, line) in snapshot.lines.iter().enumerate() {
+            for (col, cell) in line.cells().iter().enumerate() {
+                let pen = cell.pen();
+                let (x, y) = (col * self.cell_width, row * self.cell_height);
+                let fg = truecolor_from_avt(fg(pen).unwrap(), palette);
+                let bg = truecolor_from_avt(bg(pen).unwrap(), palette);
+                canvas.fill(x, y, self.cell_width, self.cell_height, &bg);
+
+                let c = cell.char();
+                if c == ' ' {
+                    continue;
+                }
+                let (font, size) = (&self.font, self.theme.font_size);
+                let (metrics, bitmap) = self.glyphs.entry(c).or_insert_with(|| font.rasterize(c, size));
+                // bitmap rows top down, ymin is the offset of its bottom from the baseline
+                let top = y as f32 + self.baseline - (metrics.height as f32 + metrics.ymin as f32);
+                for (i, &coverage) in bitmap.iter().enumerate() {
+                    if coverage > 0 {
+                        let gx = x as i64 + i64::from(metrics.xmin) + (i % metrics.width) as i64;
+                        let gy = top.round() as i64 + (i / metrics.width) as i64;
+                        canvas.blend(gx, gy, coverage, &fg);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Seconds each frame is shown for, from the capture timestamps
+fn delays(frames: &[Snapshot], speed: f64) -> Vec<f64> {
+    let mut delays: Vec<f64> = frames
+        .windows(2)
+        .map(|w| (w[1].timestamp - w[0].timestamp).max(0.0) / speed)
+        .collect();
+    delays.push(LAST_FRAME_DELAY);
+    delays
+}
+
+fn canvases(frames: &[Snapshot], rasterizer: &mut Rasterizer) -> Vec<Canvas> {
+    let cols = frames.iter().map(|f| f.lines.first().map_or(0, |l| l.len())).max().unwrap_or(0);
+    let rows = frames.iter().map(|f| f.lines.len()).max().unwrap_or(0);
+    let (width, height) = rasterizer.size(cols, rows);
+    frames
+        .iter()
+        .map(|frame| {
+            let mut canvas = Canvas { width, height, rgb: vec![0; 3 * width * height] };
+            rasterizer.draw(frame, &mut canvas);
+            canvas
+        })
+        .collect()
+}
+
+fn write_gif(path: &Path, canvases: &[Canvas], delays: &[f64]) -> Result<()> {
+    let (width, height) = (u16::try_from(canvases[0].width)?, u16::try_from(canvases[0].height)?);
+    let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for (canvas, delay) in canvases.iter().zip(delays) {
+        let mut frame = gif::Frame::from_rgb_speed(width, height, &canvas.rgb, 10);
+        // in hundredths of a second, browsers treat less than 2 as 10
+        frame.delay = (delay * 100.0).round().clamp(2.0, f64::from(u16::MAX)) as u16;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+fn write_apng(path: &Path, canvases: &[Canvas], delays: &[f64]) -> Result<()> {
+    let (width, height) = (u32::try_from(canvases[0].width)?, u32::try_from(canvases[0].height)?);
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(u32::try_from(canvases.len())?, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (canvas, delay) in canvases.iter().zip(delays) {
+        // in milliseconds
+        writer.set_frame_delay((delay * 1000.0).round().min(f64::from(u16::MAX)) as u16, 1000)?;
+        writer.write_image_data(&canvas.rgb)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes the snapshot as a PNG image
+pub fn write_png(path: &Path, snapshot: &Snapshot, rasterizer: &mut Rasterizer) -> Result<()> {
+    let canvas = canvases(std::slice::from_ref(snapshot), rasterizer).swap_remove(0);
+    let (width, height) = (u32::try_from(canvas.width)?, u32::try_from(canvas.height)?);
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&canvas.rgb)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Animated image formats
+#[derive(Clone, Copy)]
+pub enum Format {
+    Gif,
+    Apng,
+}
+
+impl Format {
+    /// From the extension of `path`: .gif, or .png/.apng for APNG
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Format::Gif),
+            "png" | "apng" => Some(Format::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the frames, at least one, as an animation with each frame shown for as long as
+/// it lasted when captured, divided by `speed`
+pub fn write_animation(
+    path: &Path,
+    format: Format,
+    frames: &[Snapshot],
+    rasterizer: &mut Rasterizer,
+    speed: f64,
+) -> Result<()> {
+    let canvases = canvases(frames, rasterizer);
+    let delays = delays(frames, speed);
+    match format {
+        Format::Gif => write_gif(path, &canvases, &delays),
+        Format::Apng => write_apng(path, &canvases, &delays),
+    }
+}
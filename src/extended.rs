@@ -0,0 +1,330 @@
+// Attributes avt doesn't keep per cell (underline style and color, concealed text, hyperlinks) are tracked
+// by a second Vt fed the same output with SGR sequences rewritten to encode them as colors,
+// so they move with the cells through scrolling, insertions and erasures just like the rest.
+
+use crate::escapes::Event;
+use rgb::RGB8;
+use std::collections::{BTreeMap, HashMap};
+
+// foreground red of the shadow pen: set, then concealed and the underline style;
+// green and blue are the hyperlink id
+const SET: u8 = 0x80;
+const CONCEALED: u8 = 0x08;
+#[cfg(feature = "python")]
+const STYLE: u8 = 0x07;
+
+/// Underline styles as in SGR 4:n
+pub const UNDERLINE_NONE: u8 = 0;
+pub const UNDERLINE_SINGLE: u8 = 1;
+#[cfg(feature = "python")]
+pub const UNDERLINE_DOUBLE: u8 = 2;
+#[cfg(feature = "python")]
+pub const UNDERLINE_CURLY: u8 = 3;
+#[cfg(feature = "python")]
+pub const UNDERLINE_DOTTED: u8 = 4;
+pub const UNDERLINE_DASHED: u8 = 5;
+
+/// Line sizes as set with DECSWL, DECDWL and DECDHL (`ESC # 5`, `ESC # 6`, `ESC # 3` and `ESC # 4`)
+pub const LINE_SINGLE: u8 = 0;
+pub const LINE_DOUBLE_WIDTH: u8 = 1;
+pub const LINE_DOUBLE_HEIGHT_TOP: u8 = 2;
+pub const LINE_DOUBLE_HEIGHT_BOTTOM: u8 = 3;
+
+/// A Vt in the same state as `vt` (but for its scrollback), recreated from a dump of it
+pub fn clone_vt(vt: &avt::Vt) -> avt::Vt {
+    let (cols, rows) = vt.size();
+    let mut clone = avt::Vt::builder().size(cols, rows).build();
+    clone.feed_str(&vt.dump());
+    clone
+}
+
+#[derive(Default, Clone)]
+struct Pen {
+    underline_style: u8,
+    underline_color: Option<avt::Color>,
+    concealed: bool,
+    // 0 outside hyperlinks
+    link: u16,
+}
+
+// a color from SGR 38/48/58 parameters after the first, in colon or semicolon form,
+// and how many of `rest` it took in the latter
+fn color(parts: &[u16], rest: &[Vec<u16>]) -> (Option<avt::Color>, usize) {
+    let first = |i: usize| rest.get(i).and_then(|p| p.first()).copied().unwrap_or(0);
+    match parts {
+        [_, 5, index, ..] => (Some(avt::Color::Indexed(*index as u8)), 0),
+        // with or without a color space id
+        [_, 2, _, r, g, b] | [_, 2, r, g, b] => {
+            (Some(avt::Color::RGB(RGB8::new(*r as u8, *g as u8, *b as u8))), 0)
+        }
+        [_] => match first(0) {
+            5 => (Some(avt::Color::Indexed(first(1) as u8)), 2),
+            2 => (
+                Some(avt::Color::RGB(RGB8::new(first(1) as u8, first(2) as u8, first(3) as u8))),
+                4,
+            ),
+            _ => (None, 0),
+        },
+        _ => (None, 0),
+    }
+}
+
+impl Pen {
+    fn apply(&mut self, params: &str) {
+        let params: Vec<Vec<u16>> = params
+            .split(';')
+            .map(|p| p.split(':').map(|n| n.parse().unwrap_or(0)).collect())
+            .collect();
+        let mut i = 0;
+        while i < params.len() {
+            let parts = &params[i];
+            let rest = &params[i + 1..];
+            match parts.as_slice() {
+                // hyperlinks aren't graphic renditions
+                [0] => *self = Pen { link: self.link, ..Pen::default() },
+                [4] => self.underline_style = UNDERLINE_SINGLE,
+                [4, style, ..] => self.underline_style = (*style as u8).min(UNDERLINE_DASHED),
+                [24] => self.underline_style = UNDERLINE_NONE,
+                [8] => self.concealed = true,
+                [28] => self.concealed = false,
+                [38 | 48, ..] => i += color(parts, rest).1,
+                [58, ..] => {
+                    let (color, taken) = color(parts, rest);
+                    self.underline_color = color;
+                    i += taken;
+                }
+                [59] => self.underline_color = None,
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    // the SGR sequence setting the shadow pen
+    fn sgr(&self) -> String {
+        let fg = match (self.underline_style, self.concealed, self.link) {
+            (UNDERLINE_NONE, false, 0) => "39".to_string(),
+            (style, concealed, link) => {
+                let red = SET | style | if concealed { CONCEALED } else { 0 };
+                format!("38;2;{};{};{}", red, link >> 8, link & 0xff)
+            }
+        };
+        let bg = match self.underline_color {
+            None => "49".to_string(),
+            Some(avt::Color::Indexed(index)) => format!("48;5;{}", index),
+            Some(avt::Color::RGB(c)) => format!("48;2;{};{};{}", c.r, c.g, c.b),
+        };
+        format!("\x1b[0;{};{}m", fg, bg)
+    }
+}
+
+fn shadow(pen: &avt::Pen) -> Option<RGB8> {
+    match pen.foreground() {
+        Some(avt::Color::RGB(c)) if c.r & SET != 0 => Some(c),
+        _ => None,
+    }
+}
+
+fn shadow_red(pen: &avt::Pen) -> u8 {
+    shadow(pen).map(|c| c.r).unwrap_or(0)
+}
+
+/// Underline style of a cell of the extended lines, one of `UNDERLINE_*`
+#[cfg(feature = "python")]
+pub fn underline_style(pen: &avt::Pen) -> u8 {
+    shadow_red(pen) & STYLE
+}
+
+/// Underline color of a cell of the extended lines (SGR 58), None if the default
+#[cfg(feature = "python")]
+pub fn underline_color(pen: &avt::Pen) -> Option<avt::Color> {
+    pen.background()
+}
+
+/// Whether a cell of the extended lines is concealed (SGR 8)
+pub fn concealed(pen: &avt::Pen) -> bool {
+    shadow_red(pen) & CONCEALED != 0
+}
+
+/// Hyperlink id of a cell of the extended lines (OSC 8), 0 if not in a hyperlink
+pub fn link(pen: &avt::Pen) -> u16 {
+    shadow(pen).map(|c| u16::from(c.g) << 8 | u16::from(c.b)).unwrap_or(0)
+}
+
+/// The shadow Vt, fed a character at a time along with the escapes scanner
+pub struct Extended {
+    vt: avt::Vt,
+    pen: Pen,
+    // the escape sequence being scanned
+    sequence: String,
+    // rewritten output not fed to the Vt yet
+    pending: String,
+    // rows changed since the last `flush()`
+    changed: Vec<usize>,
+    // URIs of the hyperlinks seen, the link id of each being its index + 1
+    links: Vec<String>,
+    // link ids by id parameter and URI
+    link_ids: HashMap<(String, String), u16>,
+    // line sizes other than single by line index, counting scrollback so they follow the screen as it
+    // scrolls up; avt has no notion of them, so they don't follow insertions, deletions or scroll regions
+    line_sizes: HashMap<usize, u8>,
+}
+
+impl Clone for Extended {
+    fn clone(&self) -> Self {
+        Extended {
+            vt: clone_vt(&self.vt),
+            pen: self.pen.clone(),
+            sequence: self.sequence.clone(),
+            pending: self.pending.clone(),
+            changed: self.changed.clone(),
+            links: self.links.clone(),
+            link_ids: self.link_ids.clone(),
+            line_sizes: self.line_sizes.clone(),
+        }
+    }
+}
+
+impl Extended {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Extended {
+            vt: avt::Vt::builder().size(cols, rows).build(),
+            pen: Pen::default(),
+            sequence: String::new(),
+            pending: String::new(),
+            changed: Vec::new(),
+            links: Vec::new(),
+            link_ids: HashMap::new(),
+            line_sizes: HashMap::new(),
+        }
+    }
+
+    /// Feeds a character after the scanner, which gave `event` and is now `in_sequence` or not
+    pub fn feed(&mut self, c: char, event: &Option<Event>, in_sequence: bool) {
+        if in_sequence {
+            self.sequence.push(c);
+            return;
+        }
+        match event {
+            Some(Event::Csi { prefix: None, params, intermediates, action: 'm' }) if intermediates.is_empty() => {
+                self.pen.apply(params);
+                self.pending.push_str(&self.pen.sgr());
+                self.sequence.clear();
+                return;
+            }
+            Some(Event::Osc(osc)) if osc.starts_with("8;") => {
+                self.pen.link = self.link_id(&osc[2..]);
+                self.pending.push_str(&self.pen.sgr());
+                self.sequence.clear();
+                return;
+            }
+            Some(Event::Esc { intermediates, action: 'c' }) if intermediates.is_empty() => {
+                self.pen = Pen::default();
+                self.line_sizes.clear();
+            }
+            Some(Event::Esc { intermediates, action: action @ ('3' | '4' | '5' | '6') }) if intermediates == "#" => {
+                let size = match action {
+                    '6' => LINE_DOUBLE_WIDTH,
+                    '3' => LINE_DOUBLE_HEIGHT_TOP,
+                    '4' => LINE_DOUBLE_HEIGHT_BOTTOM,
+                    _ => LINE_SINGLE,
+                };
+                self.set_line_size(size);
+            }
+            Some(event @ Event::Csi { prefix: None, intermediates, action: 'J', .. })
+                if intermediates.is_empty() && matches!(event.params().first(), Some(2 | 3)) =>
+            {
+                self.feed_pending();
+                let top = self.top();
+                self.line_sizes.retain(|&line, _| line < top);
+            }
+            _ => {}
+        }
+        self.pending.push_str(&self.sequence);
+        self.pending.push(c);
+        self.sequence.clear();
+    }
+
+    // the id of the hyperlink opened by OSC 8 with `params;URI`, 0 if it closes one (empty URI);
+    // the same id parameter and URI (e.g. a link drawn in pieces) get the same id
+    fn link_id(&mut self, args: &str) -> u16 {
+        let Some((params, uri)) = args.split_once(';') else {
+            return 0;
+        };
+        if uri.is_empty() {
+            return 0;
+        }
+        let id = params.split(':').find_map(|p| p.strip_prefix("id=")).unwrap_or("");
+        let target = (id.to_string(), uri.to_string());
+        if let Some(&link) = self.link_ids.get(&target) {
+            return link;
+        }
+        // out of ids
+        if self.links.len() == usize::from(u16::MAX) {
+            return 0;
+        }
+        self.links.push(target.1.clone());
+        let link = self.links.len() as u16;
+        self.link_ids.insert(target, link);
+        link
+    }
+
+    /// The URI of each hyperlink id in `extended` lines
+    pub fn links_in(&self, extended: &[avt::Line]) -> BTreeMap<u16, String> {
+        extended
+            .iter()
+            .flat_map(|l| l.cells())
+            .map(|c| link(c.pen()))
+            .filter(|&id| id != 0)
+            .map(|id| (id, self.links[usize::from(id) - 1].clone()))
+            .collect()
+    }
+
+    // index of the first line of the screen
+    fn top(&self) -> usize {
+        self.vt.lines().len() - self.vt.size().1
+    }
+
+    // sets the size of the cursor line, once the output before it is in the Vt
+    fn set_line_size(&mut self, size: u8) {
+        self.feed_pending();
+        let row = self.vt.cursor().row;
+        self.changed.push(row);
+        let line = self.top() + row;
+        if size == LINE_SINGLE {
+            self.line_sizes.remove(&line);
+        } else {
+            self.line_sizes.insert(line, size);
+        }
+    }
+
+    /// Line size of each row of the screen, one of `LINE_*`, as of the last `flush()`
+    pub fn line_sizes(&self) -> Vec<u8> {
+        let top = self.top();
+        (top..top + self.vt.size().1)
+            .map(|line| self.line_sizes.get(&line).copied().unwrap_or(LINE_SINGLE))
+            .collect()
+    }
+
+    fn feed_pending(&mut self) {
+        let changes = self.vt.feed_str(&self.pending);
+        self.pending.clear();
+        self.changed.extend(changes.lines);
+    }
+
+    /// Feeds the Vt what has been fed so far, returning the rows that changed
+    pub fn flush(&mut self) -> Vec<usize> {
+        self.feed_pending();
+        std::mem::take(&mut self.changed)
+    }
+
+    /// The extended lines of the screen, as of the last `flush()`
+    pub fn view(&self) -> Vec<avt::Line> {
+        self.vt.view().to_vec()
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.flush();
+        self.vt.resize(cols, rows);
+    }
+}
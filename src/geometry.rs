@@ -0,0 +1,28 @@
+// Limits on terminal sizes: the pty keeps them as 16-bit numbers (`struct winsize`), and the emulator
+// allocates every cell up front, twice with the extended attributes.
+
+/// Most columns and rows a pty can have
+pub const MAX_COLS: usize = u16::MAX as usize;
+pub const MAX_ROWS: usize = u16::MAX as usize;
+/// Most cells of a screen, 4096 x 4096
+pub const MAX_CELLS: usize = 1 << 24;
+
+/// Bounding box as (top, left, bottom, right) rows and columns, inclusive
+pub type Bounds = (usize, usize, usize, usize);
+
+/// Checks a screen size, returning what is wrong with it
+pub fn check(cols: usize, rows: usize) -> Result<(), String> {
+    if cols == 0 || rows == 0 {
+        return Err(format!("invalid size {}x{}, it takes at least a column and a row", cols, rows));
+    }
+    if cols > MAX_COLS || rows > MAX_ROWS {
+        return Err(format!(
+            "invalid size {}x{}, a pty has at most {} columns and {} rows",
+            cols, rows, MAX_COLS, MAX_ROWS
+        ));
+    }
+    if cols * rows > MAX_CELLS {
+        return Err(format!("invalid size {}x{}, screens have at most {} cells", cols, rows, MAX_CELLS));
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+#[cfg(feature = "python")]
+use crate::labels::Labels;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Most recent error of the pty task, e.g. failing to restart the child
+pub type LastError = Arc<Mutex<Option<String>>>;
+
+/// Liveness signals of a session, see `Terminal.health()`
+#[cfg(feature = "python")]
+#[pyclass(get_all)]
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// The `name` of the Terminal, None if it has none
+    pub name: Option<String>,
+    /// The `labels` of the Terminal
+    pub labels: BTreeMap<String, String>,
+    /// Whether the child process is running (not exited or a zombie)
+    pub alive: bool,
+    /// Whether the pty is still being driven, i.e. input can be delivered
+    pub pty_open: bool,
+    /// Whether the watchdog has flagged the session as hung
+    pub hung: bool,
+    /// Bytes of output since the session started
+    pub output_bytes: u64,
+    /// Bytes of output since the previous call to `health()`
+    pub recent_output_bytes: u64,
+    /// Requests queued for the term task
+    pub pending_requests: usize,
+    /// Input chunks queued for the pty task
+    pub input_queue: usize,
+    /// Output chunks queued for the term task
+    pub output_queue: usize,
+    /// Most recent error of the session, such as a failed restart or a recording that couldn't be written
+    pub last_error: Option<String>,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Health {
+    pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let labels = Labels { name: self.name.clone(), labels: self.labels.clone() };
+        Ok(format!(
+            "Health({}, alive={}, pty_open={}, hung={}, output_bytes={}, recent_output_bytes={}, \
+             pending_requests={}, input_queue={}, output_queue={}, last_error={:?})",
+            labels.repr(py)?,
+            self.alive,
+            self.pty_open,
+            self.hung,
+            self.output_bytes,
+            self.recent_output_bytes,
+            self.pending_requests,
+            self.input_queue,
+            self.output_queue,
+            self.last_error,
+        ))
+    }
+}
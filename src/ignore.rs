@@ -0,0 +1,99 @@
+// Dynamic parts of the screen (clocks, timestamps, spinners) left out of diffs and comparisons,
+// given as rectangles or as regular expressions matched against the text of each row.
+
+use crate::geometry::Bounds;
+use crate::lines::diff_lines;
+use ndarray::Array2;
+use pyo3::exceptions::{PyAssertionError, PyValueError};
+use pyo3::prelude::*;
+use regex::Regex;
+
+#[derive(Default)]
+pub struct Ignore {
+    regions: Vec<Bounds>,
+    patterns: Vec<Regex>,
+}
+
+impl Ignore {
+    pub fn new(regions: Option<Vec<Bounds>>, patterns: Option<Vec<String>>) -> PyResult<Self> {
+        let patterns = patterns
+            .unwrap_or_default()
+            .iter()
+            .map(|p| Regex::new(p).map_err(|e| PyValueError::new_err(e.to_string())))
+            .collect::<PyResult<_>>()?;
+        Ok(Ignore { regions: regions.unwrap_or_default(), patterns })
+    }
+
+    /// Whether each cell of a row, with the characters `chars`, is ignored
+    pub fn row_mask(&self, row: usize, chars: &[char]) -> Vec<bool> {
+        let mut mask = vec![false; chars.len()];
+        for &(top, left, bottom, right) in &self.regions {
+            if (top..=bottom).contains(&row) {
+                mask.iter_mut().take(right + 1).skip(left).for_each(|m| *m = true);
+            }
+        }
+        if !self.patterns.is_empty() {
+            // a character per cell, so cells are found by counting characters up to byte offsets
+            let text: String = chars.iter().collect();
+            let cell = |offset: usize| text[..offset].chars().count();
+            for pattern in &self.patterns {
+                for m in pattern.find_iter(&text) {
+                    mask[cell(m.start())..cell(m.end())].iter_mut().for_each(|m| *m = true);
+                }
+            }
+        }
+        mask
+    }
+
+    /// A _rows_ x _cols_ matrix, True where the cell (character or pen) differs between `a` and `b`,
+    /// of the same shape, and isn't ignored in either
+    pub fn diff(&self, a: &[avt::Line], b: &[avt::Line]) -> Array2<bool> {
+        diff_lines(a, b) & !(self.mask(a) | self.mask(b))
+    }
+
+    /// A _rows_ x _cols_ matrix, True for ignored cells
+    pub fn mask(&self, lines: &[avt::Line]) -> Array2<bool> {
+        let cols = lines.first().map(|l| l.len()).unwrap_or(0);
+        let v: Vec<bool> = lines
+            .iter()
+            .enumerate()
+            .flat_map(|(row, l)| self.row_mask(row, &l.chars().collect::<Vec<_>>()))
+            .collect();
+        Array2::from_shape_vec([lines.len(), cols], v).unwrap()
+    }
+
+    // a row of text with the ignored cells blanked and trailing blanks trimmed
+    fn blank(&self, row: usize, text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mask = self.row_mask(row, &chars);
+        let blanked: String = chars.iter().zip(mask).map(|(&c, m)| if m { ' ' } else { c }).collect();
+        blanked.trim_end().to_string()
+    }
+
+    /// Compares the text of a screen with the `expected` text, a golden copy, row by row: ignored cells
+    /// and trailing blanks don't count, and missing rows at the end of `expected` are taken as blank.
+    /// Raises an AssertionError listing the rows that differ.
+    pub fn assert_text(&self, lines: &[avt::Line], expected: &str) -> PyResult<()> {
+        let expected: Vec<&str> = expected.lines().collect();
+        if expected.len() > lines.len() {
+            return Err(PyAssertionError::new_err(format!(
+                "expected {} rows, the screen has {}",
+                expected.len(),
+                lines.len()
+            )));
+        }
+        let differences: Vec<String> = lines
+            .iter()
+            .enumerate()
+            .filter_map(|(row, line)| {
+                let got = self.blank(row, &line.text());
+                let want = self.blank(row, expected.get(row).unwrap_or(&""));
+                (got != want).then(|| format!("row {}: expected {:?}, got {:?}", row, want, got))
+            })
+            .collect();
+        if differences.is_empty() {
+            return Ok(());
+        }
+        Err(PyAssertionError::new_err(format!("screen differs:\n{}", differences.join("\n"))))
+    }
+}
@@ -0,0 +1,244 @@
+// Snapshots as JSON, for tools other than NumPy: the cells of an `Archive` row by row, each with its character and,
+// when not the defaults, its colors and attributes.
+
+use crate::archive::{Archive, ARCHIVE_VERSION};
+use crate::cursor::{Cursor, SHAPES};
+use crate::snapshot::Snapshot;
+
+use rgb::RGB8;
+use serde::{Deserialize, Serialize};
+
+/// A palette index, or an RGB color as "#rrggbb"
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum Color {
+    Indexed(u8),
+    Rgb(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Cell {
+    char: char,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fg: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bg: Option<Color>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    attrs: u16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CursorJson {
+    row: usize,
+    col: usize,
+    visible: bool,
+    shape: String,
+    blinking: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct SnapshotJson {
+    version: u32,
+    cols: usize,
+    rows: usize,
+    cells: Vec<Vec<Cell>>,
+    continued: Vec<bool>,
+    cursor: CursorJson,
+    #[serde(default)]
+    alt_screen: bool,
+    #[serde(default)]
+    timestamp: f64,
+    palette: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_fg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_bg: Option<String>,
+}
+
+fn is_zero(attrs: &u16) -> bool {
+    *attrs == 0
+}
+
+fn hex(color: RGB8) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+fn rgb(hex: &str) -> Result<RGB8, String> {
+    let component = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    match (hex.len(), hex.strip_prefix('#'), component(1), component(3), component(5)) {
+        (7, Some(_), Some(r), Some(g), Some(b)) => Ok(RGB8 { r, g, b }),
+        _ => Err(format!("invalid color {:?}, colors are \"#rrggbb\"", hex)),
+    }
+}
+
+fn color(color: avt::Color) -> Color {
+    match color {
+        avt::Color::Indexed(i) => Color::Indexed(i),
+        avt::Color::RGB(c) => Color::Rgb(hex(c)),
+    }
+}
+
+fn avt_color(color: &Color) -> Result<avt::Color, String> {
+    match color {
+        Color::Indexed(i) => Ok(avt::Color::Indexed(*i)),
+        Color::Rgb(c) => rgb(c).map(avt::Color::RGB),
+    }
+}
+
+impl Snapshot {
+    /// The snapshot as JSON, in the layout described in the README: its size, cells, cursor and colors
+    pub fn to_json(&self) -> String {
+        let archive = Archive::new(self);
+        let cells = (0..archive.cols * archive.rows)
+            .map(|i| Cell {
+                char: archive.chars[i],
+                fg: archive.foreground[i].map(color),
+                bg: archive.background[i].map(color),
+                attrs: archive.attrs[i],
+            })
+            .collect::<Vec<_>>();
+        let json = SnapshotJson {
+            version: ARCHIVE_VERSION,
+            cols: archive.cols,
+            rows: archive.rows,
+            cells: cells.chunks(archive.cols.max(1)).map(|row| row.to_vec()).collect(),
+            continued: archive.continued,
+            cursor: CursorJson {
+                row: archive.cursor.row,
+                col: archive.cursor.col,
+                visible: archive.cursor.visible,
+                shape: archive.cursor.shape.to_string(),
+                blinking: archive.cursor.blinking,
+            },
+            alt_screen: archive.alt_screen,
+            timestamp: archive.timestamp,
+            palette: archive.palette.into_iter().map(hex).collect(),
+            default_fg: archive.default_foreground.map(hex),
+            default_bg: archive.default_background.map(hex),
+        };
+        serde_json::to_string(&json).unwrap()
+    }
+
+    /// The snapshot in `json` as written by `to_json`, or what is wrong with it
+    pub fn from_json(json: &str) -> Result<Snapshot, String> {
+        let json: SnapshotJson = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if json.version != ARCHIVE_VERSION {
+            return Err(format!("unknown snapshot JSON version {}", json.version));
+        }
+        if json.cells.len() != json.rows || json.cells.iter().any(|row| row.len() != json.cols) {
+            return Err(format!("the cells don't make up {}x{}", json.cols, json.rows));
+        }
+        let Some(&shape) = SHAPES.iter().find(|&&s| s == json.cursor.shape) else {
+            return Err(format!("unknown cursor shape {:?}", json.cursor.shape));
+        };
+        let cells: Vec<_> = json.cells.into_iter().flatten().collect();
+        let colors = |f: fn(&Cell) -> &Option<Color>| -> Result<Vec<_>, String> {
+            cells.iter().map(|c| f(c).as_ref().map(avt_color).transpose()).collect()
+        };
+        let archive = Archive {
+            cols: json.cols,
+            rows: json.rows,
+            chars: cells.iter().map(|c| c.char).collect(),
+            foreground: colors(|c| &c.fg)?,
+            background: colors(|c| &c.bg)?,
+            attrs: cells.iter().map(|c| c.attrs).collect(),
+            continued: json.continued,
+            cursor: Cursor {
+                row: json.cursor.row,
+                col: json.cursor.col,
+                visible: json.cursor.visible,
+                shape,
+                blinking: json.cursor.blinking,
+            },
+            alt_screen: json.alt_screen,
+            timestamp: json.timestamp,
+            palette: json.palette.iter().map(|c| rgb(c)).collect::<Result<_, _>>()?,
+            default_foreground: json.default_fg.as_deref().map(rgb).transpose()?,
+            default_background: json.default_bg.as_deref().map(rgb).transpose()?,
+        };
+        archive.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{attrs_from_lines, continued_from_lines, text_from_lines};
+    use crate::palette::Palette;
+    use crate::screen::Screen;
+
+    fn snapshot(cols: usize, rows: usize, output: &str) -> Snapshot {
+        let mut screen = Screen::new(cols, rows);
+        screen.feed_str(output);
+        screen.snapshot()
+    }
+
+    fn error(json: &str) -> String {
+        Snapshot::from_json(json).err().unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut snapshot = snapshot(
+            8,
+            3,
+            "\x1b[1;31mab\x1b[0;38;2;255;0;128;48;5;17mcd\x1b[0;8mef\x1b[0m宽01234\x1b[?25l\x1b[5 q\x1b[3;2H",
+        );
+        snapshot.palette = Palette::configured(&[RGB8 { r: 1, g: 2, b: 3 }], None, Some(RGB8 { r: 4, g: 5, b: 6 }));
+        let json = snapshot.to_json();
+        let rebuilt = Snapshot::from_json(&json).unwrap();
+        assert!(rebuilt.lines == snapshot.lines);
+        assert_eq!(
+            attrs_from_lines(&rebuilt.lines, &rebuilt.extended),
+            attrs_from_lines(&snapshot.lines, &snapshot.extended)
+        );
+        assert_eq!(continued_from_lines(&rebuilt.lines).to_vec(), [false, true, false]);
+        assert_eq!(rebuilt.cursor, snapshot.cursor);
+        assert!(!rebuilt.cursor.visible);
+        assert_eq!(rebuilt.cursor.shape, "bar");
+        assert_eq!(rebuilt.timestamp, snapshot.timestamp);
+        assert_eq!(rebuilt.palette.colors, snapshot.palette.colors);
+        assert_eq!(rebuilt.palette.background, Some(RGB8 { r: 4, g: 5, b: 6 }));
+        assert_eq!(rebuilt.screen_hash(), snapshot.screen_hash());
+        assert_eq!(rebuilt.to_json(), json);
+    }
+
+    #[test]
+    fn writes_the_documented_layout() {
+        let json = snapshot(3, 1, "\x1b[1;31ma\x1b[0;48;2;1;2;3mb").to_json();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!((json["cols"].as_u64(), json["rows"].as_u64()), (Some(3), Some(1)));
+        assert_eq!(json["cells"][0][0], serde_json::json!({"char": "a", "fg": 1, "attrs": 1}));
+        assert_eq!(json["cells"][0][1], serde_json::json!({"char": "b", "bg": "#010203"}));
+        assert_eq!(json["cells"][0][2], serde_json::json!({"char": " "}));
+        assert_eq!(json["cursor"]["col"], 2);
+        assert_eq!(json["palette"].as_array().unwrap().len(), 256);
+        assert!(json.get("default_fg").is_none());
+    }
+
+    #[test]
+    fn replaces_control_characters_with_spaces() {
+        let json = snapshot(3, 1, "abc").to_json().replace("\"char\":\"b\"", "\"char\":\"\\u0007\"");
+        let rebuilt = Snapshot::from_json(&json).unwrap();
+        assert_eq!(text_from_lines(&rebuilt.lines), "a c");
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let json: serde_json::Value = serde_json::from_str(&snapshot(3, 2, "abc").to_json()).unwrap();
+        let changed = |change: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            change(&mut json);
+            error(&json.to_string())
+        };
+        assert!(error("{}").contains("missing field `version`"));
+        assert!(changed(&|j| j["version"] = 2.into()).contains("version 2"));
+        assert!(changed(&|j| j["cols"] = 4.into()).contains("don't make up 4x2"));
+        assert!(changed(&|j| j["cursor"]["shape"] = "box".into()).contains("cursor shape"));
+        assert!(changed(&|j| j["palette"][0] = "black".into()).contains("invalid color"));
+        assert!(changed(&|j| _ = j["palette"].as_array_mut().unwrap().pop()).contains("255 palette colors"));
+        assert!(changed(&|j| j["cells"][0][0]["fg"] = "#12345".into()).contains("invalid color"));
+        assert!(changed(&|j| j["cells"][1][0]["char"] = "ab".into()).contains("invalid"));
+    }
+}
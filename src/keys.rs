@@ -1,3 +1,5 @@
+use std::sync::{Arc, Mutex};
+
 #[derive(Debug, PartialEq)]
 pub enum InputSeq {
     Standard(String),
@@ -22,6 +24,128 @@ fn seq_as_bytes(seq: &InputSeq, app_mode: bool) -> &[u8] {
     }
 }
 
+/// What `\n` in input text is sent as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Newline {
+    Lf,
+    Cr,
+    CrLf,
+    /// Whatever ends a line given the pty's termios, like the Enter key
+    Auto,
+}
+
+impl Newline {
+    pub fn parse(name: &str) -> Option<Newline> {
+        match name {
+            "lf" => Some(Newline::Lf),
+            "cr" => Some(Newline::Cr),
+            "crlf" => Some(Newline::CrLf),
+            "auto" => Some(Newline::Auto),
+            _ => None,
+        }
+    }
+
+    /// `Auto` resolved for the input flags and local mode flags of the pty: Enter sends CR, which
+    /// ends a line in canonical mode only if ICRNL translates it, else LF is needed
+    pub fn resolve(self, icrnl: bool, icanon: bool) -> Newline {
+        match self {
+            Newline::Auto if icanon && !icrnl => Newline::Lf,
+            Newline::Auto => Newline::Cr,
+            newline => newline,
+        }
+    }
+
+    pub fn translate(self, text: &str) -> String {
+        match self {
+            Newline::Lf | Newline::Auto => text.to_string(),
+            Newline::Cr => text.replace('\n', "\r"),
+            Newline::CrLf => text.replace('\n', "\r\n"),
+        }
+    }
+}
+
+/// How keys with modifiers are encoded, as requested by the child
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyEncoding {
+    #[default]
+    Legacy,
+    /// xterm's modifyOtherKeys, at level 1 or 2
+    ModifyOtherKeys(u16),
+    /// fixterms / kitty `CSI code ; modifiers u`
+    CsiU,
+}
+
+/// Updated by the term task as the child changes the encoding, read when sending keys
+pub type SharedKeyEncoding = Arc<Mutex<KeyEncoding>>;
+
+const SHIFT: u32 = 1;
+const ALT: u32 = 2;
+const CTRL: u32 = 4;
+
+fn split_modifiers(key: &str) -> (u32, &str) {
+    let mut mods = 0;
+    let mut rest = key;
+    // a prefix with nothing after it is the key itself, e.g. `-` in `C--`
+    while let Some((m, after)) = [("S-", SHIFT), ("A-", ALT), ("C-", CTRL)]
+        .iter()
+        .find_map(|&(prefix, m)| Some((m, rest.strip_prefix(prefix)?)))
+        .filter(|(_, after)| !after.is_empty())
+    {
+        mods |= m;
+        rest = after;
+    }
+    (mods, rest)
+}
+
+fn key_code(name: &str) -> Option<u32> {
+    match name {
+        "Enter" => Some(13),
+        "Tab" => Some(9),
+        "Escape" => Some(27),
+        "Backspace" => Some(127),
+        "Space" => Some(32),
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(u32::from(c)),
+                _ => None,
+            }
+        }
+    }
+}
+
+// None when the key is sent as in legacy encoding
+fn encoded_key(key: &str, encoding: KeyEncoding) -> Option<String> {
+    let (mods, name) = split_modifiers(key);
+    let mut code = key_code(name)?;
+    if mods & CTRL != 0 {
+        // C-A is C-a, as in legacy encoding
+        code = char::from_u32(code).map_or(code, |c| u32::from(c.to_ascii_lowercase()));
+    }
+
+    match encoding {
+        KeyEncoding::Legacy => None,
+        KeyEncoding::CsiU if mods == 0 => (code == 27).then(|| "\x1b[27u".to_string()),
+        KeyEncoding::CsiU => Some(format!("\x1b[{};{}u", code, mods + 1)),
+        KeyEncoding::ModifyOtherKeys(_) if mods == 0 => None,
+        KeyEncoding::ModifyOtherKeys(level) => {
+            // level 1 only covers combinations without a legacy encoding
+            if level < 2 && parse_key(key.to_string()) != InputSeq::Standard(key.to_string()) {
+                return None;
+            }
+            Some(format!("\x1b[27;{};{}~", mods + 1, code))
+        }
+    }
+}
+
+/// Like `parse_key` but encoding modified keys as requested by the child
+pub fn parse_key_with(key: String, encoding: KeyEncoding) -> InputSeq {
+    match encoded_key(&key, encoding) {
+        Some(seq) => standard_key(seq),
+        None => parse_key(key),
+    }
+}
+
 fn standard_key<S: ToString>(seq: S) -> InputSeq {
     InputSeq::Standard(seq.to_string())
 }
@@ -174,3 +298,78 @@ pub fn parse_key(key: String) -> InputSeq {
 
     standard_key(seq)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(key: &str, encoding: KeyEncoding) -> InputSeq {
+        parse_key_with(key.to_string(), encoding)
+    }
+
+    fn standard(seq: &str) -> InputSeq {
+        InputSeq::Standard(seq.to_string())
+    }
+
+    const ENCODINGS: [KeyEncoding; 4] =
+        [KeyEncoding::Legacy, KeyEncoding::ModifyOtherKeys(1), KeyEncoding::ModifyOtherKeys(2), KeyEncoding::CsiU];
+
+    #[test]
+    fn splits_modifiers() {
+        assert_eq!(split_modifiers("C-S-a"), (CTRL | SHIFT, "a"));
+        assert_eq!(split_modifiers("A-Enter"), (ALT, "Enter"));
+        assert_eq!(split_modifiers("C--"), (CTRL, "-"));
+        assert_eq!(split_modifiers("C-"), (0, "C-"));
+        assert_eq!(split_modifiers("C-€"), (CTRL, "€"));
+        assert_eq!(split_modifiers("€"), (0, "€"));
+    }
+
+    #[test]
+    fn sends_non_ascii_keys_as_they_are() {
+        for encoding in ENCODINGS {
+            assert_eq!(sent("€", encoding), standard("€"));
+            assert_eq!(sent("宽", encoding), standard("宽"));
+            assert_eq!(sent("😀", encoding), standard("😀"));
+        }
+        assert_eq!(sent("A-€", KeyEncoding::Legacy), standard("\x1b€"));
+    }
+
+    #[test]
+    fn encodes_modified_non_ascii_keys() {
+        assert_eq!(sent("C-€", KeyEncoding::CsiU), standard("\x1b[8364;5u"));
+        assert_eq!(sent("A-😀", KeyEncoding::CsiU), standard("\x1b[128512;3u"));
+        assert_eq!(sent("C-€", KeyEncoding::ModifyOtherKeys(1)), standard("\x1b[27;5;8364~"));
+        assert_eq!(sent("C-€", KeyEncoding::ModifyOtherKeys(2)), standard("\x1b[27;5;8364~"));
+    }
+
+    #[test]
+    fn encodes_csi_u() {
+        assert_eq!(sent("C-a", KeyEncoding::CsiU), standard("\x1b[97;5u"));
+        assert_eq!(sent("C-A", KeyEncoding::CsiU), standard("\x1b[97;5u"));
+        assert_eq!(sent("C-S-a", KeyEncoding::CsiU), standard("\x1b[97;6u"));
+        assert_eq!(sent("A-Enter", KeyEncoding::CsiU), standard("\x1b[13;3u"));
+        assert_eq!(sent("C-Space", KeyEncoding::CsiU), standard("\x1b[32;5u"));
+        // unmodified keys are sent as they are, but Escape, which would be ambiguous
+        assert_eq!(sent("a", KeyEncoding::CsiU), standard("a"));
+        assert_eq!(sent("Enter", KeyEncoding::CsiU), standard("\r"));
+        assert_eq!(sent("Escape", KeyEncoding::CsiU), standard("\x1b[27u"));
+        // keys without a code keep their legacy encoding
+        assert_eq!(sent("Left", KeyEncoding::CsiU), InputSeq::Cursor("\x1b[D".to_string(), "\x1bOD".to_string()));
+        assert_eq!(sent("C-F5", KeyEncoding::CsiU), standard("\x1b[15;5~"));
+    }
+
+    #[test]
+    fn encodes_modify_other_keys() {
+        assert_eq!(sent("C-a", KeyEncoding::ModifyOtherKeys(2)), standard("\x1b[27;5;97~"));
+        assert_eq!(sent("C-S-a", KeyEncoding::ModifyOtherKeys(2)), standard("\x1b[27;6;97~"));
+        assert_eq!(sent("A-x", KeyEncoding::ModifyOtherKeys(2)), standard("\x1b[27;3;120~"));
+        // level 1 keeps the legacy encoding where there is one
+        assert_eq!(sent("C-a", KeyEncoding::ModifyOtherKeys(1)), standard("\x01"));
+        assert_eq!(sent("A-x", KeyEncoding::ModifyOtherKeys(1)), standard("\x1bx"));
+        assert_eq!(sent("C-Enter", KeyEncoding::ModifyOtherKeys(1)), standard("\x1b[27;5;13~"));
+        assert_eq!(sent("S-Tab", KeyEncoding::ModifyOtherKeys(1)), standard("\x1b[27;2;9~"));
+        // unmodified keys are sent as they are
+        assert_eq!(sent("a", KeyEncoding::ModifyOtherKeys(2)), standard("a"));
+        assert_eq!(sent("Escape", KeyEncoding::ModifyOtherKeys(2)), standard("\x1b"));
+    }
+}
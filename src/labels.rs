@@ -0,0 +1,47 @@
+// What tells Terminals apart when many run at once, e.g. in a pool: carried into health reports,
+// recordings and the exceptions raised, so diagnostics point at the session they came from.
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+
+/// The `name` and `labels` a Terminal was created with
+#[derive(Clone, Debug, Default)]
+pub struct Labels {
+    pub name: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Labels {
+    /// `name='...', labels={...}` as in Python, for reprs
+    #[cfg(feature = "python")]
+    pub fn repr(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "name={}, labels={}",
+            self.name.clone().into_pyobject(py)?.repr()?,
+            self.labels.clone().into_pyobject(py)?.repr()?,
+        ))
+    }
+
+    /// Sets the `name` and `labels` attributes of an exception raised for the Terminal,
+    /// and adds a note (shown in tracebacks from Python 3.11) naming it
+    #[cfg(feature = "python")]
+    pub fn tag(&self, py: Python<'_>, err: PyErr) -> PyErr {
+        if self.name.is_none() && self.labels.is_empty() {
+            return err;
+        }
+        let value = err.value(py);
+        let tag = || -> PyResult<()> {
+            value.setattr("name", self.name.clone())?;
+            value.setattr("labels", self.labels.clone())?;
+            if value.hasattr("add_note")? {
+                value.call_method1("add_note", (format!("in Terminal({})", self.repr(py)?),))?;
+            }
+            Ok(())
+        };
+        match tag() {
+            Ok(()) => err,
+            Err(e) => e,
+        }
+    }
+}
@@ -1,57 +1,613 @@
+#[cfg(feature = "python")]
 use crate::color::indexedcolor_from_avt;
+#[cfg(feature = "python")]
+use crate::color::rgb32_from_avt;
+#[cfg(feature = "python")]
+use crate::color::DEFAULT_RGB32;
 use crate::color::truecolor_from_avt;
-use ndarray::{Array2, Array3};
+use crate::extended::concealed;
+#[cfg(feature = "python")]
+use crate::extended::{link, underline_style};
+use crate::geometry::Bounds;
+use crate::palette::Palette;
+use avt::util::TextUnwrapper;
+use ndarray::{Array1, Array2};
+#[cfg(feature = "python")]
+use ndarray::Array3;
+#[cfg(feature = "python")]
+use numpy::{Element, PyArrayDescr, PyFixedUnicode};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyDict;
+#[cfg(feature = "python")]
+use std::mem::offset_of;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 
-fn style_fg(c: avt::Color) -> String {
+// SGR parameters for a color, `base` 38 for foreground or 48 for background;
+// indexed colors the child redefined are rendered as truecolor
+#[cfg(feature = "python")]
+fn style(c: avt::Color, base: u8, palette: &Palette) -> String {
     match c {
-        avt::Color::RGB(_) => {
-            // TBD
-            "".to_string()
+        avt::Color::Indexed(u8) if !palette.redefined(u8) => {
+            format!("\x1b[{};5;{}m", base, u8)
         }
-        avt::Color::Indexed(u8) => {
-            format!("\x1b[38;5;{}m", u8)
+        c => {
+            let c = truecolor_from_avt(c, palette);
+            format!("\x1b[{};2;{};{};{}m", base, c.r, c.g, c.b)
         }
     }
 }
 
 
-fn style_bg(c: avt::Color) -> String {
-    match c {
-        avt::Color::RGB(_) => {
-            // TBD
-            "".to_string()
+// columns of the lines, 0 if there are none, so matrices of an empty screen come out 0 x 0
+fn width(lines: &[avt::Line]) -> usize {
+    lines.first().map_or(0, |l| l.len())
+}
+
+
+pub fn chars_from_lines(lines: &[avt::Line]) -> Array2<u32> {
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let v: Vec<_> = lines.iter()
+        .flat_map(|l|l.chars().map(u32::from))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+/// Like `chars_from_lines` but NFC-normalized: a combining mark printed after a base character
+/// lands in a cell of its own, so runs of base + marks that compose to a single code point are
+/// replaced by it in the base cell and blanks in the cells of the marks.
+pub fn nfc_chars_from_lines(lines: &[avt::Line]) -> Array2<u32> {
+    let mut m = chars_from_lines(lines);
+    for mut row in m.rows_mut() {
+        let cols = row.len();
+        let mut col = 0;
+        while col < cols {
+            let mut end = col + 1;
+            while end < cols && char::from_u32(row[end]).is_some_and(is_combining_mark) {
+                end += 1;
+            }
+            let run: String = row.iter()
+                .skip(col)
+                .take(end - col)
+                .filter_map(|&c| char::from_u32(c))
+                .collect();
+            let mut composed = run.nfc();
+            if let (Some(c), None) = (composed.next(), composed.next()) {
+                row[col] = u32::from(c);
+                row.iter_mut().skip(col + 1).take(end - col - 1).for_each(|c| *c = u32::from(' '));
+            }
+            col = end;
         }
-        avt::Color::Indexed(u8) => {
-            format!("\x1b[48;5;{}m", u8)
+    }
+    m
+}
+
+
+const ZWJ: char = '\u{200d}';
+
+// characters that extend the grapheme cluster before them: combining marks, joiners,
+// variation selectors, emoji skin tone modifiers and tags
+fn extends_grapheme(c: char) -> bool {
+    is_combining_mark(c)
+        || matches!(c, ZWJ | '\u{fe00}'..='\u{fe0f}' | '\u{1f3fb}'..='\u{1f3ff}' | '\u{e0020}'..='\u{e007f}' | '\u{e0100}'..='\u{e01ef}')
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1f1e6}'..='\u{1f1ff}')
+}
+
+/// The full grapheme cluster of each cell: every code point takes a cell of its own, so a cluster
+/// (base + combining marks, ZWJ emoji sequences, variation selectors, flags) is gathered into the cell
+/// of its first code point, leaving empty strings in the cells of the rest.
+pub fn graphemes_from_lines(lines: &[avt::Line]) -> Vec<Vec<String>> {
+    lines.iter()
+        .map(|l| {
+            let mut row: Vec<String> = vec![];
+            // the cell of the current cluster, and whether a flag in it is still missing its second half
+            let mut start = 0;
+            let mut flag = false;
+            for (col, c) in l.chars().enumerate() {
+                let joined = row.get(start).is_some_and(|g| g.ends_with(ZWJ));
+                if col > 0 && (extends_grapheme(c) || joined || (flag && is_regional_indicator(c))) {
+                    row[start].push(c);
+                    row.push(String::new());
+                    flag = false;
+                } else {
+                    start = col;
+                    flag = is_regional_indicator(c);
+                    row.push(c.to_string());
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+
+/// Code points per cell in `chars_str_from_lines`, enough for ZWJ family emoji and tag flags
+#[cfg(feature = "python")]
+pub const CHARS_STR_LEN: usize = 8;
+
+#[cfg(feature = "python")]
+/// The grapheme clusters of `graphemes_from_lines` as fixed-size unicode strings, for a numpy
+/// `<U8` array; longer clusters are truncated
+pub fn chars_str_from_lines(lines: &[avt::Line]) -> Array2<PyFixedUnicode<CHARS_STR_LEN>> {
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let v: Vec<_> = graphemes_from_lines(lines)
+        .iter()
+        .flatten()
+        .map(|g| {
+            let mut s = [0; CHARS_STR_LEN];
+            for (c, u) in s.iter_mut().zip(g.chars()) {
+                *c = u32::from(u);
+            }
+            PyFixedUnicode(s)
+        })
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+/// How many columns each cell takes: 2 for double-width characters (CJK, emoji), 0 for the blank
+/// cell after one, which its glyph covers, and 1 for anything else.
+/// The emulator advances a single column per character, so a double-width character followed
+/// right away by another one has no continuation cell.
+#[cfg(feature = "python")]
+pub fn cell_widths_from_lines(lines: &[avt::Line]) -> Array2<u8> {
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let mut v = Vec::with_capacity(rows * cols);
+    for line in lines {
+        let mut covered = false;
+        for cell in line.cells() {
+            if covered && cell.char() == ' ' {
+                v.push(0);
+                covered = false;
+            } else {
+                covered = cell.width() == 2;
+                v.push(if covered { 2 } else { 1 });
+            }
         }
     }
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
 }
 
 
-pub fn chars_from_lines(lines: &Vec<avt::Line>) -> Array2<u32> {
+/// Kinds of cell content in `kinds_from_lines`
+#[cfg(feature = "python")]
+pub const KIND_EMPTY: u8 = 0;
+#[cfg(feature = "python")]
+pub const KIND_TEXT: u8 = 1;
+#[cfg(feature = "python")]
+pub const KIND_HYPERLINK: u8 = 2;
+
+/// What each cell holds: nothing (a blank with the default background), text, or part of the
+/// anchor of a hyperlink, as the extended lines have it (see `link`).
+#[cfg(feature = "python")]
+pub fn kinds_from_lines(lines: &[avt::Line], extended: &[avt::Line]) -> Array2<u8> {
     let rows = lines.len();
-    let line0 = lines.get(0).unwrap();
-    let cols = line0.len();
+    let cols = width(lines);
 
     let v: Vec<_> = lines.iter()
-        .flat_map(|l|l.chars().map(|c| u32::from(c)))
+        .flat_map(|l| l.cells())
+        .zip(extended.iter().flat_map(|l| l.cells()))
+        .map(|(c, e)| {
+            let pen = c.pen();
+            if link(e.pen()) != 0 {
+                KIND_HYPERLINK
+            } else if c.char() == ' ' && pen.background().is_none() && !pen.is_inverse() {
+                KIND_EMPTY
+            } else {
+                KIND_TEXT
+            }
+        })
         .collect();
 
     Array2::from_shape_vec([rows, cols], v).unwrap()
 }
 
 
-pub fn truecolor_from_lines<F>(lines: &Vec<avt::Line>, f: F) -> (Array3<u8>, Array2<bool>)
+/// Whether each cell's pen has an attribute, e.g. `avt::Pen::is_bold`
+#[cfg(feature = "python")]
+pub fn attr_from_lines<F>(lines: &[avt::Line], f: F) -> Array2<bool>
+where
+    F: Fn(&avt::Pen) -> bool,
+{
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let v: Vec<_> = lines.iter()
+        .flat_map(|l| l.cells())
+        .map(|c| f(c.pen()))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+/// Bits of the pen attributes in `attrs_from_lines`
+pub const ATTR_BOLD: u16 = 1;
+pub const ATTR_FAINT: u16 = 1 << 1;
+pub const ATTR_ITALIC: u16 = 1 << 2;
+pub const ATTR_UNDERLINE: u16 = 1 << 3;
+pub const ATTR_BLINK: u16 = 1 << 4;
+pub const ATTR_INVERSE: u16 = 1 << 5;
+pub const ATTR_HIDDEN: u16 = 1 << 6;
+pub const ATTR_STRIKETHROUGH: u16 = 1 << 7;
+
+type PenAttr = fn(&avt::Pen) -> bool;
+
+const ATTR_BITS: [(PenAttr, u16); 7] = [
+    (avt::Pen::is_bold, ATTR_BOLD),
+    (avt::Pen::is_faint, ATTR_FAINT),
+    (avt::Pen::is_italic, ATTR_ITALIC),
+    (avt::Pen::is_underline, ATTR_UNDERLINE),
+    (avt::Pen::is_blink, ATTR_BLINK),
+    (avt::Pen::is_inverse, ATTR_INVERSE),
+    (avt::Pen::is_strikethrough, ATTR_STRIKETHROUGH),
+];
+
+/// The `ATTR_*` bits of a cell given its pen and that of the extended lines
+pub fn cell_attrs(pen: &avt::Pen, extended: &avt::Pen) -> u16 {
+    let attrs = ATTR_BITS.iter().filter(|(f, _)| f(pen)).fold(0, |attrs, (_, bit)| attrs | bit);
+    if concealed(extended) { attrs | ATTR_HIDDEN } else { attrs }
+}
+
+/// Whether any cell has any of the `ATTR_*` bits of `attrs`, see `cell_attrs`
+#[cfg(feature = "python")]
+pub fn any_attrs(lines: &[avt::Line], extended: &[avt::Line], attrs: u16) -> bool {
+    attrs_from_lines(lines, extended).iter().any(|a| a & attrs != 0)
+}
+
+/// The attributes of each cell's pen packed as `ATTR_*` bits, hidden (concealed) from the extended lines
+pub fn attrs_from_lines(lines: &[avt::Line], extended: &[avt::Line]) -> Array2<u16> {
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let v: Vec<_> = lines.iter()
+        .flat_map(|l| l.cells())
+        .zip(extended.iter().flat_map(|l| l.cells()))
+        .map(|(c, e)| cell_attrs(c.pen(), e.pen()))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+/// The underline style (`UNDERLINE_*`) of each cell of the extended lines
+#[cfg(feature = "python")]
+pub fn underline_style_from_lines(extended: &[avt::Line]) -> Array2<u8> {
+    let rows = extended.len();
+    let cols = width(extended);
+
+    let v: Vec<_> = extended.iter()
+        .flat_map(|l| l.cells())
+        .map(|c| underline_style(c.pen()))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+/// The hyperlink id of each cell of the extended lines, 0 outside hyperlinks
+#[cfg(feature = "python")]
+pub fn links_from_lines(extended: &[avt::Line]) -> Array2<u16> {
+    let rows = extended.len();
+    let cols = width(extended);
+
+    let v: Vec<_> = extended.iter()
+        .flat_map(|l| l.cells())
+        .map(|c| link(c.pen()))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+pub fn text_from_lines(lines: &[avt::Line]) -> String {
+    lines
+        .iter()
+        .map(|l| l.text())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The text of each row, without trailing spaces if `strip_trailing`
+pub fn rows_from_lines(lines: &[avt::Line], strip_trailing: bool) -> Vec<String> {
+    lines
+        .iter()
+        .map(|l| {
+            let text = l.text();
+            match strip_trailing {
+                true => text.trim_end().to_string(),
+                false => text,
+            }
+        })
+        .collect()
+}
+
+/// How `text()` cleans up the text, all off by default for the rows as they are
+#[derive(Clone, Copy, Default)]
+pub struct TextOptions {
+    /// Trailing spaces of each line dropped
+    pub strip_trailing: bool,
+    /// Blank lines at the end dropped
+    pub trim_blank_lines: bool,
+    /// Soft-wrapped rows joined into the logical lines they are part of
+    pub join_wrapped: bool,
+    /// Control characters and trailing spaces shown as symbols and line ends marked, see `show_controls`
+    pub show_controls: bool,
+}
+
+/// Marks the end of a line that doesn't wrap into the next row
+pub const LINE_END: char = '\u{23ce}';
+/// Stands for a trailing space
+pub const VISIBLE_SPACE: char = '\u{2420}';
+
+// the Control Pictures symbol of a C0 control or DEL, e.g. ␉ for a tab
+fn control_picture(c: char) -> Option<char> {
+    match c {
+        '\0'..='\u{1f}' => char::from_u32(0x2400 + c as u32),
+        '\u{7f}' => Some('\u{2421}'),
+        _ => None,
+    }
+}
+
+/// `line` with its control characters as their Control Pictures symbols, its trailing spaces as `VISIBLE_SPACE`
+/// and, unless it goes on in the next row, `LINE_END` after it
+fn show_controls(line: &str, wraps: bool) -> String {
+    let content = line.trim_end_matches(' ');
+    let mut shown: String = content.chars().map(|c| control_picture(c).unwrap_or(c)).collect();
+    shown.extend(std::iter::repeat_n(VISIBLE_SPACE, line.len() - content.len()));
+    if !wraps {
+        shown.push(LINE_END);
+    }
+    shown
+}
+
+/// Like `text_from_lines`, cleaned up as in `options`
+pub fn text_from_lines_with(lines: &[avt::Line], options: TextOptions) -> String {
+    let mut text: Vec<String> = vec![];
+    // whether each line of `text` goes on in the next row
+    let mut wraps: Vec<bool> = vec![];
+    for (line, continued) in lines.iter().zip(continued_from_lines(lines)) {
+        match text.last_mut() {
+            Some(last) if continued && options.join_wrapped => last.push_str(&line.text()),
+            _ => {
+                if let Some(wrapped) = wraps.last_mut() {
+                    *wrapped = continued;
+                }
+                text.push(line.text());
+                wraps.push(false);
+            }
+        }
+    }
+    if options.strip_trailing {
+        for line in text.iter_mut() {
+            line.truncate(line.trim_end().len());
+        }
+    }
+    if options.trim_blank_lines {
+        while text.last().is_some_and(|l| l.trim().is_empty()) {
+            text.pop();
+        }
+    }
+    if options.show_controls {
+        for (line, wraps) in text.iter_mut().zip(wraps) {
+            *line = show_controls(line, wraps);
+        }
+    }
+    text.join("\n")
+}
+
+/// The `(row, col)` of the first cell of each occurrence of `text`, top to bottom and left to right,
+/// not overlapping. With `wrapped` an occurrence can go on in the rows a soft-wrapped line continues in.
+pub fn find_in_lines(lines: &[avt::Line], text: &str, wrapped: bool) -> Vec<(usize, usize)> {
+    let needle: Vec<char> = text.chars().collect();
+    if needle.is_empty() {
+        return vec![];
+    }
+    // the cells searched in one go: each row, or each logical line with `wrapped`
+    let mut runs: Vec<Vec<(char, (usize, usize))>> = vec![];
+    for (row, (line, continued)) in lines.iter().zip(continued_from_lines(lines)).enumerate() {
+        let cells = line.chars().enumerate().map(|(col, c)| (c, (row, col)));
+        match runs.last_mut() {
+            Some(run) if continued && wrapped => run.extend(cells),
+            _ => runs.push(cells.collect()),
+        }
+    }
+    let mut hits = vec![];
+    for run in runs {
+        let mut i = 0;
+        while i + needle.len() <= run.len() {
+            if run[i..i + needle.len()].iter().map(|(c, _)| *c).eq(needle.iter().copied()) {
+                hits.push(run[i].1);
+                i += needle.len();
+            } else {
+                i += 1;
+            }
+        }
+    }
+    hits
+}
+
+
+// box-drawing characters and block elements
+#[cfg(feature = "python")]
+fn is_drawing(c: char) -> bool {
+    matches!(c, '\u{2500}'..='\u{259f}')
+}
+
+// ASCII characters rules and borders are made of
+#[cfg(feature = "python")]
+fn is_ascii_decoration(c: char) -> bool {
+    matches!(c, '-' | '=' | '_' | '*' | '~' | '#' | '+' | '|')
+}
+
+// a line with drawing characters and words made only of decoration dropped,
+// and table borders stripped from words
+#[cfg(feature = "python")]
+fn reading_line(line: &str) -> String {
+    let line: String = line.chars().map(|c| if is_drawing(c) { ' ' } else { c }).collect();
+    line.split_whitespace()
+        .filter(|w| !w.chars().all(is_ascii_decoration))
+        .map(|w| w.trim_matches('|'))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether each row is a soft-wrapped continuation of the one before, i.e. that one wrapped
+/// at its last column rather than ending with a newline
+pub fn continued_from_lines(lines: &[avt::Line]) -> Array1<bool> {
+    let mut unwrapper = TextUnwrapper::new();
+    let mut continued = false;
+    lines
+        .iter()
+        .map(|l| {
+            let this = continued;
+            // nothing comes out while the line goes on in the next row
+            continued = unwrapper.push(l).is_none();
+            this
+        })
+        .collect()
+}
+
+
+/// The text in human reading order: lines that wrapped joined, box-drawing and decoration
+/// dropped, whitespace collapsed, and lines between blank ones joined into paragraphs
+#[cfg(feature = "python")]
+pub fn reading_text_from_lines(lines: &[avt::Line]) -> Vec<String> {
+    let mut unwrapper = TextUnwrapper::new();
+    let mut logical: Vec<String> = lines.iter().filter_map(|l| unwrapper.push(l)).collect();
+    logical.extend(unwrapper.flush());
+
+    let mut paragraphs = vec![];
+    let mut paragraph: Vec<String> = vec![];
+    for line in logical.iter().map(|l| reading_line(l)) {
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(paragraph.join(" "));
+                paragraph.clear();
+            }
+        } else {
+            paragraph.push(line);
+        }
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph.join(" "));
+    }
+    paragraphs
+}
+
+
+#[cfg(feature = "python")]
+pub fn diff_lines(a: &[avt::Line], b: &[avt::Line]) -> Array2<bool> {
+    let rows = a.len();
+    let cols = width(a);
+
+    let v: Vec<_> = a.iter().zip(b.iter())
+        .flat_map(|(la, lb)| la.cells().iter().zip(lb.cells().iter()).map(|(ca, cb)| ca != cb))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// A 64-bit FNV-1a hash of the shape and of each cell's char, colors (as RGB, None for the defaults) and
+/// `ATTR_*` bits. Unlike `std::hash` it is the same across runs and builds, so it can be stored and compared.
+pub fn hash_from_lines(lines: &[avt::Line], extended: &[avt::Line], palette: &Palette) -> u64 {
+    let mut hash = FNV_OFFSET;
+    let mut feed = |bytes: &[u8]| {
+        for b in bytes {
+            hash = (hash ^ u64::from(*b)).wrapping_mul(FNV_PRIME);
+        }
+    };
+    let color = |c: Option<avt::Color>| match c.map(|c| truecolor_from_avt(c, palette)) {
+        Some(c) => [1, c.r, c.g, c.b],
+        None => [0; 4],
+    };
+
+    feed(&(lines.len() as u64).to_le_bytes());
+    feed(&(width(lines) as u64).to_le_bytes());
+    let cells = lines.iter().flat_map(|l| l.cells());
+    let ext = extended.iter().flat_map(|l| l.cells().iter().map(|c| *c.pen())).chain(std::iter::repeat(avt::Pen::default()));
+    for (cell, ext) in cells.zip(ext) {
+        let pen = cell.pen();
+        feed(&u32::from(cell.char()).to_le_bytes());
+        feed(&color(pen.foreground()));
+        feed(&color(pen.background()));
+        feed(&cell_attrs(pen, &ext).to_le_bytes());
+    }
+    hash
+}
+
+
+/// The text within `bounds` (top, left, bottom, right, inclusive) as a terminal copies a selection of it: whole
+/// grapheme clusters, nothing for the cells covered by wide characters, trailing spaces dropped, and a newline
+/// after each row but those soft-wrapping into the next one. None if `bounds` isn't within the screen.
+#[cfg(feature = "python")]
+pub fn text_in_bounds(lines: &[avt::Line], (top, left, bottom, right): Bounds) -> Option<String> {
+    if top > bottom || left > right || bottom >= lines.len() || right >= width(lines) {
+        return None;
+    }
+    let graphemes = graphemes_from_lines(lines);
+    let widths = cell_widths_from_lines(lines);
+    let continued = continued_from_lines(lines);
+    let mut text = String::new();
+    for row in top..=bottom {
+        let line: String = (left..=right)
+            .filter(|&col| widths[[row, col]] != 0)
+            .map(|col| graphemes[row][col].as_str())
+            .collect();
+        if row < bottom && right + 1 == width(lines) && continued[row + 1] {
+            text.push_str(&line);
+        } else {
+            text.push_str(line.trim_end());
+            if row < bottom {
+                text.push('\n');
+            }
+        }
+    }
+    Some(text)
+}
+
+
+/// The cells within `bounds` (top, left, bottom, right, inclusive), row by row, clipped to the screen
+pub fn cells_in(lines: &[avt::Line], (top, left, bottom, right): Bounds) -> Vec<avt::Cell> {
+    lines
+        .iter()
+        .take(bottom + 1)
+        .skip(top)
+        .flat_map(|l| l.cells().iter().take(right + 1).skip(left).copied())
+        .collect()
+}
+
+
+#[cfg(feature = "python")]
+pub fn truecolor_from_lines<F>(lines: &[avt::Line], f: F, palette: &Palette) -> (Array3<u8>, Array2<bool>)
 where
     F: Fn(&avt::Pen) -> Option<avt::Color>,
 {
     let rows = lines.len();
-    let line0 = lines.get(0).unwrap();
-    let cols = line0.len();
+    let cols = width(lines);
 
     let cells = lines.iter().flat_map(|l|l.cells());
-    let colors = cells.map(|c| f(c.pen()).map(truecolor_from_avt));
+    let colors = cells.map(|c| f(c.pen()).map(|c| truecolor_from_avt(c, palette)));
     let vcolors: Vec<_> = colors.collect();
  
     let r = vcolors.iter().map(|c| c.as_ref().map(|cv| cv.r).unwrap_or(0));
@@ -66,16 +622,170 @@ where
     (m, mm)
 }
 
-pub fn indexedcolor_from_lines<F>(lines: &Vec<avt::Line>, f: F) -> (Array2<u8>, Array2<bool>)
+#[cfg(feature = "python")]
+pub type ColorPlanes = (Array3<u8>, Array2<bool>, Array3<u8>, Array2<bool>);
+
+/// Foreground and background truecolor matrices and masks, as `truecolor_from_lines` would
+/// return for each, in a single pass over the cells.
+#[cfg(feature = "python")]
+pub fn colors_from_lines<F, G>(lines: &[avt::Line], f: F, g: G, palette: &Palette) -> ColorPlanes
 where
     F: Fn(&avt::Pen) -> Option<avt::Color>,
+    G: Fn(&avt::Pen) -> Option<avt::Color>,
 {
     let rows = lines.len();
-    let line0 = lines.get(0).unwrap();
-    let cols = line0.len();
+    let cols = width(lines);
+    let n = rows * cols;
+
+    let mut fg = vec![0u8; 3 * n];
+    let mut bg = vec![0u8; 3 * n];
+    let mut fgm = Vec::with_capacity(n);
+    let mut bgm = Vec::with_capacity(n);
+
+    for (i, cell) in lines.iter().flat_map(|l| l.cells()).enumerate() {
+        let pen = cell.pen();
+        let planes = [
+            (f(pen), &mut fg, &mut fgm),
+            (g(pen), &mut bg, &mut bgm),
+        ];
+        for (color, m, mm) in planes {
+            if let Some(c) = color.map(|c| truecolor_from_avt(c, palette)) {
+                m[i] = c.r;
+                m[n + i] = c.g;
+                m[2 * n + i] = c.b;
+            }
+            mm.push(color.is_none());
+        }
+    }
+
+    (
+        Array3::from_shape_vec([3, rows, cols], fg).unwrap(),
+        Array2::from_shape_vec([rows, cols], fgm).unwrap(),
+        Array3::from_shape_vec([3, rows, cols], bg).unwrap(),
+        Array2::from_shape_vec([rows, cols], bgm).unwrap(),
+    )
+}
+
+/// A _rows_ x _cols_ x 3 matrix of colors as RGB channels normalized to [0, 1], `default` where
+/// there is no color
+#[cfg(feature = "python")]
+pub fn float_colors_from_lines<F>(lines: &[avt::Line], f: F, default: avt::Color, palette: &Palette) -> Array3<f32>
+where
+    F: Fn(&avt::Pen) -> Option<avt::Color>,
+{
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let v: Vec<_> = lines.iter()
+        .flat_map(|l| l.cells())
+        .flat_map(|c| {
+            let c = truecolor_from_avt(f(c.pen()).unwrap_or(default), palette);
+            [c.r, c.g, c.b].map(|v| f32::from(v) / 255.0)
+        })
+        .collect();
+
+    Array3::from_shape_vec([rows, cols, 3], v).unwrap()
+}
+
+
+/// A cell in `screen_from_lines`, laid out as the numpy structured dtype
+/// `[('char', '<u4'), ('fg', 'u1', 3), ('bg', 'u1', 3), ('fg_mask', '?'), ('bg_mask', '?'), ('attrs', '<u2')]`
+#[cfg(feature = "python")]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScreenCell {
+    pub char: u32,
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+    pub fg_mask: bool,
+    pub bg_mask: bool,
+    pub attrs: u16,
+}
+
+#[cfg(feature = "python")]
+unsafe impl Element for ScreenCell {
+    const IS_COPY: bool = true;
+
+    fn get_dtype(py: Python<'_>) -> Bound<'_, PyArrayDescr> {
+        let fields = [
+            ("char", "<u4", offset_of!(ScreenCell, char)),
+            ("fg", "3u1", offset_of!(ScreenCell, fg)),
+            ("bg", "3u1", offset_of!(ScreenCell, bg)),
+            ("fg_mask", "?", offset_of!(ScreenCell, fg_mask)),
+            ("bg_mask", "?", offset_of!(ScreenCell, bg_mask)),
+            ("attrs", "<u2", offset_of!(ScreenCell, attrs)),
+        ];
+        let spec = PyDict::new(py);
+        spec.set_item("names", fields.map(|f| f.0)).unwrap();
+        spec.set_item("formats", fields.map(|f| f.1)).unwrap();
+        spec.set_item("offsets", fields.map(|f| f.2)).unwrap();
+        spec.set_item("itemsize", size_of::<ScreenCell>()).unwrap();
+        PyArrayDescr::new(py, &spec).unwrap()
+    }
+
+    fn clone_ref(&self, _py: Python<'_>) -> Self {
+        *self
+    }
+}
+
+/// Characters, foreground and background truecolors and masks (as `colors_from_lines`)
+/// and `ATTR_*` bits of each cell, in a single pass over the cells
+#[cfg(feature = "python")]
+pub fn screen_from_lines<F, G>(lines: &[avt::Line], extended: &[avt::Line], f: F, g: G, palette: &Palette) -> Array2<ScreenCell>
+where
+    F: Fn(&avt::Pen) -> Option<avt::Color>,
+    G: Fn(&avt::Pen) -> Option<avt::Color>,
+{
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let rgb = |c: Option<avt::Color>| c.map(|c| truecolor_from_avt(c, palette)).map(|c| [c.r, c.g, c.b]);
+    let v: Vec<_> = lines.iter()
+        .flat_map(|l| l.cells())
+        .zip(extended.iter().flat_map(|l| l.cells()))
+        .map(|(c, e)| {
+            let pen = c.pen();
+            let (fg, bg) = (rgb(f(pen)), rgb(g(pen)));
+            ScreenCell {
+                char: u32::from(c.char()),
+                fg: fg.unwrap_or_default(),
+                bg: bg.unwrap_or_default(),
+                fg_mask: fg.is_none(),
+                bg_mask: bg.is_none(),
+                attrs: cell_attrs(pen, e.pen()),
+            }
+        })
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+#[cfg(feature = "python")]
+pub fn rgb32_from_lines<F>(lines: &[avt::Line], f: F, palette: &Palette) -> Array2<u32>
+where
+    F: Fn(&avt::Pen) -> Option<avt::Color>,
+{
+    let rows = lines.len();
+    let cols = width(lines);
+
+    let v: Vec<_> = lines.iter()
+        .flat_map(|l|l.cells())
+        .map(|c| f(c.pen()).map(|c| rgb32_from_avt(c, palette)).unwrap_or(DEFAULT_RGB32))
+        .collect();
+
+    Array2::from_shape_vec([rows, cols], v).unwrap()
+}
+
+#[cfg(feature = "python")]
+pub fn indexedcolor_from_lines<F>(lines: &[avt::Line], f: F) -> (Array2<u8>, Array2<bool>)
+where
+    F: Fn(&avt::Pen) -> Option<avt::Color>,
+{
+    let rows = lines.len();
+    let cols = width(lines);
 
     let cells = lines.iter().flat_map(|l|l.cells());
-    let colors = cells.map(|c| f(c.pen()).map(indexedcolor_from_avt));
+    let colors = cells.map(|c| f(c.pen()).and_then(indexedcolor_from_avt));
     let vcolors: Vec<_> = colors.collect();
 
     let vm: Vec<_> = vcolors.iter().map(|c| c.unwrap_or(0)).collect();
@@ -88,17 +798,55 @@ where
 }
 
 
-pub fn render_lines(lines: &Vec<avt::Line>) -> String {
+/// The SGR parameter setting each attribute
+#[cfg(any(feature = "python", feature = "json"))]
+pub const ATTR_SGR: [(u16, u8); 8] = [
+    (ATTR_BOLD, 1),
+    (ATTR_FAINT, 2),
+    (ATTR_ITALIC, 3),
+    (ATTR_UNDERLINE, 4),
+    (ATTR_BLINK, 5),
+    (ATTR_INVERSE, 7),
+    (ATTR_HIDDEN, 8),
+    (ATTR_STRIKETHROUGH, 9),
+];
+
+/// The lines with the SGR sequences for their colors and attributes, each line reset at its end.
+/// Concealed text is rendered concealed too unless `reveal`.
+#[cfg(feature = "python")]
+pub fn render_lines(lines: &[avt::Line], extended: &[avt::Line], palette: &Palette, reveal: bool) -> String {
     let mut s = "".to_string();
-    for l in lines.iter() {
+    let shown = if reveal { !ATTR_HIDDEN } else { !0 };
+    for (row, l) in lines.iter().enumerate() {
         let mut foreground: Option<avt::Color> = None;
         let mut background: Option<avt::Color> = None;
-        for c in l.cells() {
+        let mut attrs = 0;
+        let ext = extended.get(row).map(|e| e.cells()).unwrap_or_default();
+        for (col, c) in l.cells().iter().enumerate() {
             let &p = c.pen();
+            let e = ext.get(col).map(|e| *e.pen()).unwrap_or_default();
+            let a = cell_attrs(&p, &e) & shown;
+            if a != attrs {
+                // attributes are turned off by resetting all, colors included, and setting those left again
+                let set = if attrs & !a != 0 {
+                    s.push_str("\x1b[0m");
+                    foreground = None;
+                    background = None;
+                    a
+                } else {
+                    a & !attrs
+                };
+                for (bit, sgr) in ATTR_SGR {
+                    if set & bit != 0 {
+                        s.push_str(&format!("\x1b[{}m", sgr));
+                    }
+                }
+                attrs = a;
+            }
             if p.foreground() != foreground {
                 let cc = p
                     .foreground()
-                    .map(style_fg)
+                    .map(|c| style(c, 38, palette))
                     .unwrap_or("\x1b[39m".to_string());
                 s.push_str(&cc);
                 foreground = p.foreground();
@@ -106,15 +854,15 @@ pub fn render_lines(lines: &Vec<avt::Line>) -> String {
             if p.background() != background {
                 let cc = p
                     .background()
-                    .map(style_bg)
+                    .map(|c| style(c, 48, palette))
                     .unwrap_or("\x1b[49m".to_string());
                 s.push_str(&cc);
                 background = p.background();
             }
-            s.push_str(&c.char().to_string());
+            s.push(c.char());
         }
         s.push_str("\x1b[0m");
-        s.push_str("\n")
+        s.push('\n')
     }
     s
 }
@@ -0,0 +1,201 @@
+// Counters across all the Terminals of the process, for monitoring services built on numpty: read with
+// `numpty.metrics()`, or scraped in the Prometheus text format from `numpty.serve_metrics()`.
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use std::collections::BTreeMap;
+#[cfg(feature = "python")]
+use std::fmt::Write as _;
+#[cfg(feature = "python")]
+use std::io::{self, BufRead, BufReader, Read, Write};
+#[cfg(feature = "python")]
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "python")]
+use std::thread;
+use std::time::Duration;
+
+// the most of a request read before answering, and after
+#[cfg(feature = "python")]
+const MAX_REQUEST_HEAD: u64 = 16 * 1024;
+
+// upper bounds of the settle latency buckets, in seconds
+const SETTLE_BUCKETS: [f64; 10] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+pub struct Metrics {
+    pub sessions: AtomicU64,
+    pub sessions_started: AtomicU64,
+    pub spawn_errors: AtomicU64,
+    pub output_bytes: AtomicU64,
+    pub restarts: AtomicU64,
+    pub failures: AtomicU64,
+    pub hangs: AtomicU64,
+    // settles by bucket, the last one for those beyond all bounds
+    settle_buckets: [AtomicU64; SETTLE_BUCKETS.len() + 1],
+    // total time settling in µs
+    settle_micros: AtomicU64,
+}
+
+pub static METRICS: Metrics = Metrics {
+    sessions: AtomicU64::new(0),
+    sessions_started: AtomicU64::new(0),
+    spawn_errors: AtomicU64::new(0),
+    output_bytes: AtomicU64::new(0),
+    restarts: AtomicU64::new(0),
+    failures: AtomicU64::new(0),
+    hangs: AtomicU64::new(0),
+    settle_buckets: [const { AtomicU64::new(0) }; SETTLE_BUCKETS.len() + 1],
+    settle_micros: AtomicU64::new(0),
+};
+
+/// Counts `n` more of a counter
+pub fn add(counter: &AtomicU64, n: u64) {
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+/// A running session, counted in `sessions` for as long as the term task keeps it
+pub struct Session;
+
+impl Session {
+    pub fn new() -> Self {
+        add(&METRICS.sessions, 1);
+        Session
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        METRICS.sessions.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Records how long a settle took
+    pub fn settled(&self, took: Duration) {
+        let seconds = took.as_secs_f64();
+        let bucket = SETTLE_BUCKETS.iter().position(|&le| seconds <= le).unwrap_or(SETTLE_BUCKETS.len());
+        add(&self.settle_buckets[bucket], 1);
+        add(&self.settle_micros, took.as_micros() as u64);
+    }
+
+    #[cfg(feature = "python")]
+    // (name, help, type, value) of each metric but the histogram
+    fn values(&self) -> [(&'static str, &'static str, &'static str, u64); 7] {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        [
+            ("sessions", "Terminals running", "gauge", get(&self.sessions)),
+            ("sessions_started_total", "Terminals started", "counter", get(&self.sessions_started)),
+            ("spawn_errors_total", "Commands that could not be run", "counter", get(&self.spawn_errors)),
+            ("output_bytes_total", "Bytes of output from the children", "counter", get(&self.output_bytes)),
+            ("restarts_total", "Children restarted after failing", "counter", get(&self.restarts)),
+            ("failures_total", "Children that failed", "counter", get(&self.failures)),
+            ("hangs_total", "Sessions the watchdog flagged as hung", "counter", get(&self.hangs)),
+        ]
+    }
+
+    #[cfg(feature = "python")]
+    // settles up to each bound, cumulative, and in all
+    fn settle_counts(&self) -> (Vec<u64>, u64) {
+        let mut total = 0;
+        let counts = self.settle_buckets.iter().map(|b| {
+            total += b.load(Ordering::Relaxed);
+            total
+        });
+        let mut counts: Vec<u64> = counts.collect();
+        let count = counts.pop().unwrap_or(0);
+        (counts, count)
+    }
+
+    #[cfg(feature = "python")]
+    fn settle_seconds(&self) -> f64 {
+        self.settle_micros.load(Ordering::Relaxed) as f64 / 1e6
+    }
+
+    #[cfg(feature = "python")]
+    /// The metrics in the Prometheus text exposition format, names prefixed with `numpty_`
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (name, help, kind, value) in self.values() {
+            _ = writeln!(text, "# HELP numpty_{} {}.\n# TYPE numpty_{} {}\nnumpty_{} {}", name, help, name, kind, name, value);
+        }
+        let (counts, count) = self.settle_counts();
+        _ = writeln!(text, "# HELP numpty_settle_seconds Time taken by settle().\n# TYPE numpty_settle_seconds histogram");
+        for (le, n) in SETTLE_BUCKETS.iter().zip(counts) {
+            _ = writeln!(text, "numpty_settle_seconds_bucket{{le=\"{}\"}} {}", le, n);
+        }
+        _ = writeln!(text, "numpty_settle_seconds_bucket{{le=\"+Inf\"}} {}", count);
+        _ = writeln!(text, "numpty_settle_seconds_sum {}", self.settle_seconds());
+        _ = writeln!(text, "numpty_settle_seconds_count {}", count);
+        text
+    }
+}
+
+/// Retrieves a dict with the metrics of all the Terminals of the process: `sessions` running, and the totals since
+/// the module was loaded of `sessions_started_total`, `spawn_errors_total`, `output_bytes_total`, `restarts_total`,
+/// `failures_total` and `hangs_total`, plus `settle_seconds_sum` and `settle_seconds_count` for `settle()` latencies.
+#[cfg(feature = "python")]
+#[pyfunction]
+pub fn metrics() -> BTreeMap<String, f64> {
+    let mut metrics: BTreeMap<String, f64> =
+        METRICS.values().iter().map(|&(name, _, _, value)| (name.to_string(), value as f64)).collect();
+    metrics.insert("settle_seconds_sum".to_string(), METRICS.settle_seconds());
+    metrics.insert("settle_seconds_count".to_string(), METRICS.settle_counts().1 as f64);
+    metrics
+}
+
+/// Retrieves the metrics in the Prometheus text exposition format, names prefixed with `numpty_`,
+/// with `settle()` latencies as the `numpty_settle_seconds` histogram.
+#[cfg(feature = "python")]
+#[pyfunction]
+pub fn metrics_text() -> String {
+    METRICS.text()
+}
+
+#[cfg(feature = "python")]
+// answers a scrape, whatever the method, with the metrics for /metrics and 404 for anything else
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    // the headers are read up to the blank line ending them: closing with input unread resets the connection,
+    // which can lose the response before the scraper reads it
+    let mut head = BufReader::new((&stream).take(MAX_REQUEST_HEAD));
+    let mut request = String::new();
+    head.read_line(&mut request)?;
+    let mut header = String::new();
+    while head.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+    let (status, body) = match path {
+        "/metrics" => ("200 OK", METRICS.text()),
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    // and whatever else comes, such as a body, until the scraper closes its end
+    stream.shutdown(Shutdown::Write)?;
+    io::copy(&mut (&stream).take(MAX_REQUEST_HEAD), &mut io::sink())?;
+    Ok(())
+}
+
+/// Serves the metrics over HTTP at `/metrics` on `host` and `port` (0 for any free port), for Prometheus to scrape,
+/// from a background thread for the rest of the process. Returns the port.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (port, *, host="127.0.0.1"))]
+pub fn serve_metrics(port: u16, host: &str) -> PyResult<u16> {
+    let listener = TcpListener::bind((host, port))?;
+    let port = listener.local_addr()?.port();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // each on its own thread, so a slow scraper doesn't hold up the others, and gives up after a while
+            _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            thread::spawn(move || respond(stream));
+        }
+    });
+    Ok(port)
+}
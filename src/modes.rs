@@ -0,0 +1,148 @@
+use crate::cursor::cursor_style;
+use crate::escapes::Event;
+use crate::keys::KeyEncoding;
+use std::collections::HashSet;
+
+pub const ALT_SCREEN: u16 = 47;
+pub const ALT_SCREEN_BUFFER: u16 = 1047;
+pub const SAVE_CURSOR_ALT_SCREEN_BUFFER: u16 = 1049;
+pub const REVERSE_VIDEO: u16 = 5;
+pub const CURSOR_VISIBLE: u16 = 25;
+pub const BRACKETED_PASTE: u16 = 2004;
+pub const SYNCHRONIZED_OUTPUT: u16 = 2026;
+
+/// Whether the event marks the end of a frame: showing the cursor again after drawing,
+/// or ending a synchronized update
+pub fn ends_frame(event: &Event) -> bool {
+    match event {
+        Event::Csi { prefix: Some('?'), intermediates, action, .. } if intermediates.is_empty() => {
+            let params = event.params();
+            match action {
+                'h' => params.contains(&CURSOR_VISIBLE),
+                'l' => params.contains(&SYNCHRONIZED_OUTPUT),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Whether the event shows (Some(true)) or hides (Some(false)) the cursor
+pub fn cursor_visibility(event: &Event) -> Option<bool> {
+    match event {
+        Event::Csi { prefix: Some('?'), intermediates, action: action @ ('h' | 'l'), .. } if intermediates.is_empty() => {
+            event.params().contains(&CURSOR_VISIBLE).then_some(*action == 'h')
+        }
+        _ => None,
+    }
+}
+
+/// Whether the event switches to the alternate screen (Some(true)) or back to the primary one (Some(false)),
+/// given the modes before it is applied
+pub fn switches_screen(event: &Event, modes: &Modes) -> Option<bool> {
+    match event {
+        Event::Csi { prefix: Some('?'), intermediates, action: action @ ('h' | 'l'), .. } if intermediates.is_empty() => {
+            let params = event.params();
+            let alt_screen = [ALT_SCREEN, ALT_SCREEN_BUFFER, SAVE_CURSOR_ALT_SCREEN_BUFFER];
+            if !alt_screen.iter().any(|mode| params.contains(mode)) {
+                return None;
+            }
+            let entering = *action == 'h';
+            (entering != modes.alt_screen()).then_some(entering)
+        }
+        _ => None,
+    }
+}
+
+/// DEC private modes the child has set, as observed on its output
+#[derive(Default, Clone)]
+pub struct Modes {
+    set: HashSet<u16>,
+    // xterm modifyOtherKeys level, CSI > 4 ; level m
+    modify_other_keys: u16,
+    // kitty keyboard protocol flags stack, CSI > flags u / CSI < n u / CSI = flags ; mode u
+    key_flags: Vec<u16>,
+    // DECSCUSR, CSI Ps SP q
+    cursor_style: u16,
+}
+
+impl Modes {
+    pub fn apply(&mut self, event: &Event) {
+        if let Some(style) = cursor_style(event) {
+            self.cursor_style = style;
+        }
+        match event {
+            Event::Csi { prefix: Some('?'), intermediates, action: 'h', .. } if intermediates.is_empty() => {
+                self.set.extend(event.params());
+            }
+            Event::Csi { prefix: Some('?'), intermediates, action: 'l', .. } if intermediates.is_empty() => {
+                for mode in event.params() {
+                    self.set.remove(&mode);
+                }
+            }
+            Event::Csi { prefix: Some('>'), intermediates, action: 'm', .. } if intermediates.is_empty() => {
+                let params = event.params();
+                if params.first() == Some(&4) {
+                    self.modify_other_keys = params.get(1).copied().unwrap_or(0);
+                }
+            }
+            Event::Csi { prefix: Some('>'), intermediates, action: 'u', .. } if intermediates.is_empty() => {
+                self.key_flags.push(event.params().first().copied().unwrap_or(0));
+            }
+            Event::Csi { prefix: Some('<'), intermediates, action: 'u', .. } if intermediates.is_empty() => {
+                let n = event.params().first().copied().unwrap_or(1).max(1);
+                let len = self.key_flags.len().saturating_sub(usize::from(n));
+                self.key_flags.truncate(len);
+            }
+            Event::Csi { prefix: Some('='), intermediates, action: 'u', .. } if intermediates.is_empty() => {
+                let params = event.params();
+                let flags = params.first().copied().unwrap_or(0);
+                let current = self.key_flags.last().copied().unwrap_or(0);
+                let flags = match params.get(1).copied().unwrap_or(1) {
+                    2 => current | flags,
+                    3 => current & !flags,
+                    _ => flags,
+                };
+                match self.key_flags.last_mut() {
+                    Some(top) => *top = flags,
+                    None => self.key_flags.push(flags),
+                }
+            }
+            Event::Esc { intermediates, action: 'c' } if intermediates.is_empty() => {
+                // RIS, full reset
+                *self = Modes::default();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_set(&self, mode: u16) -> bool {
+        self.set.contains(&mode)
+    }
+
+    /// All the modes set, in order
+    pub fn set_modes(&self) -> Vec<u16> {
+        let mut modes: Vec<_> = self.set.iter().copied().collect();
+        modes.sort_unstable();
+        modes
+    }
+
+    pub fn key_encoding(&self) -> KeyEncoding {
+        if self.key_flags.last().is_some_and(|&f| f != 0) {
+            KeyEncoding::CsiU
+        } else if self.modify_other_keys > 0 {
+            KeyEncoding::ModifyOtherKeys(self.modify_other_keys)
+        } else {
+            KeyEncoding::Legacy
+        }
+    }
+
+    /// The cursor style set with DECSCUSR, 0 for the default
+    pub fn cursor_style(&self) -> u16 {
+        self.cursor_style
+    }
+
+    pub fn alt_screen(&self) -> bool {
+        self.is_set(ALT_SCREEN) || self.is_set(ALT_SCREEN_BUFFER) || self.is_set(SAVE_CURSOR_ALT_SCREEN_BUFFER)
+    }
+}
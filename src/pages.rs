@@ -0,0 +1,66 @@
+// Pagers like less show a long text a screen at a time; the screens captured while paging through it are
+// stitched into a single snapshot as tall as the text.
+
+use crate::snapshot::Snapshot;
+
+// how many rows at the top of `page` repeat the rows at the bottom of `lines`, the most that do
+fn overlap(lines: &[avt::Line], page: &[avt::Line]) -> usize {
+    (1..=lines.len().min(page.len()))
+        .rev()
+        .find(|&n| lines[lines.len() - n..] == page[..n])
+        .unwrap_or(0)
+}
+
+/// `page` without its last `status_rows` (a pager's prompt) appended to `tall`. On the `last` page the rows at
+/// its top that repeat the rows at the bottom of `tall` are left out, as a pager scrolls less than a screen there;
+/// on the others a row repeating at the boundary is part of the text, such as a run of blank lines.
+/// The cursor, links and the rest are those of `page`, the cursor moved down with it.
+pub fn stitch(tall: Option<Snapshot>, page: Snapshot, status_rows: usize, last: bool) -> Snapshot {
+    let rows = page.lines.len().saturating_sub(status_rows);
+    let Some(mut tall) = tall else {
+        let mut page = page;
+        page.lines.truncate(rows);
+        page.extended.truncate(rows);
+        page.line_sizes.truncate(rows);
+        return page;
+    };
+    let skip = if last { overlap(&tall.lines, &page.lines[..rows]) } else { 0 };
+    let offset = tall.lines.len() - skip;
+    tall.lines.extend_from_slice(&page.lines[skip..rows]);
+    tall.extended.extend(page.extended.into_iter().take(rows).skip(skip));
+    tall.line_sizes.extend(page.line_sizes.into_iter().take(rows).skip(skip));
+    tall.links.extend(page.links);
+    tall.cursor = page.cursor;
+    tall.cursor.row += offset;
+    tall.alt_screen = page.alt_screen;
+    tall.timestamp = page.timestamp;
+    tall.palette = page.palette;
+    tall
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::Screen;
+
+    // a screen of the rows of `text` from `top`, with a pager's prompt below them
+    fn page(text: &[&str], top: usize, rows: usize) -> Snapshot {
+        let mut screen = Screen::new(10, rows + 1);
+        for line in &text[top..top + rows] {
+            screen.feed_str(&format!("{}\r\n", line));
+        }
+        screen.feed_str(":");
+        screen.snapshot()
+    }
+
+    #[test]
+    fn keeps_blank_lines_across_page_breaks() {
+        let text = ["a", "b", "", "", "", "", "c", "d", "e", ""];
+        // the last page scrolls less than a screen, repeating "c" and "d"
+        let pages = [page(&text, 0, 4), page(&text, 4, 4), page(&text, 6, 4)];
+        let last = pages.len() - 1;
+        let tall = pages.into_iter().enumerate().fold(None, |tall, (i, page)| Some(stitch(tall, page, 1, i == last)));
+        let lines: Vec<_> = tall.unwrap().lines.iter().map(|l| l.text().trim_end().to_string()).collect();
+        assert_eq!(lines, text);
+    }
+}
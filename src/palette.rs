@@ -0,0 +1,267 @@
+use crate::color::{DEFAULT_BG_INDEX, DEFAULT_FG_INDEX, PALETTE};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use rgb::RGB8;
+
+/// The palette of a Terminal, from Python: the name of a preset, or colors replacing the first indexed ones
+/// as `(r, g, b)` sequences (e.g. a 256 x 3 matrix) or color specs such as `"#1d2021"` or `"rgb:ff/00/00"`
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+#[derive(Clone, Debug)]
+pub enum PaletteArg {
+    Preset(String),
+    Specs(Vec<String>),
+    Rgb(Vec<[u8; 3]>),
+}
+
+#[cfg(feature = "python")]
+impl PaletteArg {
+    pub fn palette(&self) -> Result<Palette, String> {
+        let colors: Vec<RGB8> = match self {
+            PaletteArg::Preset(name) => {
+                return Palette::preset(name).ok_or_else(|| {
+                    let names: Vec<_> = PRESETS.iter().map(|p| p.name).collect();
+                    format!("unknown palette {:?}, expected one of {}", name, names.join(", "))
+                })
+            }
+            PaletteArg::Specs(specs) => specs
+                .iter()
+                .map(|spec| parse_spec(spec).ok_or_else(|| format!("invalid color {:?}", spec)))
+                .collect::<Result<_, _>>()?,
+            PaletteArg::Rgb(colors) => colors.iter().map(|&[r, g, b]| RGB8 { r, g, b }).collect(),
+        };
+        if colors.len() > 256 {
+            return Err("palette has more than 256 colors".to_string());
+        }
+        Ok(Palette::configured(&colors, None, None))
+    }
+}
+
+// a color scheme as terminals ship it: the 16 base colors and the default foreground and background,
+// None to leave them as palette colors 7 and 0
+struct Preset {
+    name: &'static str,
+    colors: [&'static str; 16],
+    foreground: Option<&'static str>,
+    background: Option<&'static str>,
+}
+
+static PRESETS: &[Preset] = &[
+    Preset {
+        name: "xterm",
+        colors: [
+            "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+            "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+        ],
+        foreground: None,
+        background: None,
+    },
+    Preset {
+        name: "solarized-dark",
+        colors: [
+            "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+            "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+        ],
+        foreground: Some("#839496"),
+        background: Some("#002b36"),
+    },
+    Preset {
+        name: "solarized-light",
+        colors: [
+            "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+            "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+        ],
+        foreground: Some("#657b83"),
+        background: Some("#fdf6e3"),
+    },
+    Preset {
+        name: "dracula",
+        colors: [
+            "#21222c", "#ff5555", "#50fa7b", "#f1fa8c", "#bd93f9", "#ff79c6", "#8be9fd", "#f8f8f2",
+            "#6272a4", "#ff6e6e", "#69ff94", "#ffffa5", "#d6acff", "#ff92df", "#a4ffff", "#ffffff",
+        ],
+        foreground: Some("#f8f8f2"),
+        background: Some("#282a36"),
+    },
+    Preset {
+        name: "gruvbox",
+        colors: [
+            "#282828", "#cc241d", "#98971a", "#d79921", "#458588", "#b16286", "#689d6a", "#a89984",
+            "#928374", "#fb4934", "#b8bb26", "#fabd2f", "#83a598", "#d3869b", "#8ec07c", "#ebdbb2",
+        ],
+        foreground: Some("#ebdbb2"),
+        background: Some("#282828"),
+    },
+];
+
+/// The colors as the child sees them: the configured palette, updated as the child redefines
+/// indexed colors with OSC 4 and the default foreground and background with OSC 10 and 11
+/// (and resets them with OSC 104, 110 and 111)
+#[derive(Clone)]
+pub struct Palette {
+    pub colors: [RGB8; 256],
+    /// Default foreground, None for palette color 7
+    pub foreground: Option<RGB8>,
+    /// Default background, None for palette color 0
+    pub background: Option<RGB8>,
+    configured: [RGB8; 256],
+    configured_foreground: Option<RGB8>,
+    configured_background: Option<RGB8>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: PALETTE,
+            foreground: None,
+            background: None,
+            configured: PALETTE,
+            configured_foreground: None,
+            configured_background: None,
+        }
+    }
+}
+
+// X11 color spec as in xterm: rgb:r/g/b with 1 to 4 hex digits per component, or #rgb, #rrggbb etc.
+fn parse_spec(spec: &str) -> Option<RGB8> {
+    fn component(hex: &str) -> Option<u8> {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1u32 << (4 * hex.len())) - 1;
+        Some((value * 255 / max) as u8)
+    }
+
+    let components: Vec<&str> = match spec.strip_prefix("rgb:") {
+        Some(rgb) => rgb.split('/').collect(),
+        None => {
+            let hex = spec.strip_prefix('#')?;
+            let n = hex.len() / 3;
+            if n == 0 || hex.len() % 3 != 0 || !hex.is_ascii() {
+                return None;
+            }
+            vec![&hex[..n], &hex[n..2 * n], &hex[2 * n..]]
+        }
+    };
+
+    match components.as_slice() {
+        [r, g, b] => Some(RGB8 {
+            r: component(r)?,
+            g: component(g)?,
+            b: component(b)?,
+        }),
+        _ => None,
+    }
+}
+
+fn format_spec(color: RGB8) -> String {
+    // 16 bits per component, as xterm does
+    let c = |v: u8| u16::from(v) * 257;
+    format!("rgb:{:04x}/{:04x}/{:04x}", c(color.r), c(color.g), c(color.b))
+}
+
+impl Palette {
+    /// The inbuilt palette with its first colors replaced by `colors`, and the default foreground
+    /// and background if given
+    pub fn configured(colors: &[RGB8], foreground: Option<RGB8>, background: Option<RGB8>) -> Self {
+        let mut palette = Palette::default();
+        palette.configured[..colors.len()].copy_from_slice(colors);
+        palette.configured_foreground = foreground;
+        palette.configured_background = background;
+        palette.reset();
+        palette
+    }
+
+    /// The inbuilt palette with a preset color scheme, e.g. "solarized-dark"
+    pub fn preset(name: &str) -> Option<Self> {
+        let preset = PRESETS.iter().find(|p| p.name == name)?;
+        let colors: Vec<RGB8> = preset.colors.iter().filter_map(|c| parse_spec(c)).collect();
+        let foreground = preset.foreground.and_then(parse_spec);
+        let background = preset.background.and_then(parse_spec);
+        Some(Palette::configured(&colors, foreground, background))
+    }
+
+    /// Apply an OSC sequence (without the leading `ESC ]` and the terminator),
+    /// returning the reply to any queries in it
+    pub fn apply(&mut self, osc: &str) -> Option<String> {
+        let (command, rest) = osc.split_once(';').unwrap_or((osc, ""));
+        match command {
+            "4" => {
+                let mut replies = String::new();
+                let mut args = rest.split(';');
+                while let (Some(index), Some(spec)) = (args.next(), args.next()) {
+                    let Ok(index) = index.parse::<u8>() else {
+                        continue;
+                    };
+                    if spec == "?" {
+                        let color = format_spec(self.colors[usize::from(index)]);
+                        replies.push_str(&format!("\x1b]4;{};{}\x1b\\", index, color));
+                    } else if let Some(color) = parse_spec(spec) {
+                        self.colors[usize::from(index)] = color;
+                    }
+                }
+                (!replies.is_empty()).then_some(replies)
+            }
+            "104" => {
+                if rest.is_empty() {
+                    self.colors = self.configured;
+                }
+                for index in rest.split(';').filter_map(|i| i.parse::<u8>().ok()) {
+                    self.colors[usize::from(index)] = self.configured[usize::from(index)];
+                }
+                None
+            }
+            "10" | "11" => {
+                let foreground = command == "10";
+                let (current, default) = match foreground {
+                    true => (
+                        &mut self.foreground,
+                        self.configured_foreground.unwrap_or(self.configured[usize::from(DEFAULT_FG_INDEX)]),
+                    ),
+                    false => (
+                        &mut self.background,
+                        self.configured_background.unwrap_or(self.configured[usize::from(DEFAULT_BG_INDEX)]),
+                    ),
+                };
+                if rest == "?" {
+                    let color = format_spec(current.unwrap_or(default));
+                    return Some(format!("\x1b]{};{}\x1b\\", command, color));
+                }
+                if let Some(color) = parse_spec(rest) {
+                    *current = Some(color);
+                }
+                None
+            }
+            "110" => {
+                self.foreground = self.configured_foreground;
+                None
+            }
+            "111" => {
+                self.background = self.configured_background;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the child has redefined the indexed color
+    pub fn redefined(&self, index: u8) -> bool {
+        self.colors[usize::from(index)] != self.configured[usize::from(index)]
+    }
+
+    /// Whether the child has redefined the default foreground (`foreground`) or background
+    pub fn redefined_default(&self, foreground: bool) -> bool {
+        match foreground {
+            true => self.foreground != self.configured_foreground,
+            false => self.background != self.configured_background,
+        }
+    }
+
+    /// Back to the configured palette, e.g. for a new child
+    pub fn reset(&mut self) {
+        self.colors = self.configured;
+        self.foreground = self.configured_foreground;
+        self.background = self.configured_background;
+    }
+}
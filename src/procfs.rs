@@ -0,0 +1,138 @@
+use nix::libc;
+use nix::unistd::Pid;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(target_arch = "x86_64")]
+const POLL_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_poll,
+    libc::SYS_select,
+    libc::SYS_epoll_wait,
+    libc::SYS_pselect6,
+    libc::SYS_ppoll,
+    libc::SYS_epoll_pwait,
+];
+
+#[cfg(not(target_arch = "x86_64"))]
+const POLL_SYSCALLS: &[libc::c_long] = &[libc::SYS_pselect6, libc::SYS_ppoll, libc::SYS_epoll_pwait];
+
+const READ_SYSCALLS: &[libc::c_long] = &[libc::SYS_read, libc::SYS_readv];
+
+// used when /proc/<pid>/syscall can't be read
+const INPUT_WCHANS: &[&str] = &["n_tty_read", "wait_woken", "do_select", "do_sys_poll", "ep_poll"];
+
+/// Process state letter from /proc/<pid>/stat, e.g. 'R', 'S', 'Z'
+pub fn state(pid: Pid) -> Option<char> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // comm is in parentheses and may contain anything, state follows the last ')'
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.trim_start().chars().next()
+}
+
+/// Ids from /proc/<pid>/stat
+#[cfg(feature = "python")]
+pub struct Stat {
+    pub pgrp: i32,
+    pub session: i32,
+    /// Device number of the controlling terminal, 0 if none
+    pub tty_nr: u64,
+    /// Foreground process group of the controlling terminal
+    pub tpgid: i32,
+}
+
+#[cfg(feature = "python")]
+pub fn stat(pid: Pid) -> Option<Stat> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, rest) = stat.rsplit_once(')')?;
+    // state ppid pgrp session tty_nr tpgid
+    let fields: Vec<&str> = rest.split_whitespace().take(6).collect();
+    let [_, _, pgrp, session, tty_nr, tpgid] = fields.as_slice() else {
+        return None;
+    };
+    Some(Stat {
+        pgrp: pgrp.parse().ok()?,
+        session: session.parse().ok()?,
+        tty_nr: tty_nr.parse::<i64>().ok()? as u64,
+        tpgid: tpgid.parse().ok()?,
+    })
+}
+
+/// Command name of the process, from /proc/<pid>/comm
+#[cfg(feature = "python")]
+pub fn comm(pid: Pid) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+/// The open file descriptors of the process, from /proc/<pid>/fd, in order
+#[cfg(feature = "python")]
+pub fn open_fds(pid: Pid) -> Option<Vec<i32>> {
+    let mut fds: Vec<i32> = fs::read_dir(format!("/proc/{}/fd", pid))
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    fds.sort_unstable();
+    Some(fds)
+}
+
+fn is_terminal_fd(pid: Pid, fd: u64) -> bool {
+    match fs::read_link(format!("/proc/{}/fd/{}", pid, fd)) {
+        Ok(path) => {
+            let path = path.to_string_lossy();
+            path.starts_with("/dev/pts/") || path.starts_with("/dev/tty")
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether the process is sleeping in a read on a terminal, or waiting on poll/select
+/// (as curses programs do), a strong hint that it is waiting for input.
+pub fn blocked_on_input(pid: Pid) -> bool {
+    if state(pid) != Some('S') {
+        return false;
+    }
+
+    match fs::read_to_string(format!("/proc/{}/syscall", pid)) {
+        Ok(syscall) => {
+            let mut fields = syscall.split_whitespace();
+            let Some(Ok(nr)) = fields.next().map(|n| n.parse::<libc::c_long>()) else {
+                return false;
+            };
+            if READ_SYSCALLS.contains(&nr) {
+                let fd = fields
+                    .next()
+                    .and_then(|a| u64::from_str_radix(a.trim_start_matches("0x"), 16).ok());
+                fd.is_some_and(|fd| is_terminal_fd(pid, fd))
+            } else {
+                POLL_SYSCALLS.contains(&nr)
+            }
+        }
+        Err(_) => match fs::read_to_string(format!("/proc/{}/wchan", pid)) {
+            Ok(wchan) => INPUT_WCHANS.contains(&wchan.trim()),
+            Err(_) => false,
+        },
+    }
+}
+
+/// The core file written since `since` by a child run in `dir`, as named by kernel.core_pattern:
+/// the newest file in its directory starting like it. None if cores are piped to a handler
+/// (e.g. systemd-coredump or apport).
+pub fn core_file(dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    let pattern = fs::read_to_string("/proc/sys/kernel/core_pattern").ok()?;
+    let pattern = pattern.trim();
+    if pattern.is_empty() || pattern.starts_with('|') {
+        return None;
+    }
+    let pattern = dir.join(pattern);
+    let prefix = pattern.file_name()?.to_string_lossy();
+    let prefix = prefix.split('%').next().unwrap_or_default();
+    fs::read_dir(pattern.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(modified, _)| *modified >= since)
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
@@ -1,13 +1,176 @@
+use crate::cursor::Cursor;
+use crate::exit::ExitStatus;
+use crate::geometry::Bounds;
 use futures::channel::oneshot;
+use nix::unistd::Pid;
+use regex::Regex;
+use std::collections::BTreeMap;
 use tokio::time::Duration;
 
-pub struct Reply {
+/// The screen as captured for a snapshot
+#[derive(Clone)]
+pub struct Capture {
     pub lines: Vec<avt::Line>,
+    /// The extended attributes of the same cells, see `Extended`
+    pub extended: Vec<avt::Line>,
+    pub cursor: Cursor,
+    /// The DEC line size of each row, see `Extended::line_sizes`
+    pub line_sizes: Vec<u8>,
+    /// The URI of each hyperlink id in the extended lines
+    pub links: BTreeMap<u16, String>,
+    /// Whether it is of the alternate screen
+    pub alt_screen: bool,
+}
+
+/// Which screen a request captures
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Buffer {
+    /// Whichever the child is on
+    #[default]
+    Active,
+    Primary,
+    Alternate,
+}
+
+impl Buffer {
+    /// From the `screen` argument of the Terminal methods
+    #[cfg(feature = "python")]
+    pub fn parse(screen: Option<&str>) -> Result<Self, String> {
+        match screen {
+            None => Ok(Buffer::Active),
+            Some("primary") => Ok(Buffer::Primary),
+            Some("alternate") => Ok(Buffer::Alternate),
+            Some(screen) => Err(format!("unknown screen: {}, expected \"primary\" or \"alternate\"", screen)),
+        }
+    }
+}
+
+pub struct Reply {
+    pub capture: Capture,
+    /// Whether the condition waited for was reached, always the case when settling
+    pub reached: bool,
     pub error: Option<String>,
 }
 
-pub struct Req {
-    pub wait_first: Duration,
-    pub wait_more: Duration,
+/// What a request waits for before it is replied to
+pub enum Until {
+    /// No output for `Waits::more` after some output
+    Settled,
+    /// Like `Settled`, but only output changing the cells within the (top, left, bottom, right) bounds counts,
+    /// `cells` being their contents as of the last change, filled in as the request arrives
+    SettledIn { bounds: Bounds, cells: Vec<avt::Cell> },
+    /// Like `Settled`, but only once the cursor has been shown again after being hidden, from `Redraw::Pending`
+    Redrawn(Redraw),
+    /// The child being (or not being) on the alternate screen
+    AltScreen(bool),
+    /// The child having printed something other than blanks, or switched to the alternate screen
+    Ready,
+    /// The process blocking on a read of its input, checked by polling
+    BlockedOnInput(Pid),
+    /// The text appearing anywhere on the screen
+    Text(String),
+    /// The screen differing from the given lines, or from the screen as the request arrives if None
+    Changed(Option<Vec<avt::Line>>),
+    /// The text before the cursor ending with any of the prompts
+    Prompt(Vec<String>),
+    /// The regex matching the text output since the request arrived, escape sequences left out,
+    /// and in the last `lookback` bytes of output before, which `seen` is filled in with as the request arrives
+    Output { regex: Regex, lookback: usize, seen: String },
+    /// The regex matching the window title
+    Title(Regex),
+    /// The child exiting for good, not to be restarted
+    Exited,
+}
+
+impl Until {
+    /// Whether it is for the screen to settle
+    pub fn settles(&self) -> bool {
+        matches!(self, Until::Settled | Until::SettledIn { .. } | Until::Redrawn(_))
+    }
+
+    /// Whether it is reached once the output ends, the child having exited for good
+    pub fn reached_at_exit(&self) -> bool {
+        self.settles() || matches!(self, Until::Exited)
+    }
+}
+
+/// How far the child is through a redraw, as seen by `Until::Redrawn`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Redraw {
+    /// The cursor hasn't been hidden since the request
+    Pending,
+    /// The cursor is hidden, the child drawing
+    Hidden,
+    /// The cursor has been shown again after being hidden
+    Done,
+}
+
+impl Redraw {
+    /// After the child shows or hides the cursor
+    pub fn cursor(self, shown: bool) -> Self {
+        match (self, shown) {
+            (_, false) => Redraw::Hidden,
+            (Redraw::Pending, true) => Redraw::Pending,
+            (_, true) => Redraw::Done,
+        }
+    }
+}
+
+/// How long a request waits, None meaning no limit of that kind
+#[derive(Clone, Copy, Default)]
+pub struct Waits {
+    /// Give up if there is no output at all for this long
+    pub first: Option<Duration>,
+    /// Give up, or when settling be settled, after this long without further output
+    pub more: Option<Duration>,
+    /// Give up after this long regardless
+    pub max: Option<Duration>,
+}
+
+impl Waits {
+    /// From milliseconds
+    pub fn from_ms(first: Option<u64>, more: Option<u64>, max: Option<u64>) -> Self {
+        Waits {
+            first: first.map(Duration::from_millis),
+            more: more.map(Duration::from_millis),
+            max: max.map(Duration::from_millis),
+        }
+    }
+}
+
+/// A request waiting for a condition, replied to with the screen once it is met or it gives up
+pub struct Wait {
+    pub until: Until,
+    pub waits: Waits,
+    /// Reply with the screen as of the last frame boundary, if any
+    pub at_frame: bool,
+    pub buffer: Buffer,
     pub reply: oneshot::Sender<Reply>,
 }
+
+/// What the Terminal asks the term task
+pub enum Req {
+    /// Wait for a condition, cancelling any previous wait
+    Wait(Wait),
+    /// The screen right away
+    Snapshot(Buffer, oneshot::Sender<Capture>),
+    /// The lines scrolled off the top of the primary screen, oldest first
+    History(oneshot::Sender<Vec<avt::Line>>),
+    /// The DEC private modes currently set
+    Modes(oneshot::Sender<Vec<u16>>),
+    /// Up to the given number of bytes of the most recent raw output
+    RecentOutput(usize, oneshot::Sender<Vec<u8>>),
+    /// Resize the screen, the pty is resized by the Terminal
+    Resize {
+        cols: usize,
+        rows: usize,
+        reply: oneshot::Sender<()>,
+    },
+}
+
+/// What the pty task sends the term task
+pub enum Output {
+    Data(Vec<u8>),
+    /// The child exited, and is about to be restarted if `restarting`
+    Exited { status: ExitStatus, restarting: bool },
+}
@@ -1,24 +1,34 @@
+use crate::activity::Activity;
+use crate::ctty;
+use crate::exit::ExitStatus;
 use crate::nbio;
+use crate::procfs;
+use crate::sandbox;
+use crate::protocol::Output;
 use anyhow::Result;
 use futures::channel::oneshot;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
 use nix::libc;
 use nix::pty;
 use nix::pty::Winsize;
 use std::convert::Infallible;
+use nix::sys::resource::{self, Resource};
 use nix::sys::signal::{self, SigHandler, Signal};
-use nix::sys::wait;
+use nix::sys::wait::{self, WaitStatus};
 use nix::unistd::{self, ForkResult, Pid};
 use std::io::pipe;
 use std::io::Write;
-use nix::unistd::close;
 use std::env;
 use std::ffi::{CString, NulError};
-use std::fs::File;
-use std::future::Future;
-use std::io::{PipeReader, PipeWriter};
+use std::fs::{File, OpenOptions};
 use std::io::Read;
 use std::os::fd::FromRawFd;
-use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::mem::ManuallyDrop;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use std::{error::Error, fmt};
 use tokio::io::unix::AsyncFd;
 use tokio::sync::mpsc;
@@ -26,7 +36,26 @@ use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
 pub struct ExecError {
-    message: String
+    pub message: String,
+    /// The error number of the failure in the child, if it had one
+    pub errno: Option<i32>,
+}
+
+impl ExecError {
+    // from what the child writes to the error pipe: the error number, if any, and a newline before the message
+    fn parse(report: &str) -> Self {
+        let (errno, message) = report.split_once('\n').unwrap_or(("", report));
+        ExecError { message: message.to_string(), errno: errno.parse().ok() }
+    }
+
+    fn report(e: &anyhow::Error) -> String {
+        let errno = match (e.downcast_ref::<nix::errno::Errno>(), e.downcast_ref::<std::io::Error>()) {
+            (Some(errno), _) => Some(*errno as i32),
+            (None, Some(e)) => e.raw_os_error(),
+            _ => None,
+        };
+        format!("{}\n{}", errno.map(|n| n.to_string()).unwrap_or_default(), e)
+    }
 }
 
 impl Error for ExecError {}
@@ -37,14 +66,73 @@ impl fmt::Display for ExecError {
     }
 }
 
+/// What `run_pty` launches
+#[derive(Clone)]
+pub struct Launch {
+    pub command: Vec<String>,
+    pub cols: usize,
+    pub rows: usize,
+    /// How many times to restart the child when it fails
+    pub restarts: usize,
+    /// Directory to run the child in with core dumps enabled, if any
+    pub core_dir: Option<PathBuf>,
+    /// File descriptors the child inherits besides stdin, stdout and stderr
+    pub keep_fds: Vec<RawFd>,
+    /// Throwaway HOME to give the child, if any
+    pub home: Option<PathBuf>,
+}
+
+/// The child currently running in the pty
+#[derive(Debug)]
+pub struct Started {
+    pub child: Pid,
+    /// A duplicate of the pty master, for ioctls on the terminal
+    pub master: OwnedFd,
+}
+
+/// Shared with the Terminal, updated by `run_pty` every time the child is (re)started
+pub type CurrentChild = Arc<Mutex<Option<Started>>>;
+
+fn winsize(cols: usize, rows: usize) -> Winsize {
+    Winsize {
+        ws_col: u16::try_from(cols).unwrap(),
+        ws_row: u16::try_from(rows).unwrap(),
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+/// Resize the terminal, the kernel signals SIGWINCH to its foreground process group
+pub fn set_winsize(master: &OwnedFd, cols: usize, rows: usize) -> Result<()> {
+    let winsize = winsize(cols, rows);
+    if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+fn get_winsize(master: &OwnedFd) -> Option<Winsize> {
+    let mut winsize = winsize(0, 0);
+    if unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) } < 0 {
+        return None;
+    }
+    Some(winsize)
+}
 
-fn spawn(
-    command: Vec<String>,
-    winsize: &pty::Winsize,
-    input_rx: mpsc::Receiver<Vec<u8>>,
-    output_tx: mpsc::Sender<Vec<u8>>,
-    token: CancellationToken
-) -> Result<impl Future<Output = Result<()>>> {
+/// Bytes written to the pty that the child hasn't read yet, from the slave side since the master doesn't tell.
+/// In canonical mode only complete lines count, an unfinished one can't be read yet.
+pub fn unread_input(master: &OwnedFd) -> Result<usize> {
+    let path = ctty::pty_path(master).ok_or_else(std::io::Error::last_os_error)?;
+    // only open for the ioctl, the slave being held open would keep the master from seeing the child hang up
+    let slave = OpenOptions::new().read(true).custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK).open(path)?;
+    let mut n: libc::c_int = 0;
+    if unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCINQ, &mut n) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(n as usize)
+}
+
+fn spawn(launch: &Launch, winsize: &pty::Winsize) -> Result<(Pid, OwnedFd)> {
 
     let (pipe_in, pipe_out) = pipe()?;
 
@@ -52,30 +140,30 @@ fn spawn(
 
     match result.fork_result {
         ForkResult::Parent { child } => {
-            let mut reader = PipeReader::from(pipe_in);
+            let mut reader = pipe_in;
             let mut s: String = "".to_string();
-            close(pipe_out.as_raw_fd()).unwrap();
+            drop(pipe_out);
             let res = reader.read_to_string(&mut s);
             match res {
                 Ok(_) => {
                     if s.is_empty() {
-                        Ok(drive_child(child, result.master, input_rx, output_tx, token))
+                        Ok((child, result.master))
                     } else {
-                        Err(ExecError{message: s}.into())
+                        Err(ExecError::parse(&s).into())
                     }
                 },
                 Err(e) => {
-                    Err(ExecError{message: e.to_string()}.into())
+                    Err(ExecError{message: e.to_string(), errno: e.raw_os_error()}.into())
                 }
             }
         },
 
         ForkResult::Child => {
-            close(pipe_in.as_raw_fd()).unwrap();
-            match exec(command) {
+            drop(pipe_in);
+            match exec(launch) {
                 Err(e) => {
-                    let mut writer = PipeWriter::from(pipe_out);
-                    writer.write(e.to_string().as_bytes()).unwrap();
+                    let mut writer = pipe_out;
+                    writer.write_all(ExecError::report(&e).as_bytes()).unwrap();
                     unsafe { libc::_exit(1) }
                 }
                 Ok(_) => {
@@ -89,39 +177,50 @@ fn spawn(
 async fn drive_child(
     child: Pid,
     master: OwnedFd,
-    input_rx: mpsc::Receiver<Vec<u8>>,
-    output_tx: mpsc::Sender<Vec<u8>>,
-    token: CancellationToken
-) -> Result<()> {
-    let result = do_drive_child(master, input_rx, output_tx, token).await;
+    input_rx: &mut mpsc::Receiver<Vec<u8>>,
+    output_tx: &mpsc::Sender<Output>,
+    activity: &Activity,
+    token: &CancellationToken
+) -> Result<WaitStatus> {
+    let result = do_drive_child(master, input_rx, output_tx, activity, token).await;
+    // what was taken for this child and not written is gone with it
+    activity.unwritten(0);
     unsafe { libc::kill(child.as_raw(), libc::SIGHUP) };
 
-    tokio::task::spawn_blocking(move || {
-        let _ = wait::waitpid(child, None);
+    let status = tokio::task::spawn_blocking(move || {
+        wait::waitpid(child, None)
     }).await.unwrap();
-    result
+    result?;
+    Ok(status?)
 }
 
 const READ_BUF_SIZE: usize = 128 * 1024;
 
+// input taken from the channel but not written to the child yet, no more is taken beyond this
+// so a child that stops reading fills the channel and writers see it
+const MAX_PENDING_INPUT: usize = 64 * 1024;
+
 async fn do_drive_child(
     master: OwnedFd,
-    mut input_rx: mpsc::Receiver<Vec<u8>>,
-    output_tx: mpsc::Sender<Vec<u8>>,
-    token: CancellationToken
+    input_rx: &mut mpsc::Receiver<Vec<u8>>,
+    output_tx: &mpsc::Sender<Output>,
+    activity: &Activity,
+    token: &CancellationToken
 ) -> Result<()> {
     let mut buf = [0u8; READ_BUF_SIZE];
     let mut input: Vec<u8> = Vec::with_capacity(READ_BUF_SIZE);
     nbio::set_non_blocking(&master.as_raw_fd())?;
-    let mut master_file = unsafe { File::from_raw_fd(master.as_raw_fd()) };
+    // owned by master_fd, must not be closed twice
+    let mut master_file = ManuallyDrop::new(unsafe { File::from_raw_fd(master.as_raw_fd()) });
     let master_fd = AsyncFd::new(master)?;
 
     loop {
         tokio::select! {
-            result = input_rx.recv() => {
+            result = input_rx.recv(), if input.len() < MAX_PENDING_INPUT => {
                 match result {
                     Some(data) => {
                         input.extend_from_slice(&data);
+                        activity.taken(data.len(), input.len());
                     }
 
                     None => {
@@ -134,13 +233,14 @@ async fn do_drive_child(
                 let mut guard = result?;
 
                 loop {
-                    match nbio::read(&mut master_file, &mut buf)? {
+                    match nbio::read(&mut *master_file, &mut buf)? {
                         Some(0) => {
                             return Ok(());
                         }
 
                         Some(n) => {
-                            output_tx.send(buf[0..n].to_vec()).await?;
+                            activity.output(n);
+                            output_tx.send(Output::Data(buf[0..n].to_vec())).await?;
                         }
 
                         None => {
@@ -156,12 +256,13 @@ async fn do_drive_child(
                 let mut buf: &[u8] = input.as_ref();
 
                 loop {
-                    match nbio::write(&mut master_file, buf)? {
+                    match nbio::write(&mut *master_file, buf)? {
                         Some(0) => {
                             return Ok(());
                         }
 
                         Some(n) => {
+                            activity.input();
                             buf = &buf[n..];
 
                             if buf.is_empty() {
@@ -183,6 +284,7 @@ async fn do_drive_child(
                 } else {
                     input.drain(..input.len() - left);
                 }
+                activity.unwritten(input.len());
             }
 
             _ = token.cancelled() => {
@@ -194,41 +296,108 @@ async fn do_drive_child(
     Ok(())
 }
 
-fn exec(command: Vec<String>) -> Result<Infallible> {
-    let command = command.iter()
+// Marks every fd above stderr close-on-exec but those to keep, which are made inheritable,
+// so runtime fds (the tokio driver, Python's) don't leak into the child and keep pipes alive
+fn restrict_fds(keep_fds: &[RawFd]) -> Result<()> {
+    let mut keep: Vec<RawFd> = keep_fds.iter().copied().filter(|&fd| fd > 2).collect();
+    keep.sort_unstable();
+    keep.dedup();
+    let mut first = 3;
+    for &fd in keep.iter().chain([RawFd::MAX].iter()) {
+        if fd > first {
+            cloexec_range(first, fd - 1);
+        }
+        first = fd.saturating_add(1);
+    }
+    for fd in keep {
+        let flags = fcntl(fd, FcntlArg::F_GETFD)?;
+        let flags = FdFlag::from_bits_truncate(flags) - FdFlag::FD_CLOEXEC;
+        fcntl(fd, FcntlArg::F_SETFD(flags))?;
+    }
+    Ok(())
+}
+
+fn cloexec_range(first: RawFd, last: RawFd) {
+    if unsafe { libc::close_range(first as libc::c_uint, last as libc::c_uint, libc::CLOSE_RANGE_CLOEXEC as libc::c_int) } == 0 {
+        return;
+    }
+    // kernels before 5.11
+    let max = match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+        n if n > 0 => n.min(RawFd::MAX as libc::c_long) as RawFd,
+        _ => 1024,
+    };
+    for fd in first..=last.min(max) {
+        let _ = fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC));
+    }
+}
+
+fn exec(launch: &Launch) -> Result<Infallible> {
+    restrict_fds(&launch.keep_fds)?;
+    if let Some(core_dir) = &launch.core_dir {
+        resource::setrlimit(Resource::RLIMIT_CORE, resource::RLIM_INFINITY, resource::RLIM_INFINITY)?;
+        unistd::chdir(core_dir)?;
+    }
+    let command = launch.command.iter()
     .map(|s| CString::new(s.as_bytes()))
     .collect::<Result<Vec<CString>, NulError>>()?;
     env::set_var("TERM", "xterm-256color");
+    if let Some(home) = &launch.home {
+        sandbox::set_env(home);
+    }
     unsafe { signal::signal(Signal::SIGPIPE, SigHandler::SigDfl) }?;
     Ok(unistd::execvp(&command[0], &command)?)
 }
 
 
 pub async fn run_pty(
-    command: Vec<String>,
-    cols: usize,
-    rows: usize,
-    input_rx: mpsc::Receiver<Vec<u8>>,
-    output_tx: mpsc::Sender<Vec<u8>>,
+    launch: Launch,
+    mut input_rx: mpsc::Receiver<Vec<u8>>,
+    output_tx: mpsc::Sender<Output>,
+    activity: Arc<Activity>,
+    current: CurrentChild,
     start_tx: oneshot::Sender<Result<()>>,
     token: CancellationToken,
 ) -> Result<()> {
-    let winsize = Winsize {
-        ws_col: u16::try_from(cols).unwrap(),
-        ws_row: u16::try_from(rows).unwrap(),
-        ws_xpixel: 0,
-        ws_ypixel: 0,
-    };
+    let mut winsize = winsize(launch.cols, launch.rows);
+    let mut start_tx = Some(start_tx);
+    let mut restarts = launch.restarts;
 
-    let outcome = spawn(command, &winsize, input_rx, output_tx, token);
-    match outcome {
-        Ok(f) => {
+    loop {
+        let started = SystemTime::now();
+        let (child, master) = match spawn(&launch, &winsize) {
+            Ok(spawned) => spawned,
+            Err(e) => match start_tx.take() {
+                Some(start_tx) => {
+                    start_tx.send(Err(e)).unwrap();
+                    return Ok(());
+                }
+                // failed to restart
+                None => return Err(e),
+            },
+        };
+        *current.lock().unwrap() = Some(Started {
+            child,
+            master: master.try_clone()?,
+        });
+        if let Some(start_tx) = start_tx.take() {
             start_tx.send(Ok(())).unwrap();
-            tokio::spawn(f).await?
         }
-        Err(e) => {
-            start_tx.send(Err(e)).unwrap();
-            Ok(())
+
+        let status = drive_child(child, master, &mut input_rx, &output_tx, &activity, &token).await?;
+        let mut status = ExitStatus::from(status);
+        if let (true, Some(core_dir)) = (status.core_dumped, &launch.core_dir) {
+            status.core_file = procfs::core_file(core_dir, started).map(|p| p.to_string_lossy().into_owned());
+        }
+        let restarting = status.failed() && restarts > 0 && !token.is_cancelled();
+        // ignore failure, the term task may be gone already
+        _ = output_tx.send(Output::Exited { status, restarting }).await;
+        if !restarting {
+            return Ok(());
+        }
+        restarts -= 1;
+        // restart at the size it was resized to, if any
+        if let Some(size) = current.lock().unwrap().as_ref().and_then(|s| get_winsize(&s.master)) {
+            winsize = size;
         }
     }
-}
\ No newline at end of file
+}
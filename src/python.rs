@@ -0,0 +1,1833 @@
+// The Python module: `Terminal` over a `Session`, converting what it captures to numpy arrays and its
+// errors to Python exceptions, and the classes and functions of the rest of the crate.
+
+use crate::boxes::boxes_from_lines;
+use crate::cell::Cell;
+use crate::clipboard::SELECTIONS;
+use crate::color::{rgb32_from_avt, ColorArgs};
+use crate::geometry::Bounds;
+use crate::lines::any_attrs;
+use crate::lines::attr_from_lines;
+use crate::lines::attrs_from_lines;
+use crate::lines::cell_widths_from_lines;
+use crate::lines::chars_from_lines;
+use crate::lines::chars_str_from_lines;
+use crate::lines::colors_from_lines;
+use crate::lines::continued_from_lines;
+use crate::lines::find_in_lines;
+use crate::lines::float_colors_from_lines;
+use crate::lines::graphemes_from_lines;
+use crate::lines::indexedcolor_from_lines;
+use crate::lines::kinds_from_lines;
+use crate::lines::links_from_lines;
+use crate::lines::nfc_chars_from_lines;
+use crate::lines::render_lines;
+use crate::lines::screen_from_lines;
+use crate::lines::rgb32_from_lines;
+use crate::lines::rows_from_lines;
+use crate::lines::text_from_lines;
+use crate::lines::text_in_bounds;
+use crate::lines::text_from_lines_with;
+use crate::lines::TextOptions;
+use crate::lines::{ATTR_BLINK, ATTR_HIDDEN};
+use crate::lines::truecolor_from_lines;
+use crate::lines::underline_style_from_lines;
+use crate::lines::ScreenCell;
+use crate::lines::CHARS_STR_LEN;
+use crate::protocol::{Buffer, Capture, Redraw, Reply, Req, Until, Waits};
+use crate::modes::{ALT_SCREEN, ALT_SCREEN_BUFFER, SAVE_CURSOR_ALT_SCREEN_BUFFER};
+use crate::pages::stitch;
+use crate::palette::PaletteArg;
+use crate::pty::ExecError;
+#[cfg(feature = "recording")]
+use crate::recording::Recording;
+use crate::spawn::{spawn_error, SpawnError};
+use crate::regions::label_regions;
+use crate::reports::ScriptedReport;
+use crate::screen::Screen;
+use crate::search::{search_lines, Match};
+use crate::snapshot::{Snapshot, SnapshotSeries};
+use crate::table::table_from_lines;
+#[cfg(feature = "export")]
+use crate::export::{write_png, Rasterizer};
+#[cfg(feature = "export")]
+use crate::theme::Theme;
+
+use crate::ages::CellAges;
+use crate::archive::Archive;
+use crate::exit::ExitStatus;
+use crate::ctty::ControllingTerminal;
+use crate::cursor::Cursor;
+use crate::deadline::Deadline;
+use crate::diff::DiffStats;
+use crate::encoding::Codec;
+use crate::health::Health;
+use crate::ignore::Ignore;
+use crate::labels::Labels;
+use ndarray::{Array, Array2, Dimension};
+use numpy::{PyArray, PyArray1, PyArray2, PyArray3, PyFixedUnicode};
+
+use futures::channel::oneshot;
+use nix::unistd::tcgetpgrp;
+use std::collections::BTreeMap;
+use std::os::fd::IntoRawFd;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::PathBuf;
+use std::sync::Arc;
+use pyo3::exceptions::{PyIndexError, PyOSError, PyTimeoutError, PyValueError};
+use pyo3::create_exception;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+use regex::Regex;
+use pyo3::PyAny;
+use rgb::RGB8;
+use tokio::time::Duration;
+use crate::session::{Config, Session, SessionError};
+use crate::watchdog::Callback;
+#[cfg(feature = "recording")]
+use crate::recording;
+use crate::{archive, color, extended, geometry, keys, lines, metrics, procfs, severity};
+
+create_exception!(
+    numpty,
+    ClosedError,
+    PyValueError,
+    "The Terminal has been closed with `close()`, so calls needing the session can't be made."
+);
+
+create_exception!(
+    numpty,
+    ProcessExited,
+    PyOSError,
+    "The child has exited for good, so the input can't be sent or the condition waited for can't come about: \
+     `exit_status` is how it exited."
+);
+
+// what `wait_for_prompt` looks for by default
+const DEFAULT_PROMPTS: &[&str] = &["$ ", "# ", "> ", "% "];
+
+type IndexedColorMatrices<'py> = (Bound<'py, PyArray2<u8>>, Bound<'py, PyArray2<bool>>);
+type Hyperlinks<'py> = (Bound<'py, PyArray2<u16>>, BTreeMap<u16, String>);
+type TrueColorMatrices<'py> = (Bound<'py, PyArray3<u8>>, Bound<'py, PyArray2<bool>>);
+type ColorMatrices<'py> = (
+    Bound<'py, PyArray3<u8>>,
+    Bound<'py, PyArray2<bool>>,
+    Bound<'py, PyArray3<u8>>,
+    Bound<'py, PyArray2<bool>>,
+);
+
+/// Color matrices as (matrix, mask) tuples, as numpy masked arrays with `masked=True`,
+/// or just the matrix when there is a `default` color to substitute
+#[derive(IntoPyObject)]
+pub enum MaybeMasked<'py, T> {
+    Plain(T),
+    Masked(Bound<'py, PyAny>),
+    Dense(Bound<'py, PyArray3<u8>>),
+}
+
+// numpy.ma.MaskedArray of `colors`, masked where `mask` is True (default colors);
+// a rows x cols mask is broadcast over the planes of 3 x rows x cols truecolor matrices
+fn masked_array<'py, D: Dimension>(
+    py: Python<'py>,
+    colors: Array<u8, D>,
+    mask: Array2<bool>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mask = mask.broadcast(colors.raw_dim()).unwrap().to_owned();
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("mask", PyArray::from_owned_array(py, mask))?;
+    py.import("numpy.ma")?
+        .getattr("MaskedArray")?
+        .call((PyArray::from_owned_array(py, colors),), Some(&kwargs))
+}
+
+// tells apart the files of `timeout_dump_dir`
+static TIMEOUT_DUMPS: AtomicUsize = AtomicUsize::new(0);
+
+// the waits of a call waiting for a condition: `timeout` ms cut short by `deadline`, one of which is needed
+fn waits(wait_first: Option<u64>, wait_more: Option<u64>, timeout: Option<u64>, deadline: Option<Deadline>) -> PyResult<Waits> {
+    let max = Deadline::limit(timeout.map(Duration::from_millis), deadline.as_ref())
+        .ok_or_else(|| PyValueError::new_err("a timeout or a deadline is needed"))?;
+    Ok(Waits { max: Some(max), ..Waits::from_ms(wait_first, wait_more, None) })
+}
+
+/// A child process running in a headless pseudo-terminal
+#[pyclass]
+pub struct Terminal {
+    session: Session,
+    health_bytes: u64,
+    snapshot: Option<Snapshot>,
+    // the one before, for `diff()`
+    previous: Option<Snapshot>,
+    ages: CellAges,
+    raise_on_timeout: bool,
+    timeout_dump_dir: Option<PathBuf>,
+}
+
+impl Terminal {
+    // replaces the snapshot, keeping track of when cells changed
+    fn set_snapshot(&mut self, capture: Capture) {
+        self.ages.update(self.snapshot.as_ref().map(|s| s.lines.as_slice()), &capture.lines);
+        self.previous = self.snapshot.replace(self.session.new_snapshot(capture));
+    }
+
+    fn attr<'py>(&self, py: Python<'py>, f: fn(&avt::Pen) -> bool) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.snapshot.as_ref()
+            .map(|s| attr_from_lines(&s.lines, f))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    // input as a string to bytes for the child, with newlines translated
+    fn input_bytes(&self, input: String, newline: &str) -> PyResult<Vec<u8>> {
+        let newline = keys::Newline::parse(newline)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown newline: {}", newline)))?;
+        self.session.input_bytes(&input, newline).map_err(|e| self.py_err(e))
+    }
+
+    // `ProcessExited` once the child has exited for good
+    fn check_exited(&self) -> PyResult<()> {
+        let Some(status) = self.session.exit_status() else {
+            return Ok(());
+        };
+        let err = ProcessExited::new_err(format!("the child has exited: {}", status.__repr__()));
+        Python::with_gil(|py| {
+            err.value(py).setattr("exit_status", status)?;
+            Err(self.session.observed.labels.tag(py, err))
+        })
+    }
+
+    // queues input for the child, waiting for room at most `timeout` ms
+    fn send_input(&self, data: Vec<u8>, timeout: Option<u64>) -> PyResult<()> {
+        self.check_exited()?;
+        let timeout = timeout.map(Duration::from_millis);
+        self.session.send(data, timeout).map_err(|e| self.py_err(e))
+    }
+
+    // send a request to the term task and wait for its reply
+    fn ask<T>(&self, req: impl FnOnce(oneshot::Sender<T>) -> Req) -> PyResult<T> {
+        self.session.ask(req).map_err(|e| self.py_err(e))
+    }
+
+    fn request(&self, until: Until, waits: Waits, at_frame: bool) -> PyResult<Reply> {
+        self.request_screen(until, waits, at_frame, Buffer::Active)
+    }
+
+    // the same, replying with the screen in `buffer`
+    fn request_screen(&self, until: Until, waits: Waits, at_frame: bool, buffer: Buffer) -> PyResult<Reply> {
+        self.session.request(until, waits, at_frame, buffer).map_err(|e| self.py_err(e))
+    }
+
+    // what a wait returns, or raises with `raise_on_timeout` if it timed out
+    // raises `ProcessExited` instead if it gave up because the child exited
+    fn reached(&self, reached: bool, wait: &str) -> PyResult<bool> {
+        if !reached {
+            self.check_exited()?;
+        }
+        if reached || !self.raise_on_timeout {
+            return Ok(reached);
+        }
+        let err = PyTimeoutError::new_err(format!("{} timed out", wait));
+        Python::with_gil(|py| {
+            self.attach_screen(py, &err);
+            Err(self.session.observed.labels.tag(py, err))
+        })
+    }
+
+    // sets the `screen` attribute of a timeout to the text on the screen now, also added as a note, and with
+    // `timeout_dump_dir` saves it to a file there, set as `screen_file`
+    fn attach_screen(&self, py: Python<'_>, err: &PyErr) {
+        let Ok(capture) = self.session.ask(|reply| Req::Snapshot(Buffer::Active, reply)) else {
+            return;
+        };
+        let options = TextOptions { strip_trailing: true, trim_blank_lines: true, ..TextOptions::default() };
+        let text = text_from_lines_with(&capture.lines, options);
+        let value = err.value(py);
+        let attach = || -> PyResult<()> {
+            value.setattr("screen", &text)?;
+            if let Some(dir) = &self.timeout_dump_dir {
+                let n = TIMEOUT_DUMPS.fetch_add(1, Ordering::Relaxed);
+                let path = dir.join(format!("numpty-timeout-{}-{}.txt", std::process::id(), n));
+                fs::write(&path, format!("{}\n", text))?;
+                value.setattr("screen_file", path)?;
+            }
+            if value.hasattr("add_note")? {
+                value.call_method1("add_note", (format!("screen at the timeout:\n{}", text),))?;
+            }
+            Ok(())
+        };
+        // the timeout is what matters, even without the screen, which is left noted
+        if let Err(e) = attach() {
+            if value.hasattr("add_note").unwrap_or(false) {
+                _ = value.call_method1("add_note", (format!("couldn't attach the screen: {}", e),));
+            }
+        }
+    }
+
+    // what calls needing the session raise before it starts, or once it is closed
+    fn not_started(&self) -> PyErr {
+        self.py_err(self.session.not_started().into())
+    }
+
+    // the Python exception for an error of the session, with the `name` and `labels` of this Terminal
+    fn py_err(&self, e: anyhow::Error) -> PyErr {
+        Python::with_gil(|py| {
+            let err = match (e.downcast_ref::<SessionError>(), e.downcast_ref::<ExecError>()) {
+                (Some(SessionError::Closed), _) => ClosedError::new_err("the Terminal is closed"),
+                (Some(SessionError::InputTimeout(_)), _) => {
+                    let err = PyTimeoutError::new_err(e.to_string());
+                    self.attach_screen(py, &err);
+                    err
+                }
+                (Some(_), _) => PyValueError::new_err(e.to_string()),
+                (_, Some(exec_error)) => spawn_error(py, &self.session.config.command[0], exec_error),
+                _ => PyOSError::new_err(e.to_string()),
+            };
+            self.session.observed.labels.tag(py, err)
+        })
+    }
+}
+
+#[pymethods]
+impl Terminal {
+    /// Create a Terminal with `cols` and `rows` to run `command`
+    /// The subprocess is not started until either `start` is called
+    /// or the runtime context is enter - if Terminal is used as a context manager.
+    /// If the subprocess fails (exits with a non-zero code or is terminated by a signal)
+    /// it is restarted up to `restarts` times, see `failures()`.
+    /// Colors the child redefines (OSC 4, 10 and 11) are honored when converting indexed colors to truecolor
+    /// and rendering, unless `fixed_palette` for deterministic output.
+    /// `palette` replaces the first indexed colors of the inbuilt palette (e.g. the 16 base colors of a theme):
+    /// `(r, g, b)` sequences such as a 256 x 3 matrix, or color specs such as `"#1d2021"`,
+    /// or it names a preset: `"xterm"`, `"solarized-dark"`, `"solarized-light"`, `"dracula"` or `"gruvbox"`.
+    /// With `measure_echo` the time from each input write to the output that follows is measured, see `echo_latencies()`.
+    /// With `core_dir` the child runs in that directory with core dumps enabled (unlimited `RLIMIT_CORE`),
+    /// the core file of a crash is reported in its `ExitStatus`.
+    /// The child inherits only stdin, stdout and stderr (the pty) and the file descriptors in `keep_fds`,
+    /// which are made inheritable, see `child_fds()`.
+    /// With `sandbox_home` the child gets a throwaway `HOME`, with `XDG_CONFIG_HOME`, `XDG_CACHE_HOME`, `XDG_DATA_HOME`,
+    /// `XDG_STATE_HOME` and `XDG_RUNTIME_DIR` inside it, removed once it stops, see `home`.
+    /// Input is encoded to, and output decoded from, `encoding`: any single- or double-byte encoding Python knows
+    /// (e.g. `"latin-1"`, `"cp1252"`, `"shift_jis"`) for legacy programs, or UTF-8 by default.
+    /// With `record` the session is recorded to that path in asciicast v2, with the command, geometry,
+    /// some of the environment and the numpty version in its header, see `Recording`.
+    /// Up to `scrollback` lines scrolled off the top of the primary screen are kept, see `history_chars()`.
+    /// With `raise_on_timeout` the waits raise `TimeoutError` when they time out instead of returning False.
+    /// Timeouts raised carry the text on the screen in their `screen` attribute (and in a note, shown in tracebacks
+    /// from Python 3.11), and with `timeout_dump_dir` it is also saved to a file there, its path in `screen_file`.
+    /// `name` and `labels` (a dict of strings) tell Terminals apart when many run at once: they show in the repr,
+    /// `health()`, recordings and log lines, and are set as attributes of the exceptions raised by the Terminal.
+    #[new]
+    #[pyo3(signature = (command, cols, rows, *, restarts=0, fixed_palette=false, measure_echo=false, core_dir=None, keep_fds=vec![], sandbox_home=false, encoding="utf-8", record=None, palette=None, scrollback=10000, raise_on_timeout=false, timeout_dump_dir=None, name=None, labels=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn py_new(
+        py: Python<'_>,
+        command: Vec<String>,
+        cols: usize,
+        rows: usize,
+        restarts: usize,
+        fixed_palette: bool,
+        measure_echo: bool,
+        core_dir: Option<PathBuf>,
+        keep_fds: Vec<i32>,
+        sandbox_home: bool,
+        encoding: &str,
+        record: Option<PathBuf>,
+        palette: Option<PaletteArg>,
+        scrollback: usize,
+        raise_on_timeout: bool,
+        timeout_dump_dir: Option<PathBuf>,
+        name: Option<String>,
+        labels: Option<BTreeMap<String, String>>,
+    ) -> PyResult<Self> {
+        let mut config = Config::new(command, cols, rows);
+        config.check().map_err(PyValueError::new_err)?;
+        config.codec = Codec::new(py, encoding)?.map(Arc::new);
+        if let Some(palette) = palette {
+            config.palette = palette.palette().map_err(PyValueError::new_err)?;
+        }
+        config.restarts = restarts;
+        config.fixed_palette = fixed_palette;
+        config.measure_echo = measure_echo;
+        config.core_dir = core_dir;
+        config.keep_fds = keep_fds;
+        config.sandbox_home = sandbox_home;
+        config.record = record;
+        config.scrollback = scrollback;
+        config.labels = Labels { name, labels: labels.unwrap_or_default() };
+        let session = Session::new(config).map_err(|e| match e.downcast::<SessionError>() {
+            Ok(e) => PyValueError::new_err(e.to_string()),
+            Err(e) => PyOSError::new_err(e.to_string()),
+        })?;
+
+        Ok(Terminal {
+            session,
+            health_bytes: 0,
+            snapshot: None,
+            previous: None,
+            ages: CellAges::default(),
+            raise_on_timeout,
+            timeout_dump_dir,
+        })
+    }
+
+    /// Start the subprocess by running the command specified creating the Terminal.
+    /// Raises `SpawnError` if the command can't be run, e.g. it isn't installed or isn't executable.
+    pub fn start(&mut self) -> PyResult<()> {
+        self.health_bytes = 0;
+        self.session.start().map_err(|e| self.py_err(e))
+    }
+
+    #[pyo3(name = "__enter__")]
+    pub fn enter<'a>(mut slf: PyRefMut<'a, Self>, _py: Python) -> PyResult<PyRefMut<'a, Self>> {
+        slf.start()?;
+        Ok(slf)
+    }
+
+    #[pyo3(name = "__exit__")]
+    pub fn exit(
+        &mut self,
+        _py: Python,
+        _exception_type: Py<PyAny>,
+        _exception_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> bool {
+        self.session.stop_quietly();
+        false
+    }
+
+    /// Wait for output to the Terminal to settle and then capture the terminal's context in a snapshot.
+    /// This happens using a simple heuristic.
+    /// First wait for at most `wait_first` ms for some output to arrive. If none arrives give up, not taking any snapshot.
+    /// If some output arrives then wait repeatedly until `wait_more` ms have passed without any additional output.
+    /// At that point the terminal is considered "settled" and a snapshot is taken replacing the previous one.
+    /// If `max_wait` is given settling gives up after that many ms even if output keeps coming,
+    /// and with `deadline` (a `Deadline`, as for the waits) by then at the latest.
+    /// With `at_frame_boundary` the snapshot is of the screen as of the last frame boundary, i.e. when the
+    /// cursor was last shown or a synchronized update last ended, so it never shows a half-drawn frame.
+    /// If there hasn't been any boundary the current screen is captured.
+    /// Settling is then also deferred while a synchronized update is in progress, for up to 1s.
+    /// With `cursor_redraw` the screen only counts as settled once the child has shown the cursor again after
+    /// hiding it, as curses programs do around each redraw, a stronger sign a full-screen program is done than
+    /// silence. It needs `max_wait`, for programs that keep the cursor hidden.
+    /// With `region`, a `(top, left, bottom, right)` rectangle (inclusive), only output changing cells within it
+    /// counts, so the screen settles even if other parts (e.g. a clock in the status bar) keep updating.
+    /// With `screen`, `"primary"` or `"alternate"`, the snapshot is of that screen even if the child is on the other:
+    /// the primary one as the child switched to the alternate one, or the alternate one as the child left it.
+    #[pyo3(signature = (wait_first, wait_more, *, max_wait=None, deadline=None, at_frame_boundary=false, cursor_redraw=false, region=None, screen=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn settle(
+        &mut self,
+        wait_first: u64,
+        wait_more: u64,
+        max_wait: Option<u64>,
+        deadline: Option<Deadline>,
+        at_frame_boundary: bool,
+        cursor_redraw: bool,
+        region: Option<Bounds>,
+        screen: Option<&str>,
+    ) -> PyResult<()> {
+        let buffer = Buffer::parse(screen).map_err(PyValueError::new_err)?;
+        let until = match region {
+            Some((top, left, bottom, right)) if top > bottom || left > right => {
+                return Err(PyValueError::new_err("invalid region"));
+            }
+            Some(_) if cursor_redraw => return Err(PyValueError::new_err("cursor_redraw can't be used with a region")),
+            Some(bounds) => Until::SettledIn { bounds, cells: vec![] },
+            None if cursor_redraw => Until::Redrawn(Redraw::Pending),
+            None => Until::Settled,
+        };
+        let max = Deadline::limit(max_wait.map(Duration::from_millis), deadline.as_ref());
+        if cursor_redraw && max.is_none() {
+            return Err(PyValueError::new_err("cursor_redraw needs max_wait or a deadline"));
+        }
+        let waits = Waits { max, ..Waits::from_ms(Some(wait_first), Some(wait_more), None) };
+        let reply = self.request_screen(until, waits, at_frame_boundary, buffer)?;
+        self.set_snapshot(reply.capture);
+        Ok(())
+    }
+
+    /// Capture a snapshot of the screen right away, without waiting for anything.
+    /// `screen` picks the primary or alternate screen like in `settle()`.
+    #[pyo3(signature = (*, screen=None))]
+    pub fn capture(&mut self, screen: Option<&str>) -> PyResult<()> {
+        let buffer = Buffer::parse(screen).map_err(PyValueError::new_err)?;
+        let capture = self.ask(|reply| Req::Snapshot(buffer, reply))?;
+        self.set_snapshot(capture);
+        Ok(())
+    }
+
+    /// Pages through the output shown by a full-screen pager such as `less`, starting from the screen as it is now:
+    /// sends `next_key` and settles (`wait_first` and `wait_more` as in `settle()`) until the last `status_rows`
+    /// rows, the pager's prompt, show the text `end_pattern` (or the whole screen with 0), the screen stops
+    /// changing, or `max_pages` screens were seen. Retrieves a `Snapshot` as tall as all the pages stitched together,
+    /// without their status rows and without the rows shown again when the last page scrolls less than a screen,
+    /// so `chars()`, `text()` and the other accessors of `Snapshot` work on the whole text. The snapshot of the
+    /// Terminal is the last page.
+    #[pyo3(signature = (next_key="Space", end_pattern="(END)", *, wait_first=1000, wait_more=100, status_rows=1, max_pages=1000))]
+    pub fn capture_all_pages(
+        &mut self,
+        next_key: &str,
+        end_pattern: &str,
+        wait_first: u64,
+        wait_more: u64,
+        status_rows: usize,
+        max_pages: usize,
+    ) -> PyResult<Snapshot> {
+        if max_pages == 0 {
+            return Err(PyValueError::new_err("max_pages must be at least 1"));
+        }
+        self.capture(None)?;
+        let mut tall = None;
+        for pages in 1.. {
+            let Some(page) = self.snapshot.clone() else {
+                break;
+            };
+            let status = &page.lines[page.lines.len().saturating_sub(status_rows)..];
+            let status = if status_rows == 0 { &page.lines[..] } else { status };
+            if text_from_lines(status).contains(end_pattern) {
+                return Ok(stitch(tall, page, status_rows, true));
+            }
+            if pages == max_pages {
+                return Ok(stitch(tall, page, status_rows, false));
+            }
+            self.keys(vec![next_key.to_string()], None)?;
+            let reply = self.request(Until::Settled, Waits::from_ms(Some(wait_first), Some(wait_more), None), false)?;
+            self.set_snapshot(reply.capture);
+            // the page is only known not to be the last once the next one differs
+            let last = self.snapshot.as_ref().is_some_and(|s| s.lines == page.lines);
+            tall = Some(stitch(tall, page, status_rows, last));
+            if last {
+                break;
+            }
+        }
+        tall.ok_or_else(|| PyValueError::new_err("no snapshot"))
+    }
+
+    /// Retrieves a sorted list of the DEC private modes (as in `CSI ? n h`) the child has currently set.
+    pub fn modes(&self) -> PyResult<Vec<u16>> {
+        self.session.modes().map_err(|e| self.py_err(e))
+    }
+
+    /// Whether the child is on the alternate screen right now.
+    #[getter]
+    pub fn alt_screen(&self) -> PyResult<bool> {
+        let modes = self.modes()?;
+        Ok([ALT_SCREEN, ALT_SCREEN_BUFFER, SAVE_CURSOR_ALT_SCREEN_BUFFER].iter().any(|m| modes.contains(m)))
+    }
+
+    /// Retrieves up to `n_bytes` (by default all) of the most recent raw output of the child as bytes,
+    /// escape sequences included, e.g. for diagnostics after a failure. Only the last 64 KiB are kept.
+    #[pyo3(signature = (n_bytes=None))]
+    pub fn last_output<'py>(&self, py: Python<'py>, n_bytes: Option<usize>) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.session.last_output(n_bytes.unwrap_or(usize::MAX)).map_err(|e| self.py_err(e))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Resize the terminal to `cols` and `rows`, the child gets a SIGWINCH.
+    /// The snapshot keeps the previous size until the next capture.
+    pub fn resize(&mut self, cols: usize, rows: usize) -> PyResult<()> {
+        self.session.resize(cols, rows).map_err(|e| self.py_err(e))
+    }
+
+    /// Whether the child has used synchronized output (DEC mode 2026), which makes
+    /// `settle(..., at_frame_boundary=True)` reliable.
+    #[getter]
+    pub fn uses_synchronized_output(&self) -> bool {
+        self.session.observed.synchronized_output.load(Ordering::Relaxed)
+    }
+
+    /// Wait for at most `timeout` ms for the child to switch to the alternate screen,
+    /// which is how full-screen programs signal they have actually started.
+    /// Like all the waits it can also give up early, if there is no output at all for `wait_first` ms
+    /// or no further output for `wait_more` ms.
+    /// Instead of, or besides, `timeout` they take a `deadline`, a `Deadline` shared by a sequence of calls
+    /// to give them one overall time budget, and give up by then at the latest.
+    /// Returns True if the child is on the alternate screen, False if it timed out.
+    /// The snapshot is not updated.
+    #[pyo3(signature = (timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn wait_for_alt_screen(
+        &mut self,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::AltScreen(true), waits, false)?;
+        self.reached(reply.reached, "wait_for_alt_screen")
+    }
+
+    /// Wait for at most `timeout` ms for the window title (see `title`) to match the regular expression `pattern`,
+    /// as programs often signal phase changes ("building...", "done") there. Returns True if it matched,
+    /// False if it timed out. The snapshot is not updated.
+    #[pyo3(signature = (pattern, timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn wait_for_title(
+        &mut self,
+        pattern: &str,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let regex = Regex::new(pattern).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::Title(regex), waits, false)?;
+        self.reached(reply.reached, "wait_for_title")
+    }
+
+    /// Retrieves a tuple with the number of times the child has rung the bell and a `f64` vector with when,
+    /// in seconds since the epoch like `time.time()` (for the last 10000). Both BEL and visual bells (reverse
+    /// video flashed on and off, DEC mode 5) count, or only visual ones with `visual=True`, audible with `visual=False`.
+    #[pyo3(signature = (*, visual=None))]
+    pub fn bells<'py>(&self, py: Python<'py>, visual: Option<bool>) -> (u64, Bound<'py, PyArray1<f64>>) {
+        let bells = self.session.observed.bells.lock().unwrap();
+        let count = match visual {
+            None => bells.audible + bells.visual,
+            Some(true) => bells.visual,
+            Some(false) => bells.audible,
+        };
+        let times: Vec<f64> = bells
+            .times
+            .iter()
+            .filter(|(_, v)| visual.is_none_or(|visual| *v == visual))
+            .map(|(t, _)| *t)
+            .collect();
+        (count, PyArray1::from_vec(py, times))
+    }
+
+    /// Retrieves what the child last copied to the clipboard with OSC 52, None if it hasn't, to test copy to
+    /// clipboard without a real one. `selection` is the OSC 52 selection: `"c"` (the clipboard), `"p"` (primary),
+    /// `"s"` (select, also where copies naming none go) or a cut buffer `"0"` to `"7"`.
+    #[pyo3(signature = (*, selection='c'))]
+    pub fn clipboard(&self, selection: char) -> Option<String> {
+        let clipboard = self.session.observed.clipboard.lock().unwrap();
+        clipboard.get(selection).map(|data| String::from_utf8_lossy(data).into_owned())
+    }
+
+    /// Puts `text` in the clipboard as if the user had copied it, for the child to paste: apps query it with OSC 52,
+    /// which is answered with what was copied last, by the child or with this. `selection` is as in `clipboard()`.
+    #[pyo3(signature = (text, *, selection='c'))]
+    pub fn set_clipboard(&self, text: &str, selection: char) -> PyResult<()> {
+        if !SELECTIONS.contains(selection) {
+            return Err(PyValueError::new_err(format!("unknown selection {:?}", selection)));
+        }
+        self.session.observed.clipboard.lock().unwrap().set(selection, text.as_bytes().to_vec());
+        Ok(())
+    }
+
+    /// Pastes `text` into the child as a terminal does, newlines sent as CR: as a bracketed paste, between `ESC[200~`
+    /// and `ESC[201~`, when the child has enabled it (mode 2004), as plain input otherwise. Blocks while the input
+    /// queue is full like `input()`, for at most `timeout` ms if given.
+    #[pyo3(signature = (text, *, timeout=None))]
+    pub fn paste(&mut self, text: &str, timeout: Option<u64>) -> PyResult<()> {
+        self.check_exited()?;
+        let timeout = timeout.map(Duration::from_millis);
+        self.session.paste(text, timeout).map_err(|e| self.py_err(e))
+    }
+
+    /// Copies the text of `region` of the snapshot (`(top, left, bottom, right)`, inclusive, by default the
+    /// whole screen) to the clipboard like `set_clipboard()`, as a terminal copies a selection: grapheme clusters
+    /// whole, wide characters once, trailing spaces dropped and soft-wrapped rows joined. Returns the text copied.
+    #[pyo3(signature = (region=None, *, selection='c'))]
+    pub fn copy_to_clipboard(&self, region: Option<Bounds>, selection: char) -> PyResult<Option<String>> {
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(None);
+        };
+        let (rows, cols) = snapshot.shape();
+        let region = region.unwrap_or((0, 0, rows.saturating_sub(1), cols.saturating_sub(1)));
+        let text = text_in_bounds(&snapshot.lines, region)
+            .ok_or_else(|| PyValueError::new_err("region out of bounds"))?;
+        self.set_clipboard(&text, selection)?;
+        Ok(Some(text))
+    }
+
+    /// Retrieves the window title as last set by the child (OSC 0 or OSC 2), None if it hasn't set one.
+    pub fn title(&self) -> Option<String> {
+        self.session.observed.titles.lock().unwrap().current.clone()
+    }
+
+    /// Retrieves a list of `(timestamp, title)` tuples with every change of the window title, oldest first,
+    /// timestamps in seconds since the epoch like `time.time()`. Programs often show their state (the current
+    /// file, progress) in the title. The last 10000 changes are kept.
+    pub fn title_history(&self) -> Vec<(f64, String)> {
+        self.session.observed.titles.lock().unwrap().history.iter().cloned().collect()
+    }
+
+    /// Wait for at most `timeout` ms for the child to be up: to have printed something other than blanks or
+    /// switched to the alternate screen, a barrier before interacting with it (`start()` only tells the command
+    /// was run). Returns right away if it already has, True if it is up, False if it timed out.
+    /// The snapshot is not updated.
+    #[pyo3(signature = (timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn wait_ready(
+        &mut self,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::Ready, waits, false)?;
+        self.reached(reply.reached, "wait_ready")
+    }
+
+    /// Wait for at most `timeout` ms for the child to switch back from the alternate screen to the primary one.
+    /// Returns True if the child is on the primary screen, False if it timed out.
+    /// The snapshot is not updated.
+    #[pyo3(signature = (timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn wait_for_primary_screen(
+        &mut self,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::AltScreen(false), waits, false)?;
+        self.reached(reply.reached, "wait_for_primary_screen")
+    }
+
+    /// Wait for at most `timeout` ms for the child to block reading its input and then capture a snapshot.
+    /// This is a stronger "waiting for me" signal than output silence, e.g. for prompts that print nothing.
+    /// The child's state is polled through /proc, it counts as blocked when sleeping in a read on the terminal
+    /// or in poll/select.
+    /// Returns True if the child blocked on input, False if it timed out, in which case a snapshot is still taken.
+    #[pyo3(signature = (timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn settle_until_reading(
+        &mut self,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let Some(child) = self.session.pid() else {
+            return Err(self.not_started());
+        };
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::BlockedOnInput(child), waits, false)?;
+        self.set_snapshot(reply.capture);
+        self.reached(reply.reached, "settle_until_reading")
+    }
+
+    /// Wait for at most `timeout` ms for `text` to appear anywhere on the screen and then capture a snapshot.
+    /// Returns True if it appeared, False if it timed out, in which case a snapshot is still taken.
+    #[pyo3(signature = (text, timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn wait_for_text(
+        &mut self,
+        text: String,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::Text(text), waits, false)?;
+        self.set_snapshot(reply.capture);
+        self.reached(reply.reached, "wait_for_text")
+    }
+
+    /// Wait for at most `timeout` ms for the output since the call, as a string of the text printed
+    /// (escape sequences and control characters other than newlines and tabs left out), to match
+    /// the regular expression `pattern`, and then capture a snapshot. Unlike `wait_for_text()` this sees
+    /// text that is overwritten or scrolls away before it can be captured.
+    /// With `lookback` the last that many bytes of output before the call are matched too (see `last_output()`).
+    /// Returns True if it matched, False if it timed out, in which case a snapshot is still taken.
+    #[pyo3(signature = (pattern, timeout=None, *, deadline=None, lookback=0, wait_first=None, wait_more=None))]
+    pub fn wait_for_output(
+        &mut self,
+        pattern: &str,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        lookback: usize,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let regex = Regex::new(pattern).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let until = Until::Output { regex, lookback, seen: String::new() };
+        let reply = self.request(until, waits, false)?;
+        self.set_snapshot(reply.capture);
+        self.reached(reply.reached, "wait_for_output")
+    }
+
+    /// Wait for at most `timeout` ms for the screen to differ from the snapshot
+    /// (or from the screen as the call is made if there is no snapshot yet) and then capture a snapshot.
+    /// Returns True if it changed, False if it timed out, in which case a snapshot is still taken.
+    #[pyo3(signature = (timeout=None, *, deadline=None, wait_first=None, wait_more=None))]
+    pub fn wait_for_change(
+        &mut self,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let baseline = self.snapshot.as_ref().map(|s| s.lines.clone());
+        let reply = self.request(Until::Changed(baseline), waits, false)?;
+        self.set_snapshot(reply.capture);
+        self.reached(reply.reached, "wait_for_change")
+    }
+
+    /// Wait for at most `timeout` ms for a prompt, i.e. the text before the cursor ending with any of `prompts`
+    /// (by default `"$ "`, `"# "`, `"> "` or `"% "`), and then capture a snapshot.
+    /// Returns True if there was a prompt, False if it timed out, in which case a snapshot is still taken.
+    #[pyo3(signature = (timeout=None, *, deadline=None, prompts=None, wait_first=None, wait_more=None))]
+    pub fn wait_for_prompt(
+        &mut self,
+        timeout: Option<u64>,
+        deadline: Option<Deadline>,
+        prompts: Option<Vec<String>>,
+        wait_first: Option<u64>,
+        wait_more: Option<u64>,
+    ) -> PyResult<bool> {
+        let prompts = prompts.unwrap_or_else(|| DEFAULT_PROMPTS.iter().map(|p| p.to_string()).collect());
+        let waits = waits(wait_first, wait_more, timeout, deadline)?;
+        let reply = self.request(Until::Prompt(prompts), waits, false)?;
+        self.set_snapshot(reply.capture);
+        self.reached(reply.reached, "wait_for_prompt")
+    }
+
+    /// Process id of the child, None if not started.
+    #[getter]
+    pub fn pid(&self) -> Option<i32> {
+        self.session.pid().map(|c| c.as_raw())
+    }
+
+    /// The throwaway HOME of the child with `sandbox_home`, None if not started or not sandboxed.
+    /// It is removed once the child stops for good.
+    #[getter]
+    pub fn home(&self) -> Option<PathBuf> {
+        self.session.home().map(|h| h.to_path_buf())
+    }
+
+    /// The `name` the Terminal was created with, None if none.
+    #[getter]
+    pub fn name(&self) -> Option<String> {
+        self.session.observed.labels.name.clone()
+    }
+
+    /// The `labels` the Terminal was created with.
+    #[getter]
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        self.session.observed.labels.labels.clone()
+    }
+
+    pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let state = match (&self.session.token, self.pid()) {
+            _ if self.session.is_closed() => "closed".to_string(),
+            (None, _) => "not started".to_string(),
+            _ if !self.session.is_running() => "stopped".to_string(),
+            (_, Some(pid)) => format!("pid={}", pid),
+            (_, None) => "no child".to_string(),
+        };
+        Ok(format!(
+            "Terminal({}, command={}, cols={}, rows={}, {})",
+            self.session.observed.labels.repr(py)?,
+            self.session.config.command.clone().into_pyobject(py)?.repr()?,
+            self.session.config.cols,
+            self.session.config.rows,
+            state,
+        ))
+    }
+
+    /// Retrieves a new file descriptor for writing input to the child, a duplicate of the pty master, e.g. to hand it
+    /// to another tool or event loop. It is the caller's to close, and is non-blocking like numpty's own, so writes may
+    /// fail with `BlockingIOError` when the child doesn't keep up. It stays with the current child: after a restart
+    /// writes fail, and a new one is needed. Don't read from it, that would take output away from the screen.
+    pub fn write_fd(&self) -> PyResult<i32> {
+        let child = self.session.child.lock().unwrap();
+        let Some(ref started) = *child else {
+            return Err(self.not_started());
+        };
+        let fd = started.master.try_clone().map_err(|e| PyOSError::new_err(e.to_string()))?;
+        Ok(fd.into_raw_fd())
+    }
+
+    /// Retrieves a new file descriptor for reading the raw output of the child from now on, across restarts, the
+    /// read end of a pipe numpty copies it to while it keeps feeding the screen. It is the caller's to close, and
+    /// must be read promptly: once the output not read exceeds what the pipe holds (64 KiB on Linux) numpty
+    /// closes its end, so the reader gets end of file rather than output with a gap in it.
+    pub fn read_fd(&self) -> PyResult<i32> {
+        if self.session.is_closed() {
+            return Err(self.not_started());
+        }
+        let fd = self.session.observed.taps.open().map_err(|e| PyOSError::new_err(e.to_string()))?;
+        Ok(fd.into_raw_fd())
+    }
+
+    /// Retrieves a list with the file descriptors the child has open, for auditing what it inherited
+    /// (0, 1 and 2 plus `keep_fds` right after it starts), None if not running.
+    pub fn child_fds(&self) -> Option<Vec<i32>> {
+        self.session.pid().and_then(procfs::open_fds)
+    }
+
+    /// Watch for the session hanging: when there has been no output, no input consumed by the child
+    /// and no change in the child's process state for `timeout` ms the session is flagged as `hung`
+    /// and `callback`, if given, is called without arguments.
+    /// It is flagged again, and `callback` called again, if it hangs again after some activity.
+    /// Pass None as `timeout` to disable the watchdog. Can be called before or after starting.
+    #[pyo3(signature = (timeout, callback=None))]
+    pub fn set_watchdog(&mut self, timeout: Option<u64>, callback: Option<Py<PyAny>>) {
+        // errors of the callback are printed, there is no caller to raise them to
+        let callback = callback.map(|callback| -> Callback {
+            Arc::new(move || {
+                Python::with_gil(|py| {
+                    if let Err(e) = callback.call0(py) {
+                        e.print(py);
+                    }
+                })
+            })
+        });
+        self.session.set_watchdog(timeout.map(|t| (Duration::from_millis(t), callback)));
+    }
+
+    /// Retrieves a list with a tuple of the `ExitStatus` and the final screen, as a `Snapshot`,
+    /// of every time the subprocess failed, whether it was restarted or not.
+    pub fn failures(&self) -> Vec<(ExitStatus, Snapshot)> {
+        self.session.observed.failures.lock().unwrap().clone()
+    }
+
+    /// How the subprocess exited, with the signal and whether it dumped core if it was terminated by one,
+    /// None while it is running (or being restarted).
+    #[getter]
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.session.observed.exit_status.lock().unwrap().clone()
+    }
+
+    /// Wait for the subprocess to exit for good, for at most `timeout` ms if given, and return its `ExitStatus`.
+    /// Raises `TimeoutError` if it is still running by then.
+    #[pyo3(signature = (timeout=None))]
+    pub fn wait(&self, timeout: Option<u64>) -> PyResult<ExitStatus> {
+        let status = self.session.wait(timeout.map(Duration::from_millis)).map_err(|e| self.py_err(e))?;
+        status.ok_or_else(|| PyTimeoutError::new_err("wait timed out, the child is still running"))
+    }
+
+    /// Whether the watchdog has flagged the session as hung, see `set_watchdog`.
+    #[getter]
+    pub fn hung(&self) -> bool {
+        self.session.hung()
+    }
+
+    /// Retrieves a `Health` report combining the liveness signals of the session, for deciding
+    /// whether to keep, restart or abandon it. `recent_output_bytes` counts output since the previous call.
+    pub fn health(&mut self) -> PyResult<Health> {
+        let (Some(input_tx), Some(req_tx), Some(activity)) =
+            (&self.session.input_tx, &self.session.req_tx, &self.session.activity)
+        else {
+            return Err(self.not_started());
+        };
+        let queued = |max: usize, capacity: usize| max - capacity;
+        let output_queue = self.session.output_tx.as_ref()
+            .and_then(|tx| tx.upgrade())
+            .map_or(0, |tx| queued(tx.max_capacity(), tx.capacity()));
+        let output_bytes = activity.output_bytes();
+        let recent_output_bytes = output_bytes.saturating_sub(self.health_bytes);
+        self.health_bytes = output_bytes;
+        Ok(Health {
+            name: self.session.observed.labels.name.clone(),
+            labels: self.session.observed.labels.labels.clone(),
+            alive: self.session.pid()
+                .and_then(procfs::state)
+                .is_some_and(|state| state != 'Z'),
+            pty_open: !input_tx.is_closed(),
+            hung: self.session.hung(),
+            output_bytes,
+            recent_output_bytes,
+            pending_requests: queued(req_tx.max_capacity(), req_tx.capacity()),
+            input_queue: queued(input_tx.max_capacity(), input_tx.capacity()),
+            output_queue,
+            last_error: self.session.last_error.lock().unwrap().clone(),
+        })
+    }
+
+    /// Milliseconds since the subprocess last produced output (or was started), None if not started.
+    pub fn idle_ms(&self) -> Option<u64> {
+        self.session.activity.as_ref().map(|a| a.since_output().as_millis() as u64)
+    }
+
+    /// Milliseconds since input was last consumed by the subprocess (or it was started),
+    /// None if not started.
+    pub fn input_idle_ms(&self) -> Option<u64> {
+        self.session.activity.as_ref().map(|a| a.since_input().as_millis() as u64)
+    }
+
+    /// Bytes of the input sent so far that the subprocess hasn't read yet, whether still queued by numpty or
+    /// waiting in the pty, so 0 once it has consumed everything. A count that stays up after a keypress means the
+    /// app ignored it, rather than a screen that is slow to change. In canonical mode the pty only counts complete
+    /// lines, as an unfinished line can't be read yet.
+    pub fn unread_input(&self) -> PyResult<usize> {
+        self.session.unread_input().map_err(|e| self.py_err(e))
+    }
+
+    /// Retrieves a `f64` vector with the milliseconds from each write of input to the pty to the first output
+    /// after it, an estimate of the echo round trip of each keypress, for the writes that got output so far.
+    /// Writes before the same output all get measured to it. Requires `measure_echo`, None if not started.
+    pub fn echo_latencies<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyArray1<f64>>>> {
+        if !self.session.config.measure_echo {
+            return Err(PyValueError::new_err("not measuring echo"));
+        }
+        Ok(self.session.activity.as_ref()
+            .and_then(|a| a.echo_latencies())
+            .map(|v| PyArray1::from_vec(py, v)))
+    }
+
+    /// Retrieves a tuple with the id of the process group currently in the terminal's foreground
+    /// and the name of its leader process (None if it can't be determined, e.g. the leader has exited).
+    /// Comparing it with `pid` tells whether e.g. a shell or a program launched from it is in control.
+    pub fn foreground_process(&self) -> PyResult<(i32, Option<String>)> {
+        let child = self.session.child.lock().unwrap();
+        let Some(ref started) = *child else {
+            return Err(self.not_started());
+        };
+        let pgrp = tcgetpgrp(&started.master).map_err(|e| PyOSError::new_err(e.to_string()))?;
+        Ok((pgrp.as_raw(), procfs::comm(pgrp)))
+    }
+
+    /// Script the answers to the child's next cursor position requests (DSR 6, `CSI 6 n`, and `CSI ? 6 n`),
+    /// which are otherwise answered with the actual position, to test how it copes with misbehaving terminals.
+    /// Each of `responses` answers one request: None to leave it unanswered, a 1-based `(row, col)` tuple to report
+    /// instead, or a string to send verbatim. Once they run out the actual position is reported again.
+    /// All answers are sent `delay` ms after the request. Can be called before or after starting.
+    #[pyo3(signature = (responses, *, delay=0))]
+    pub fn script_cursor_reports(&self, responses: Vec<Option<ScriptedReport>>, delay: u64) {
+        self.session.observed.cursor_reports.lock().unwrap().script(responses, Duration::from_millis(delay));
+    }
+
+    /// Retrieves a `ControllingTerminal` report on whether the child is set up like in a real terminal: a session
+    /// leader, with the pty as its controlling terminal and in its foreground process group.
+    /// With `check=True` raises `OSError` naming the checks that failed instead.
+    #[pyo3(signature = (*, check=false))]
+    pub fn controlling_terminal(&self, check: bool) -> PyResult<ControllingTerminal> {
+        let child = self.session.child.lock().unwrap();
+        let Some(ref started) = *child else {
+            return Err(self.not_started());
+        };
+        let report = ControllingTerminal::check(started.child, &started.master)
+            .ok_or_else(|| PyOSError::new_err("can't inspect the child, it may have exited"))?;
+        if check && !report.ok() {
+            return Err(PyOSError::new_err(report.problems().join(", ")));
+        }
+        Ok(report)
+    }
+
+    /// Retrieves a 256 x 3 `u8` matrix with the RGB values of the indexed colors as the child sees them:
+    /// the inbuilt palette, or as configured, with any redefinitions the child has made through OSC 4.
+    /// Snapshots convert indexed colors with the palette as of their capture, unless `fixed_palette`.
+    pub fn palette<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<u8>> {
+        let palette = self.session.observed.palette.lock().unwrap();
+        let v: Vec<_> = palette.colors.iter().flat_map(|c| [c.r, c.g, c.b]).collect();
+        PyArray2::from_owned_array(py, Array2::from_shape_vec([256, 3], v).unwrap())
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u32` matrix of UCS-4 (unicode) code points.
+    /// With `normalize` combining marks are composed with the character they follow (NFC),
+    /// leaving blanks in the cells they took.
+    #[pyo3(signature = (*, normalize=false))]
+    pub fn chars<'py>(&self, _py: Python<'py>, normalize: bool) -> Option<Bound<'py, PyArray2<u32>>> {
+        self.snapshot.as_ref()
+            .map(|s| &s.lines)
+            .map(|l| if normalize { nfc_chars_from_lines(l) } else { chars_from_lines(l) })
+            .map(|a|PyArray2::from_owned_array(_py, a))
+    }
+
+    /// Retrieves a _rows_ x _cols_ list of lists of strings with the full grapheme cluster of each cell, so combining
+    /// marks, ZWJ emoji sequences and variation selectors are kept: a cluster is in the cell of its first code point,
+    /// the cells of the rest (which `chars()` has separately) hold empty strings.
+    pub fn graphemes(&self) -> Option<Vec<Vec<String>>> {
+        self.snapshot.as_ref().map(|s| graphemes_from_lines(&s.lines))
+    }
+
+    /// Retrieves a _rows_ x _cols_ numpy `<U8` string matrix with the full contents of each cell, as in `graphemes()`,
+    /// for numpy string operations without decoding code points. Clusters of more than 8 code points are truncated.
+    pub fn chars_str<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<PyFixedUnicode<CHARS_STR_LEN>>>> {
+        self.snapshot.as_ref()
+            .map(|s| chars_str_from_lines(&s.lines))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u8` matrix with the number of columns each cell takes: 2 for double-width
+    /// characters (CJK, emoji), 0 for the continuation cell after one, 1 otherwise.
+    pub fn cell_widths<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<u8>>> {
+        self.snapshot.as_ref()
+            .map(|s| cell_widths_from_lines(&s.lines))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Retrieves a _rows_ x _cols_ `bool` matrix, True where the text is bold (SGR 1)
+    pub fn bold<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_bold)
+    }
+
+    /// Like `bold()` but for faint (dim) text (SGR 2)
+    pub fn faint<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_faint)
+    }
+
+    /// Like `bold()` but for italic text (SGR 3)
+    pub fn italic<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_italic)
+    }
+
+    /// Like `bold()` but for underlined text (SGR 4)
+    pub fn underline<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_underline)
+    }
+
+    /// Like `bold()` but for blinking text (SGR 5)
+    pub fn blink<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_blink)
+    }
+
+    /// Like `bold()` but for inverse video (SGR 7)
+    pub fn inverse<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_inverse)
+    }
+
+    /// Like `bold()` but for struck through text (SGR 9)
+    pub fn strikethrough<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.attr(py, avt::Pen::is_strikethrough)
+    }
+
+    /// Like `bold()` but for concealed text (SGR 8), as in password fields: `text()` has the characters anyway,
+    /// and `render()` conceals them unless `reveal`
+    pub fn concealed<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<bool>>> {
+        self.snapshot.as_ref()
+            .map(|s| attrs_from_lines(&s.lines, &s.extended).mapv(|a| a & ATTR_HIDDEN != 0))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Whether any cell of the snapshot is concealed (SGR 8), see `concealed()`
+    pub fn has_concealed(&self) -> Option<bool> {
+        self.snapshot.as_ref().map(|s| any_attrs(&s.lines, &s.extended, ATTR_HIDDEN))
+    }
+
+    /// Whether any cell of the snapshot is blinking (SGR 5), see `blink()`
+    pub fn has_blinking(&self) -> Option<bool> {
+        self.snapshot.as_ref().map(|s| any_attrs(&s.lines, &s.extended, ATTR_BLINK))
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u16` matrix with the text attributes of each cell packed as bits:
+    /// `ATTR_BOLD`, `ATTR_FAINT`, `ATTR_ITALIC`, `ATTR_UNDERLINE`, `ATTR_BLINK`, `ATTR_INVERSE`, `ATTR_HIDDEN`
+    /// and `ATTR_STRIKETHROUGH`, for testing with NumPy bit operations in one call.
+    pub fn attrs<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<u16>>> {
+        self.snapshot.as_ref()
+            .map(|s| attrs_from_lines(&s.lines, &s.extended))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u8` matrix with the kind of content of each cell:
+    /// `KIND_EMPTY`, `KIND_TEXT` or `KIND_HYPERLINK` (OSC 8 hyperlink anchors),
+    /// for segmenting the screen into semantic layers.
+    pub fn content_kinds<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<u8>>> {
+        self.snapshot.as_ref()
+            .map(|s| kinds_from_lines(&s.lines, &s.extended))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Retrieves a list of `(top, left, bottom, right)` tuples, inclusive, with the bounds of
+    /// the rectangles drawn with box-drawing characters (or ASCII `+`, `-` and `|`), e.g. dialogs.
+    /// With `panes` junctions count as corners, so a box split by lines is found as its panes.
+    #[pyo3(signature = (*, panes=false))]
+    pub fn boxes(&self, panes: bool) -> Option<Vec<Bounds>> {
+        self.snapshot.as_ref().map(|s| boxes_from_lines(&s.lines, panes))
+    }
+
+    /// Retrieves a list of tuples of strings with the fields of the rows of a table in `region`
+    /// (`(top, left, bottom, right)`, inclusive, like from `boxes()`), by default the whole screen.
+    /// Blank lines and horizontal rules are left out. Fields are split at `separator` characters
+    /// if given, else at runs of at least `min_gap` columns blank in every row.
+    #[pyo3(signature = (region=None, *, separator=None, min_gap=1))]
+    pub fn table<'py>(
+        &self,
+        py: Python<'py>,
+        region: Option<Bounds>,
+        separator: Option<char>,
+        min_gap: usize,
+    ) -> PyResult<Option<Vec<Bound<'py, PyTuple>>>> {
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(None);
+        };
+        let table = table_from_lines(&snapshot.lines, region, separator, min_gap)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let rows = table.into_iter().map(|row| PyTuple::new(py, row)).collect::<PyResult<_>>()?;
+        Ok(Some(rows))
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u64` matrix with the number of snapshots taken since each cell
+    /// (character or pen) last changed, 0 where it changed in the latest one, e.g. to find stale parts of the UI.
+    /// All cells count as changed when the size changes.
+    pub fn cell_ages<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<u64>>> {
+        self.ages.ages().map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Number of snapshots taken so far
+    #[getter]
+    pub fn snapshot_count(&self) -> u64 {
+        self.ages.snapshots()
+    }
+
+    /// Retrieves a tuple with a _rows_ x _cols_ `u8` matrix of background colors (0 if default)
+    /// and a corresponding mask (bool) matrix where an element is True if the color is not the default.
+    /// Truecolor codes have no index, so they are 0 and masked like the default unless `nearest`, which converts
+    /// them to the nearest entry of the 256-color cube or grayscale ramp, for programs that only emit truecolor.
+    /// With `masked` a `numpy.ma.MaskedArray` is retrieved instead, masked where the color is the default.
+    #[pyo3(signature = (*, masked=false, nearest=false))]
+    pub fn foreground_indexedcolor<'py>(
+        &self,
+        _py: Python<'py>,
+        masked: bool,
+        nearest: bool,
+    ) -> PyResult<Option<MaybeMasked<'py, IndexedColorMatrices<'py>>>> {
+        self.snapshot.as_ref()
+            .map(|s| &s.lines)
+            .map(|l| match nearest {
+                true => indexedcolor_from_lines(l, |pen| pen.foreground().map(color::nearest_indexed)),
+                false => indexedcolor_from_lines(l, |pen| pen.foreground()),
+            })
+            .map(|(fga, fgma)| match masked {
+                true => masked_array(_py, fga, fgma).map(MaybeMasked::Masked),
+                false => Ok(MaybeMasked::Plain((
+                    PyArray2::from_owned_array(_py, fga),
+                    PyArray2::from_owned_array(_py, fgma)
+                ))),
+            })
+            .transpose()
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u8` matrix with the underline style of each cell, as set with `SGR 4:n`:
+    /// `UNDERLINE_NONE`, `UNDERLINE_SINGLE`, `UNDERLINE_DOUBLE`, `UNDERLINE_CURLY`, `UNDERLINE_DOTTED` or `UNDERLINE_DASHED`.
+    pub fn underline_style<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray2<u8>>> {
+        self.snapshot.as_ref()
+            .map(|s| underline_style_from_lines(&s.extended))
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Retrieves a tuple with a _rows_ x _cols_ `u16` matrix with the id of the hyperlink (OSC 8) of each cell,
+    /// 0 outside hyperlinks, and a dict with the URI of each id on the screen. Ids are numbered as links first
+    /// appear and are kept for the session, so the same link has the same id across snapshots.
+    pub fn hyperlinks<'py>(&self, py: Python<'py>) -> Option<Hyperlinks<'py>> {
+        self.snapshot.as_ref()
+            .map(|s| (PyArray2::from_owned_array(py, links_from_lines(&s.extended)), s.links.clone()))
+    }
+
+    /// Retrieves a tuple with a 3 x rows_ x _cols_ `u8` matrix of underline colors (SGR 58), (0,0,0) if default
+    /// (the foreground color), and a corresponding mask, like `foreground_truecolor()`, or with `masked`
+    /// a `numpy.ma.MaskedArray`.
+    #[pyo3(signature = (*, masked=false))]
+    pub fn underline_color<'py>(
+        &self,
+        py: Python<'py>,
+        masked: bool,
+    ) -> PyResult<Option<MaybeMasked<'py, TrueColorMatrices<'py>>>> {
+        self.snapshot.as_ref()
+            .map(|s| truecolor_from_lines(&s.extended, extended::underline_color, &s.palette))
+            .map(|(color, mask)| match masked {
+                true => masked_array(py, color, mask).map(MaybeMasked::Masked),
+                false => Ok(MaybeMasked::Plain((
+                    PyArray3::from_owned_array(py, color),
+                    PyArray2::from_owned_array(py, mask)
+                ))),
+            })
+            .transpose()
+    }
+
+    /// Retrieves a tuple with a 3 x rows_ x _cols_ `u8` matrix of foreground colors ((0,0,0) if default)
+    /// and a corresponding mask.
+    /// Indexed colors are converted to truecolor using the palette, see `palette()`.
+    /// With `effective` the colors are those actually seen, see `colors()`.
+    /// With `masked` a 3 x _rows_ x _cols_ `numpy.ma.MaskedArray` is retrieved instead, masked where the color is the default.
+    /// With a `default` RGB color it is substituted where the color is the default, and just the matrix is retrieved.
+    #[pyo3(signature = (*, effective=false, masked=false, default=None))]
+    pub fn foreground_truecolor<'py>(
+        &self,
+        _py: Python<'py>,
+        effective: bool,
+        masked: bool,
+        default: Option<[u8; 3]>,
+    ) -> PyResult<Option<MaybeMasked<'py, TrueColorMatrices<'py>>>> {
+        if masked && default.is_some() {
+            return Err(PyValueError::new_err("a default color leaves nothing to mask"));
+        }
+        self.snapshot.as_ref()
+            .map(|s| {
+                let color = color::foreground(effective, &s.palette);
+                let default = default.map(|[r, g, b]| avt::Color::RGB(RGB8::new(r, g, b)));
+                truecolor_from_lines(&s.lines, |pen| color(pen).or(default), &s.palette)
+            })
+            .map(|(fga, fgma)| match (masked, default) {
+                (_, Some(_)) => Ok(MaybeMasked::Dense(PyArray3::from_owned_array(_py, fga))),
+                (true, None) => masked_array(_py, fga, fgma).map(MaybeMasked::Masked),
+                (false, None) => Ok(MaybeMasked::Plain((
+                    PyArray3::from_owned_array(_py, fga),
+                    PyArray2::from_owned_array(_py, fgma)
+                ))),
+            })
+            .transpose()
+    }
+
+    /// Retrieves a tuple with a _rows_ x _cols_ `u8` matrix of foreground colors (0 if default)
+    /// and a corresponding mask (bool) matrix where an element is True if the color is not the default.
+    /// Truecolor codes are 0 and masked unless `nearest`, and with `masked` a `numpy.ma.MaskedArray` is retrieved instead,
+    /// like `foreground_indexedcolor()`.
+    #[pyo3(signature = (*, masked=false, nearest=false))]
+    pub fn background_indexedcolor<'py>(
+        &self,
+        _py: Python<'py>,
+        masked: bool,
+        nearest: bool,
+    ) -> PyResult<Option<MaybeMasked<'py, IndexedColorMatrices<'py>>>> {
+        self.snapshot.as_ref()
+            .map(|s| &s.lines)
+            .map(|l| match nearest {
+                true => indexedcolor_from_lines(l, |pen| pen.background().map(color::nearest_indexed)),
+                false => indexedcolor_from_lines(l, |pen| pen.background()),
+            })
+            .map(|(fga, fgma)| match masked {
+                true => masked_array(_py, fga, fgma).map(MaybeMasked::Masked),
+                false => Ok(MaybeMasked::Plain((
+                    PyArray2::from_owned_array(_py, fga),
+                    PyArray2::from_owned_array(_py, fgma)
+                ))),
+            })
+            .transpose()
+    }
+
+    /// Retrieves a tuple with a 3 x rows_ x _cols_ `u8` matrix of background colors ((0,0,0) if default)
+    /// and a corresponding mask.
+    /// Indexed colors are converted to truecolor using the palette, see `palette()`.
+    /// With `effective` the colors are those actually seen, see `colors()`.
+    /// With `masked` a 3 x _rows_ x _cols_ `numpy.ma.MaskedArray` is retrieved instead, masked where the color is the default.
+    /// With a `default` RGB color it is substituted where the color is the default, and just the matrix is retrieved.
+    #[pyo3(signature = (*, effective=false, masked=false, default=None))]
+    pub fn background_truecolor<'py>(
+        &self,
+        _py: Python<'py>,
+        effective: bool,
+        masked: bool,
+        default: Option<[u8; 3]>,
+    ) -> PyResult<Option<MaybeMasked<'py, TrueColorMatrices<'py>>>> {
+        if masked && default.is_some() {
+            return Err(PyValueError::new_err("a default color leaves nothing to mask"));
+        }
+        self.snapshot.as_ref()
+            .map(|s| {
+                let color = color::background(effective, &s.palette);
+                let default = default.map(|[r, g, b]| avt::Color::RGB(RGB8::new(r, g, b)));
+                truecolor_from_lines(&s.lines, |pen| color(pen).or(default), &s.palette)
+            })
+            .map(|(fga, fgma)| match (masked, default) {
+                (_, Some(_)) => Ok(MaybeMasked::Dense(PyArray3::from_owned_array(_py, fga))),
+                (true, None) => masked_array(_py, fga, fgma).map(MaybeMasked::Masked),
+                (false, None) => Ok(MaybeMasked::Plain((
+                    PyArray3::from_owned_array(_py, fga),
+                    PyArray2::from_owned_array(_py, fgma)
+                ))),
+            })
+            .transpose()
+    }
+
+    /// Retrieves a tuple with the foreground truecolor matrix, its mask, the background truecolor matrix
+    /// and its mask, as `foreground_truecolor()` and `background_truecolor()` would,
+    /// in a single pass over the cells.
+    /// With `effective` the colors are resolved to what would actually be seen: default colors
+    /// substituted (palette 7 for the foreground, 0 for the background, or as redefined by the child
+    /// through OSC 10 and 11), bold rendered as bright
+    /// (palette 0-7 as 8-15) and inverse video applied. Masks are then all False.
+    /// With `masked` a tuple of foreground and background `numpy.ma.MaskedArray` is retrieved instead.
+    #[pyo3(signature = (*, effective=false, masked=false))]
+    pub fn colors<'py>(
+        &self,
+        _py: Python<'py>,
+        effective: bool,
+        masked: bool,
+    ) -> PyResult<Option<MaybeMasked<'py, ColorMatrices<'py>>>> {
+        self.snapshot.as_ref()
+            .map(|s| {
+                let fg = color::foreground(effective, &s.palette);
+                let bg = color::background(effective, &s.palette);
+                colors_from_lines(&s.lines, fg, bg, &s.palette)
+            })
+            .map(|(fg, fgm, bg, bgm)| match masked {
+                true => {
+                    let planes = (masked_array(_py, fg, fgm)?, masked_array(_py, bg, bgm)?);
+                    Ok(MaybeMasked::Masked(planes.into_pyobject(_py)?.into_any()))
+                }
+                false => Ok(MaybeMasked::Plain((
+                    PyArray3::from_owned_array(_py, fg),
+                    PyArray2::from_owned_array(_py, fgm),
+                    PyArray3::from_owned_array(_py, bg),
+                    PyArray2::from_owned_array(_py, bgm)
+                ))),
+            })
+            .transpose()
+    }
+
+    /// Retrieves a _rows_ x _cols_ numpy structured array with everything about each cell, aligned for vectorized
+    /// analysis: fields `char` (as in `chars()`), `fg` and `bg` (RGB triples), `fg_mask` and `bg_mask` (as in `colors()`)
+    /// and `attrs` (as in `attrs()`). With `effective` the colors are those actually seen, see `colors()`.
+    #[pyo3(signature = (*, effective=false))]
+    pub fn screen<'py>(&self, py: Python<'py>, effective: bool) -> Option<Bound<'py, PyArray2<ScreenCell>>> {
+        self.snapshot.as_ref()
+            .map(|s| {
+                let fg = color::foreground(effective, &s.palette);
+                let bg = color::background(effective, &s.palette);
+                screen_from_lines(&s.lines, &s.extended, fg, bg, &s.palette)
+            })
+            .map(|a| PyArray2::from_owned_array(py, a))
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u32` matrix of foreground colors packed as 0x00RRGGBB,
+    /// `DEFAULT_RGB32` (0xFFFFFFFF) where the color is the default.
+    /// Indexed colors are converted using the palette, see `palette()`.
+    /// With `effective` the colors are those actually seen, see `colors()`.
+    #[pyo3(signature = (*, effective=false))]
+    pub fn foreground_rgb32<'py>(
+        &self,
+        _py: Python<'py>,
+        effective: bool,
+    ) -> Option<Bound<'py, PyArray2<u32>>> {
+        self.snapshot.as_ref()
+            .map(|s| rgb32_from_lines(&s.lines, color::foreground(effective, &s.palette), &s.palette))
+            .map(|a| PyArray2::from_owned_array(_py, a))
+    }
+
+    /// Like `foreground_rgb32()` but for the background.
+    #[pyo3(signature = (*, effective=false))]
+    pub fn background_rgb32<'py>(
+        &self,
+        _py: Python<'py>,
+        effective: bool,
+    ) -> Option<Bound<'py, PyArray2<u32>>> {
+        self.snapshot.as_ref()
+            .map(|s| rgb32_from_lines(&s.lines, color::background(effective, &s.palette), &s.palette))
+            .map(|a| PyArray2::from_owned_array(_py, a))
+    }
+
+    /// Retrieves a _rows_ x _cols_ x 3 `f32` matrix of foreground colors as RGB channels normalized to [0, 1],
+    /// channel-last, for feeding ML frameworks. Default colors are substituted with what the child sees as the default
+    /// foreground (palette 7 unless redefined), or `default` if given.
+    /// With `effective` the colors are those actually seen, see `colors()`.
+    #[pyo3(signature = (*, effective=false, default=None))]
+    pub fn foreground_float<'py>(
+        &self,
+        py: Python<'py>,
+        effective: bool,
+        default: Option<[u8; 3]>,
+    ) -> Option<Bound<'py, PyArray3<f32>>> {
+        self.snapshot.as_ref()
+            .map(|s| {
+                let default = default
+                    .map(|[r, g, b]| avt::Color::RGB(RGB8::new(r, g, b)))
+                    .unwrap_or(color::default_foreground(&s.palette));
+                float_colors_from_lines(&s.lines, color::foreground(effective, &s.palette), default, &s.palette)
+            })
+            .map(|a| PyArray3::from_owned_array(py, a))
+    }
+
+    /// Like `foreground_float()` but for the background, palette 0 unless redefined by default.
+    #[pyo3(signature = (*, effective=false, default=None))]
+    pub fn background_float<'py>(
+        &self,
+        py: Python<'py>,
+        effective: bool,
+        default: Option<[u8; 3]>,
+    ) -> Option<Bound<'py, PyArray3<f32>>> {
+        self.snapshot.as_ref()
+            .map(|s| {
+                let default = default
+                    .map(|[r, g, b]| avt::Color::RGB(RGB8::new(r, g, b)))
+                    .unwrap_or(color::default_background(&s.palette));
+                float_colors_from_lines(&s.lines, color::background(effective, &s.palette), default, &s.palette)
+            })
+            .map(|a| PyArray3::from_owned_array(py, a))
+    }
+
+    /// Same as `foreground_rgb32()`, under the name image libraries use for pixel data packed as 0xRRGGBB.
+    #[pyo3(signature = (*, effective=false))]
+    pub fn foreground_rgb_packed<'py>(
+        &self,
+        py: Python<'py>,
+        effective: bool,
+    ) -> Option<Bound<'py, PyArray2<u32>>> {
+        self.foreground_rgb32(py, effective)
+    }
+
+    /// Same as `background_rgb32()`, see `foreground_rgb_packed()`.
+    #[pyo3(signature = (*, effective=false))]
+    pub fn background_rgb_packed<'py>(
+        &self,
+        py: Python<'py>,
+        effective: bool,
+    ) -> Option<Bound<'py, PyArray2<u32>>> {
+        self.background_rgb32(py, effective)
+    }
+
+    /// Segments the screen into connected regions of the same background color (as in `background_rgb32()`),
+    /// for locating selected rows, status bars and highlights. Retrieves a tuple with a _rows_ x _cols_ `u32`
+    /// matrix with the label of the region of each cell, numbered from 0 in reading order of their first cells,
+    /// and a list with the `(top, left, bottom, right)` bounding box (inclusive) of each region.
+    #[pyo3(signature = (*, effective=false))]
+    pub fn background_regions<'py>(
+        &self,
+        py: Python<'py>,
+        effective: bool,
+    ) -> Option<(Bound<'py, PyArray2<u32>>, Vec<Bounds>)> {
+        self.snapshot.as_ref()
+            .map(|s| label_regions(&rgb32_from_lines(&s.lines, color::background(effective, &s.palette), &s.palette)))
+            .map(|(labels, bounds)| (PyArray2::from_owned_array(py, labels), bounds))
+    }
+
+    /// Classifies each row by its dominant foreground color (the most common among its non-blank cells),
+    /// e.g. for scraping colored log output. `mapping` is a dict from labels to a color or a list of colors,
+    /// each a palette index or an `(r, g, b)` tuple, e.g. `{"error": [1, 9], "warning": [3, 11]}`.
+    /// Retrieves a list with the first matching label of each row, `other` if none matches, None for blank rows.
+    /// With `effective` the colors are those actually seen, see `colors()`.
+    #[pyo3(signature = (mapping, *, other=None, effective=false))]
+    pub fn classify_rows(
+        &self,
+        mapping: &Bound<'_, PyDict>,
+        other: Option<&str>,
+        effective: bool,
+    ) -> PyResult<Option<Vec<Option<String>>>> {
+        let Some(s) = &self.snapshot else {
+            return Ok(None);
+        };
+        let mapping = mapping.iter()
+            .map(|(label, colors)| {
+                let colors: ColorArgs = colors.extract()?;
+                let colors = colors.colors().into_iter().map(|c| rgb32_from_avt(c, &s.palette)).collect();
+                Ok((label.extract()?, colors))
+            })
+            .collect::<PyResult<Vec<(String, Vec<u32>)>>>()?;
+        let dominant = severity::dominant_foregrounds(&s.lines, &s.palette, effective);
+        Ok(Some(severity::classify_rows(&dominant, &mapping, other)))
+    }
+
+    /// Retrieves a text string with the text context of the snapshot, lines terminated by `\n`.
+    /// With `strip_trailing` the trailing spaces of each line are dropped, with `trim_blank_lines` the blank lines
+    /// at the end, and with `join_wrapped` soft-wrapped rows are joined into the logical lines they are part of.
+    /// With `show_controls` whitespace is made visible for debugging layouts: control characters in cells show as
+    /// their Control Pictures symbols (`␉`, `␛`...), trailing spaces as `␠` and lines that don't wrap into the
+    /// next row end with `⏎`, after the other options are applied.
+    #[pyo3(signature = (*, strip_trailing=false, trim_blank_lines=false, join_wrapped=false, show_controls=false))]
+    pub fn text(
+        &self,
+        strip_trailing: bool,
+        trim_blank_lines: bool,
+        join_wrapped: bool,
+        show_controls: bool,
+    ) -> PyResult<String> {
+        let options = TextOptions { strip_trailing, trim_blank_lines, join_wrapped, show_controls };
+        match &self.snapshot {
+            Some(snapshot) => Ok(text_from_lines_with(&snapshot.lines, options)),
+            None => Ok("".to_string()),
+        }
+    }
+
+    /// Retrieves a list with the text of each row of the snapshot, without the trailing spaces with `strip_trailing`.
+    #[pyo3(signature = (*, strip_trailing=false))]
+    pub fn rows(&self, strip_trailing: bool) -> Option<Vec<String>> {
+        self.snapshot.as_ref().map(|s| rows_from_lines(&s.lines, strip_trailing))
+    }
+
+    /// Retrieves a list with the `(row, col)` (0-based) where each occurrence of `text` in the snapshot starts,
+    /// top to bottom and left to right, not overlapping. With `wrapped` occurrences can span soft-wrapped rows.
+    #[pyo3(signature = (text, *, wrapped=false))]
+    pub fn find(&self, text: &str, wrapped: bool) -> Option<Vec<(usize, usize)>> {
+        self.snapshot.as_ref().map(|s| find_in_lines(&s.lines, text, wrapped))
+    }
+
+    /// Retrieves a list of `Match`es of the regular expression `pattern` in the text of each row of the snapshot,
+    /// top to bottom and left to right, with the `row`, the `start` and `end` columns (0-based, `end` exclusive)
+    /// and the matched `text`. Matches don't span rows.
+    pub fn search(&self, pattern: &str) -> PyResult<Option<Vec<Match>>> {
+        let regex = Regex::new(pattern).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(self.snapshot.as_ref().map(|s| search_lines(&s.lines, &regex)))
+    }
+
+    /// Like `text()` but with the colors and the attributes (bold, italic, underline, inverse...) as SGR sequences.
+    /// Concealed text (SGR 8) is rendered concealed too, unless `reveal`.
+    #[pyo3(signature = (*, reveal=false))]
+    pub fn render(&self, reveal: bool) -> Option<String> {
+        self.snapshot.as_ref().map(|s| render_lines(&s.lines, &s.extended, &s.palette, reveal))
+    }
+
+    /// Retrieves a _lines_ x _cols_ `u32` matrix of the code points of the lines scrolled off the top of the primary
+    /// screen, oldest first, up to `scrollback` of them. Unlike the snapshot accessors it is as of now, and it has
+    /// the lines above the screen of the snapshot only if nothing scrolled since. With `normalize` as in `chars()`.
+    #[pyo3(signature = (*, normalize=false))]
+    pub fn history_chars<'py>(&self, py: Python<'py>, normalize: bool) -> PyResult<Bound<'py, PyArray2<u32>>> {
+        let lines = self.ask(Req::History)?;
+        let chars = match lines.is_empty() {
+            true => Array2::zeros([0, self.session.config.cols]),
+            false if normalize => nfc_chars_from_lines(&lines),
+            false => chars_from_lines(&lines),
+        };
+        Ok(PyArray2::from_owned_array(py, chars))
+    }
+
+    /// Retrieves a text string with the lines scrolled off the top of the primary screen, as `history_chars()`,
+    /// lines separated by `\n`: joined to `text()` by a newline, it is all the output still kept.
+    pub fn history_text(&self) -> PyResult<String> {
+        Ok(text_from_lines(&self.ask(Req::History)?))
+    }
+
+    /// Compares the text of the snapshot with a golden copy, `expected`, raising an AssertionError listing the rows
+    /// that differ, leaving out cells in `ignore_regions` or matched by `ignore_patterns`, see `Snapshot.assert_screen()`.
+    #[pyo3(signature = (expected, *, ignore_regions=None, ignore_patterns=None))]
+    pub fn assert_screen(
+        &self,
+        expected: &str,
+        ignore_regions: Option<Vec<Bounds>>,
+        ignore_patterns: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        let Some(snapshot) = &self.snapshot else {
+            return Err(PyValueError::new_err("no snapshot"));
+        };
+        snapshot.assert_screen(expected, ignore_regions, ignore_patterns)
+    }
+
+    /// Saves the snapshot to `path` as a compressed NumPy .npz file, to load back with `numpty.load_snapshot()` e.g. to
+    /// compare with across runs and machines. It holds the matrices `chars`, `attrs`, `fg` and `bg` (truecolor) with
+    /// `fg_mask` and `bg_mask` (True for the default color) and `fg_index` and `bg_index` (the palette index, -1 for
+    /// RGB colors), `continued` rows, the `cursor_row`, `cursor_col`, `cursor_visible`, `cursor_shape` and
+    /// `cursor_blinking`, `alt_screen`, `timestamp`, the 256 x 3 `palette` and the `default_fg` and `default_bg`
+    /// (empty for the palette's). Links, underline styles and line sizes are not kept.
+    pub fn save_snapshot(&self, py: Python<'_>, path: PathBuf) -> PyResult<()> {
+        let Some(snapshot) = &self.snapshot else {
+            return Err(PyValueError::new_err("no snapshot"));
+        };
+        Archive::new(snapshot).save_npz(py, path)
+    }
+
+    /// Saves the snapshot as a PNG image at `path`, drawn as seen (with its effective colors) with the colors, font
+    /// and cell size of `theme` as in `SnapshotSeries.save_animation()`. `font` is the path of a monospace TrueType
+    /// font to draw with instead of the theme's.
+    #[cfg(feature = "export")]
+    #[pyo3(signature = (path, *, theme=None, font=None))]
+    pub fn to_png(&self, py: Python<'_>, path: PathBuf, theme: Option<Theme>, font: Option<PathBuf>) -> PyResult<()> {
+        let Some(snapshot) = &self.snapshot else {
+            return Err(PyValueError::new_err("no snapshot"));
+        };
+        let mut theme = theme.unwrap_or_default();
+        if font.is_some() {
+            theme.font = font;
+        }
+        py.allow_threads(|| {
+            let mut rasterizer = Rasterizer::new(&theme)?;
+            write_png(&path, snapshot, &mut rasterizer)
+        })
+        .map_err(|e| PyOSError::new_err(e.to_string()))
+    }
+
+    /// Compares the snapshot with `previous`, a `Snapshot` such as one kept from `snapshot()`, by default the snapshot
+    /// taken before it. Retrieves a tuple with a _rows_ x _cols_ `bool` matrix, True where the cell (character or pen)
+    /// changed, leaving out cells in `ignore_regions` or matched by `ignore_patterns` as in `Snapshot.diff()`, and
+    /// a `DiffStats` with the `count` of cells that changed, the `rows` with changes and their `bounds`.
+    /// None if there is no snapshot, or none before it to compare with.
+    #[pyo3(signature = (previous=None, *, ignore_regions=None, ignore_patterns=None))]
+    pub fn diff<'py>(
+        &self,
+        py: Python<'py>,
+        previous: Option<PyRef<Snapshot>>,
+        ignore_regions: Option<Vec<Bounds>>,
+        ignore_patterns: Option<Vec<String>>,
+    ) -> PyResult<Option<(Bound<'py, PyArray2<bool>>, DiffStats)>> {
+        let previous = previous.as_deref().or(self.previous.as_ref());
+        let (Some(snapshot), Some(previous)) = (&self.snapshot, previous) else {
+            return Ok(None);
+        };
+        if snapshot.shape() != previous.shape() {
+            return Err(PyValueError::new_err("snapshots have different shapes"));
+        }
+        let changed = Ignore::new(ignore_regions, ignore_patterns)?.diff(&previous.lines, &snapshot.lines);
+        let stats = DiffStats::new(&changed);
+        Ok(Some((PyArray2::from_owned_array(py, changed), stats)))
+    }
+
+    /// Retrieves a _rows_ `bool` vector, True for rows that are soft-wrapped continuations of the row before
+    /// (which reached the last column and went on), so logical lines can be told apart from visual rows.
+    pub fn continued_rows<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray1<bool>>> {
+        self.snapshot.as_ref().map(|s| PyArray1::from_owned_array(py, continued_from_lines(&s.lines)))
+    }
+
+    /// Retrieves a _rows_ `u8` vector with the line size of each row as set with DECDWL and DECDHL, one of
+    /// `LINE_SINGLE`, `LINE_DOUBLE_WIDTH`, `LINE_DOUBLE_HEIGHT_TOP` or `LINE_DOUBLE_HEIGHT_BOTTOM`.
+    pub fn line_sizes<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyArray1<u8>>> {
+        self.snapshot.as_ref().map(|s| PyArray1::from_vec(py, s.line_sizes.clone()))
+    }
+
+    /// Retrieves the most recent snapshot as a `Snapshot`, e.g. to accumulate it in a `SnapshotSeries`.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        self.snapshot.clone()
+    }
+
+    /// Retrieves the cursor of the snapshot as a `Cursor`: its `row` and `col` (0-based), whether it is `visible`,
+    /// and its `shape` (`"block"`, `"underline"` or `"bar"`) and `blinking` as set with DECSCUSR.
+    pub fn cursor(&self) -> Option<Cursor> {
+        self.snapshot.as_ref().map(|s| s.cursor.clone())
+    }
+
+    /// A 64-bit hash of the snapshot's cells: their chars, colors and attributes, and the screen size. It is the
+    /// same for the same screen across runs, so comparing it is a cheap way to tell whether the screen changed.
+    pub fn screen_hash(&self) -> Option<u64> {
+        self.snapshot.as_ref().map(Snapshot::screen_hash)
+    }
+
+    /// Retrieves the cell at `row` and `col` (0-based) of the snapshot as a `Cell`, without converting the whole
+    /// screen: its `char` (the grapheme cluster, as in `graphemes()`), `fg` and `bg` as `(r, g, b)` tuples or None
+    /// for the default colors, and `attrs` as in `attrs()`. With `effective` the colors are those actually seen,
+    /// see `colors()`. Raises `IndexError` outside the screen.
+    #[pyo3(signature = (row, col, *, effective=false))]
+    pub fn cell(&self, row: usize, col: usize, effective: bool) -> PyResult<Option<Cell>> {
+        self.snapshot.as_ref()
+            .map(|s| s.cell(row, col, effective).ok_or_else(|| PyIndexError::new_err("cell out of range")))
+            .transpose()
+    }
+
+    /// Send an input string to the controlled process.
+    /// `newline` is what each `\n` is sent as: `"lf"` (as is, the default), `"cr"`, `"crlf"`, or `"auto"`
+    /// for what ends a line given the pty's current termios settings, CR like the Enter key unless the line
+    /// discipline doesn't translate it (canonical mode without ICRNL).
+    /// Input is queued until the child reads it; when the queue is full this blocks, for at most `timeout` ms
+    /// if given, after which it raises `TimeoutError` and the input is not sent.
+    #[pyo3(signature = (input, *, newline="lf", timeout=None))]
+    pub fn input(&mut self, input: String, newline: &str, timeout: Option<u64>) -> PyResult<()> {
+        let data = self.input_bytes(input, newline)?;
+        self.send_input(data, timeout)
+    }
+
+    /// Like `input()`, but never blocks: returns False without sending anything when the input queue is full
+    /// because the child isn't reading, True once the input is queued.
+    #[pyo3(signature = (input, *, newline="lf"))]
+    pub fn try_input(&mut self, input: String, newline: &str) -> PyResult<bool> {
+        let data = self.input_bytes(input, newline)?;
+        self.check_exited()?;
+        self.session.try_send(data).map_err(|e| self.py_err(e))
+    }
+
+    /// Send input to the controlled process, through the terminal.
+    /// Each element of the array can be either a key name or an arbitrary text.
+    /// If a key is not matched by any supported key name then the text is sent to the
+    /// process as is, i.e. like when using `input()`.
+    /// 
+    /// The key and modifier specifications were inspired by
+    /// [tmux](https://github.com/tmux/tmux/wiki/Modifier-Keys).
+    /// 
+    /// The following key specifications are currently supported:
+    /// 
+    /// - `Enter`
+    /// - `Space`
+    /// - `Escape` or `^[` or `C-[`
+    /// - `Tab`
+    /// - `Left` - left arrow key
+    /// - `Right` - right arrow key
+    /// - `Up` - up arrow key
+    /// - `Down` - down arrow key
+    /// - `Home`
+    /// - `End`
+    /// - `PageUp`
+    /// - `PageDown`
+    /// - `F1` to `F12`
+    /// 
+    /// Modifier keys are supported by prepending a key with one of the prefixes:
+    /// 
+    /// - `^` - control - e.g. `^c` means <kbd>Ctrl</kbd> + <kbd>C</kbd>
+    /// - `C-` - control - e.g. `C-c` means <kbd>Ctrl</kbd> + <kbd>C</kbd>
+    /// - `S-` - shift - e.g. `S-F6` means <kbd>Shift</kbd> + <kbd>F6</kbd>
+    /// - `A-` - alt/option - e.g. `A-Home` means <kbd>Alt</kbd> + <kbd>Home</kbd>
+    /// 
+    /// Modifiers can be combined (for arrow keys only at the moment), so combinations
+    /// such as `S-A-Up` or `C-S-Left` are possible.
+    /// 
+    /// `C-` control modifier notation can be used with ASCII letters (both lower and
+    /// upper case are supported) and most special key names. The caret control notation
+    /// (`^`) may only be used with ASCII letters, not with special keys.
+    /// 
+    /// Shift modifier can be used with special key names only, such as `Left`, `PageUp`
+    /// etc. For text characters, instead of specifying e.g. `S-a` just use upper case
+    /// `A`.
+    /// 
+    /// Alt modifiers can be used with any Unicode character and most special key names.
+    ///
+    /// When the process enables xterm's modifyOtherKeys or the CSI u (kitty) keyboard protocol,
+    /// characters and `Enter`, `Tab`, `Escape`, `Backspace`, `Space` with modifiers are sent
+    /// in that encoding, so combinations such as `C-S-a` or `C-Enter` are distinguishable.
+    ///
+    /// Blocks while the input queue is full like `input()`, for at most `timeout` ms if given.
+    #[pyo3(signature = (keys, *, timeout=None))]
+    pub fn keys(&mut self, keys: Vec<String>, timeout: Option<u64>) -> PyResult<()> {
+        self.check_exited()?;
+        let timeout = timeout.map(Duration::from_millis);
+        self.session.keys(keys, timeout).map_err(|e| self.py_err(e))
+    }
+
+    pub fn stop(&mut self) -> PyResult<()> {
+        self.session.stop().map_err(|e| self.py_err(e))
+    }
+
+    /// Stop the subprocess and wait up to `timeout` ms for the tasks driving the session to finish, then shut down
+    /// the runtime, releasing the pty and the threads of the Terminal right away rather than whenever it is garbage
+    /// collected. Calls needing the session raise `ClosedError` from then on, the last snapshot stays available.
+    /// Returns whether the tasks finished in time; closing again, or a Terminal never started, returns True.
+    #[pyo3(signature = (timeout=1000))]
+    pub fn close(&mut self, py: Python<'_>, timeout: u64) -> bool {
+        let session = &mut self.session;
+        // without the GIL, which a watchdog callback may be waiting for
+        py.allow_threads(|| session.close(Duration::from_millis(timeout)))
+    }
+}
+
+#[pymodule]
+fn numpty(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Terminal>()?;
+    m.add_class::<Snapshot>()?;
+    m.add_class::<SnapshotSeries>()?;
+    m.add_class::<ExitStatus>()?;
+    m.add_class::<Health>()?;
+    m.add_class::<ControllingTerminal>()?;
+    m.add_class::<Cursor>()?;
+    m.add_class::<Cell>()?;
+    m.add_class::<Match>()?;
+    m.add_class::<Deadline>()?;
+    m.add_class::<DiffStats>()?;
+    m.add("SpawnError", m.py().get_type::<SpawnError>())?;
+    m.add("ClosedError", m.py().get_type::<ClosedError>())?;
+    m.add("ProcessExited", m.py().get_type::<ProcessExited>())?;
+    #[cfg(feature = "recording")]
+    m.add_class::<Recording>()?;
+    m.add_class::<Screen>()?;
+    #[cfg(feature = "export")]
+    m.add_class::<Theme>()?;
+    #[cfg(feature = "recording")]
+    m.add_function(wrap_pyfunction!(recording::analyze_recordings, m)?)?;
+    m.add_function(wrap_pyfunction!(archive::load_snapshot, m)?)?;
+    m.add_function(wrap_pyfunction!(metrics::metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(metrics::metrics_text, m)?)?;
+    m.add_function(wrap_pyfunction!(metrics::serve_metrics, m)?)?;
+    m.add("DEFAULT_RGB32", color::DEFAULT_RGB32)?;
+    m.add("KIND_EMPTY", lines::KIND_EMPTY)?;
+    m.add("KIND_TEXT", lines::KIND_TEXT)?;
+    m.add("KIND_HYPERLINK", lines::KIND_HYPERLINK)?;
+    m.add("UNDERLINE_NONE", extended::UNDERLINE_NONE)?;
+    m.add("UNDERLINE_SINGLE", extended::UNDERLINE_SINGLE)?;
+    m.add("UNDERLINE_DOUBLE", extended::UNDERLINE_DOUBLE)?;
+    m.add("UNDERLINE_CURLY", extended::UNDERLINE_CURLY)?;
+    m.add("UNDERLINE_DOTTED", extended::UNDERLINE_DOTTED)?;
+    m.add("UNDERLINE_DASHED", extended::UNDERLINE_DASHED)?;
+    m.add("MAX_COLS", geometry::MAX_COLS)?;
+    m.add("MAX_ROWS", geometry::MAX_ROWS)?;
+    m.add("MAX_CELLS", geometry::MAX_CELLS)?;
+    m.add("LINE_SINGLE", extended::LINE_SINGLE)?;
+    m.add("LINE_DOUBLE_WIDTH", extended::LINE_DOUBLE_WIDTH)?;
+    m.add("LINE_DOUBLE_HEIGHT_TOP", extended::LINE_DOUBLE_HEIGHT_TOP)?;
+    m.add("LINE_DOUBLE_HEIGHT_BOTTOM", extended::LINE_DOUBLE_HEIGHT_BOTTOM)?;
+    m.add("ATTR_BOLD", lines::ATTR_BOLD)?;
+    m.add("ATTR_FAINT", lines::ATTR_FAINT)?;
+    m.add("ATTR_ITALIC", lines::ATTR_ITALIC)?;
+    m.add("ATTR_UNDERLINE", lines::ATTR_UNDERLINE)?;
+    m.add("ATTR_BLINK", lines::ATTR_BLINK)?;
+    m.add("ATTR_INVERSE", lines::ATTR_INVERSE)?;
+    m.add("ATTR_HIDDEN", lines::ATTR_HIDDEN)?;
+    m.add("ATTR_STRIKETHROUGH", lines::ATTR_STRIKETHROUGH)?;
+    Ok(())
+}
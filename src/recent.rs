@@ -0,0 +1,31 @@
+use std::collections::VecDeque;
+
+/// Bytes of raw output kept by the term task, older ones are dropped beyond this
+pub const RECENT_OUTPUT_CAPACITY: usize = 64 * 1024;
+
+/// The most recent raw output of the child, bounded
+pub struct RecentOutput {
+    bytes: VecDeque<u8>,
+    capacity: usize,
+}
+
+impl RecentOutput {
+    pub fn new(capacity: usize) -> Self {
+        RecentOutput {
+            bytes: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        let data = &data[data.len().saturating_sub(self.capacity)..];
+        let excess = (self.bytes.len() + data.len()).saturating_sub(self.capacity);
+        self.bytes.drain(..excess);
+        self.bytes.extend(data);
+    }
+
+    /// The last `n` bytes, or as many as there are
+    pub fn last(&self, n: usize) -> Vec<u8> {
+        self.bytes.range(self.bytes.len().saturating_sub(n)..).copied().collect()
+    }
+}
@@ -0,0 +1,497 @@
+// Sessions are recorded in asciicast v2: a JSON header line with the metadata, then a JSON line
+// per event with the time in seconds since the start, its code ("o" for output, "r" for resize)
+// and data. What numpty adds to the header goes under a "numpty" key, which players ignore.
+
+#[cfg(feature = "python")]
+use crate::geometry;
+use crate::labels::Labels;
+#[cfg(feature = "python")]
+use crate::lines::text_from_lines;
+#[cfg(feature = "python")]
+use crate::screen::Screen;
+#[cfg(feature = "python")]
+use crate::snapshot::Snapshot;
+#[cfg(feature = "python")]
+use crate::snapshot::SnapshotSeries;
+#[cfg(feature = "python")]
+use anyhow::anyhow;
+use anyhow::Result;
+#[cfg(feature = "python")]
+use numpy::PyArray1;
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyOSError, PyValueError};
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::File;
+#[cfg(feature = "python")]
+use std::io::{BufRead, BufReader};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+#[cfg(feature = "python")]
+use std::path::PathBuf;
+#[cfg(feature = "python")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "python")]
+use std::sync::Mutex;
+#[cfg(feature = "python")]
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// variables of the parent's environment included in the header, besides TERM which the child gets
+const RECORDED_ENV: &[&str] = &["SHELL", "LANG", "LC_ALL", "LC_CTYPE"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct Extra {
+    version: String,
+    argv: Vec<String>,
+    encoding: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Header {
+    version: u8,
+    width: usize,
+    height: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    numpty: Option<Extra>,
+}
+
+/// Writes the output of a session as it comes, in the term task
+pub struct Recorder {
+    file: LineWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(
+        path: &Path,
+        argv: &[String],
+        cols: usize,
+        rows: usize,
+        encoding: &str,
+        labels: &Labels,
+    ) -> Result<Self> {
+        let mut env: HashMap<String, String> = RECORDED_ENV
+            .iter()
+            .filter_map(|name| Some((name.to_string(), env::var(name).ok()?)))
+            .collect();
+        env.insert("TERM".to_string(), "xterm-256color".to_string());
+        let header = Header {
+            version: 2,
+            width: cols,
+            height: rows,
+            timestamp: Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()),
+            command: Some(argv.join(" ")),
+            title: labels.name.clone(),
+            env,
+            numpty: Some(Extra {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                argv: argv.to_vec(),
+                encoding: encoding.to_string(),
+                name: labels.name.clone(),
+                labels: labels.labels.clone(),
+            }),
+        };
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "{}", serde_json::to_string(&header)?)?;
+        Ok(Recorder { file, start: Instant::now() })
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        writeln!(self.file, "{}", serde_json::to_string(&(time, code, data))?)?;
+        Ok(())
+    }
+
+    pub fn output(&mut self, text: &str) -> Result<()> {
+        self.event("o", text)
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) -> Result<()> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+}
+
+// screens kept from earlier seeks to replay from
+#[cfg(feature = "python")]
+const SEEK_CACHE: usize = 64;
+
+/// An asciicast v2 recording, e.g. written by `Terminal(record=path)`
+#[cfg(feature = "python")]
+#[pyclass]
+pub struct Recording {
+    header: Header,
+    events: Vec<(f64, String, String)>,
+    // the screen after replaying the first n events, by n, kept from seeks
+    keyframes: BTreeMap<usize, Screen>,
+    // the same at regular intervals, see build_index
+    index: BTreeMap<usize, Screen>,
+}
+
+#[cfg(feature = "python")]
+impl Recording {
+    fn new_screen(&self) -> Screen {
+        Screen::new(self.header.width.max(1), self.header.height.max(1))
+    }
+
+    // the nearest keyframe before the first n events have been replayed
+    fn keyframe(&self, n: usize) -> (usize, Screen) {
+        let cached = self.keyframes.range(..=n).next_back();
+        let indexed = self.index.range(..=n).next_back();
+        match cached.into_iter().chain(indexed).max_by_key(|(&start, _)| start) {
+            Some((&start, screen)) => (start, screen.clone()),
+            None => (0, self.new_screen()),
+        }
+    }
+
+    fn replay(&self, screen: &mut Screen, events: std::ops::Range<usize>) {
+        for (_, code, data) in &self.events[events] {
+            match code.as_str() {
+                "o" => _ = screen.feed_str(data),
+                "r" => {
+                    if let Some((cols, rows)) = data.split_once('x') {
+                        if let (Ok(cols), Ok(rows)) = (cols.parse(), rows.parse()) {
+                            _ = screen.resize(cols, rows);
+                        }
+                    }
+                }
+                // input, markers
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+fn read(path: &Path) -> Result<Recording> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = lines.next().ok_or_else(|| anyhow!("empty recording"))??;
+    let header: Header = serde_json::from_str(&header)?;
+    if header.version != 2 {
+        return Err(anyhow!("unsupported asciicast version {}", header.version));
+    }
+    geometry::check(header.width.max(1), header.height.max(1)).map_err(|e| anyhow!(e))?;
+    let mut events = vec![];
+    for line in lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            events.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(Recording { header, events, keyframes: BTreeMap::new(), index: BTreeMap::new() })
+}
+
+// OSError if the file couldn't be read, ValueError if it isn't a recording
+#[cfg(feature = "python")]
+fn read_error(path: &Path, e: anyhow::Error) -> PyErr {
+    let message = format!("{}: {}", path.display(), e);
+    match e.downcast_ref::<std::io::Error>() {
+        Some(io) if io.kind() != std::io::ErrorKind::InvalidData => PyOSError::new_err(message),
+        _ => PyValueError::new_err(message),
+    }
+}
+
+/// What `analyze_recordings` computes of each recording, from Python:
+/// the name of a built-in metric or a callable taking the final screen as a `Snapshot`
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum MetricArg {
+    Name(String),
+    Callback(Py<PyAny>),
+}
+
+#[cfg(feature = "python")]
+enum Metric {
+    Duration,
+    Events,
+    OutputBytes,
+    TimeToText(String),
+    EventsToText(String),
+    // replay to the end for the callback
+    FinalScreen,
+}
+
+#[cfg(feature = "python")]
+enum Outcome {
+    Value(f64),
+    Final(Box<Snapshot>),
+}
+
+#[cfg(feature = "python")]
+fn analyze(path: &Path, metric: &Metric) -> Result<Outcome> {
+    let recording = read(path)?;
+    let to_text = |text: &str| {
+        let mut screen = recording.new_screen();
+        (0..recording.events.len()).find(|&i| {
+            recording.replay(&mut screen, i..i + 1);
+            text_from_lines(screen.view()).contains(text)
+        })
+    };
+    let value = match metric {
+        Metric::Duration => recording.duration(),
+        Metric::Events => recording.events.len() as f64,
+        Metric::OutputBytes => recording.events.iter().filter(|e| e.1 == "o").map(|e| e.2.len()).sum::<usize>() as f64,
+        Metric::TimeToText(text) => to_text(text).map(|i| recording.events[i].0).unwrap_or(f64::NAN),
+        Metric::EventsToText(text) => to_text(text).map(|i| (i + 1) as f64).unwrap_or(f64::NAN),
+        Metric::FinalScreen => {
+            let mut screen = recording.new_screen();
+            recording.replay(&mut screen, 0..recording.events.len());
+            let mut snapshot = screen.snapshot();
+            if let Some(timestamp) = recording.header.timestamp {
+                snapshot.timestamp = timestamp as f64 + recording.duration();
+            }
+            return Ok(Outcome::Final(Box::new(snapshot)));
+        }
+    };
+    Ok(Outcome::Value(value))
+}
+
+/// Analyzes many recordings in parallel, on `workers` threads (by default as many as CPUs), returning a `f64`
+/// vector with the `metric` of each: `"duration"` in seconds, the number of `"events"`, `"output_bytes"`,
+/// or the seconds (`"time_to_text"`) or output events (`"events_to_text"`) until `text` first appears on the screen,
+/// NaN if it never does. `metric` may also be a callable taking the final screen of each recording as a `Snapshot`
+/// and returning a number; recordings are then replayed in parallel and the callable called in order.
+#[cfg(feature = "python")]
+#[pyfunction]
+#[pyo3(signature = (paths, metric, *, text=None, workers=None))]
+pub fn analyze_recordings<'py>(
+    py: Python<'py>,
+    paths: Vec<PathBuf>,
+    metric: MetricArg,
+    text: Option<String>,
+    workers: Option<usize>,
+) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let (metric, callback) = match metric {
+        MetricArg::Callback(callback) if callback.bind(py).is_callable() => (Metric::FinalScreen, Some(callback)),
+        MetricArg::Callback(_) => return Err(PyValueError::new_err("metric must be a name or a callable")),
+        MetricArg::Name(name) => {
+            let text = || text.clone().ok_or_else(|| PyValueError::new_err(format!("{} needs text", name)));
+            let metric = match name.as_str() {
+                "duration" => Metric::Duration,
+                "events" => Metric::Events,
+                "output_bytes" => Metric::OutputBytes,
+                "time_to_text" => Metric::TimeToText(text()?),
+                "events_to_text" => Metric::EventsToText(text()?),
+                _ => return Err(PyValueError::new_err(format!("unknown metric {:?}", name))),
+            };
+            (metric, None)
+        }
+    };
+    let workers = workers
+        .or(thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, paths.len().max(1));
+
+    let outcomes: Vec<Mutex<Option<Result<Outcome>>>> = paths.iter().map(|_| Mutex::new(None)).collect();
+    py.allow_threads(|| {
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = paths.get(i) else {
+                        break;
+                    };
+                    *outcomes[i].lock().unwrap() = Some(analyze(path, &metric));
+                });
+            }
+        });
+    });
+
+    let mut values = Vec::with_capacity(paths.len());
+    for (path, outcome) in paths.iter().zip(outcomes) {
+        let outcome = outcome.into_inner().unwrap().unwrap();
+        let outcome = outcome.map_err(|e| read_error(path, e))?;
+        values.push(match (outcome, &callback) {
+            (Outcome::Value(value), _) => value,
+            (Outcome::Final(snapshot), Some(callback)) => callback.call1(py, (*snapshot,))?.extract(py)?,
+            (Outcome::Final(_), None) => unreachable!(),
+        });
+    }
+    Ok(PyArray1::from_vec(py, values))
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Recording {
+    /// Reads the recording at `path`
+    #[new]
+    pub fn py_new(path: &str) -> PyResult<Self> {
+        read(Path::new(path)).map_err(|e| read_error(Path::new(path), e))
+    }
+
+    /// Columns of the terminal as recording started
+    #[getter]
+    pub fn width(&self) -> usize {
+        self.header.width
+    }
+
+    /// Rows of the terminal as recording started
+    #[getter]
+    pub fn height(&self) -> usize {
+        self.header.height
+    }
+
+    /// When recording started, in seconds since the epoch
+    #[getter]
+    pub fn timestamp(&self) -> Option<u64> {
+        self.header.timestamp
+    }
+
+    /// Seconds from the start to the last event
+    #[getter]
+    pub fn duration(&self) -> f64 {
+        self.events.last().map(|e| e.0).unwrap_or(0.0)
+    }
+
+    /// The command line that was recorded
+    #[getter]
+    pub fn command(&self) -> Option<String> {
+        self.header.command.clone()
+    }
+
+    /// The environment variables recorded, e.g. TERM and SHELL
+    #[getter]
+    pub fn env(&self) -> HashMap<String, String> {
+        self.header.env.clone()
+    }
+
+    /// The version of numpty that made the recording, None if made by something else
+    #[getter]
+    pub fn numpty_version(&self) -> Option<String> {
+        self.header.numpty.as_ref().map(|n| n.version.clone())
+    }
+
+    /// The command as a list of arguments, None if not made by numpty
+    #[getter]
+    pub fn argv(&self) -> Option<Vec<String>> {
+        self.header.numpty.as_ref().map(|n| n.argv.clone())
+    }
+
+    /// The encoding of the child's input and output, None if not made by numpty
+    #[getter]
+    pub fn encoding(&self) -> Option<String> {
+        self.header.numpty.as_ref().map(|n| n.encoding.clone())
+    }
+
+    /// The `name` of the Terminal recorded, None if it had none or the recording wasn't made by numpty
+    #[getter]
+    pub fn name(&self) -> Option<String> {
+        self.header.numpty.as_ref().and_then(|n| n.name.clone())
+    }
+
+    /// The `labels` of the Terminal recorded, empty if it had none or the recording wasn't made by numpty
+    #[getter]
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        self.header.numpty.as_ref().map(|n| n.labels.clone()).unwrap_or_default()
+    }
+
+    /// Retrieves a list of `(time, code, data)` tuples with every event: time in seconds from the start,
+    /// code "o" for output or "r" for a resize (data "COLSxROWS")
+    pub fn events(&self) -> Vec<(f64, String, String)> {
+        self.events.clone()
+    }
+
+    /// Retrieves the screen as of `t_ms` milliseconds into the recording as a `Snapshot`, timestamped
+    /// accordingly, by replaying its output into a `Screen`. Screens of earlier seeks are kept as keyframes
+    /// to replay from, so scrubbing back and forth only replays the output in between.
+    pub fn seek(&mut self, t_ms: u64) -> Snapshot {
+        let t = t_ms as f64 / 1000.0;
+        let n = self.events.partition_point(|e| e.0 <= t);
+        let (start, mut screen) = self.keyframe(n);
+        self.replay(&mut screen, start..n);
+        let mut snapshot = screen.snapshot();
+        if let Some(timestamp) = self.header.timestamp {
+            snapshot.timestamp = timestamp as f64 + t;
+        }
+        if start != n {
+            if self.keyframes.len() >= SEEK_CACHE {
+                self.keyframes.pop_first();
+            }
+            self.keyframes.insert(n, screen);
+        }
+        snapshot
+    }
+
+    /// Replays the whole recording once, keeping a keyframe every `interval_ms` milliseconds of recording time,
+    /// so any `seek()` after replays at most that much output: for random access into long recordings.
+    /// Replaces any previous index, returns the number of keyframes.
+    #[pyo3(signature = (interval_ms=5000))]
+    pub fn build_index(&mut self, interval_ms: u64) -> PyResult<usize> {
+        if interval_ms == 0 {
+            return Err(PyValueError::new_err("interval_ms must be positive"));
+        }
+        let interval = interval_ms as f64 / 1000.0;
+        let mut index = BTreeMap::new();
+        let mut screen = self.new_screen();
+        let (mut start, mut next) = (0, interval);
+        while start < self.events.len() {
+            let n = self.events.partition_point(|e| e.0 < next);
+            self.replay(&mut screen, start..n);
+            if n > start {
+                index.insert(n, screen.clone());
+            }
+            (start, next) = (n, next + interval);
+        }
+        self.index = index;
+        Ok(self.index.len())
+    }
+
+    /// Retrieves the recording as a `SnapshotSeries`: the screen at the end of every `interval_ms` milliseconds of
+    /// recording time with events in it, timestamped as of its last event, e.g. to draw it with `save_animation()`.
+    #[pyo3(signature = (interval_ms=100))]
+    pub fn snapshots(&self, interval_ms: u64) -> PyResult<SnapshotSeries> {
+        if interval_ms == 0 {
+            return Err(PyValueError::new_err("interval_ms must be positive"));
+        }
+        let interval = interval_ms as f64 / 1000.0;
+        let mut frames = vec![];
+        let mut screen = self.new_screen();
+        let mut start = 0;
+        while start < self.events.len() {
+            // up to the end of the interval of the next event, skipping those without any
+            let end = ((self.events[start].0 / interval).floor() + 1.0) * interval;
+            let n = self.events.partition_point(|e| e.0 < end).max(start + 1);
+            self.replay(&mut screen, start..n);
+            let mut snapshot = screen.snapshot();
+            snapshot.timestamp = self.header.timestamp.unwrap_or(0) as f64 + self.events[n - 1].0;
+            frames.push(snapshot);
+            start = n;
+        }
+        Ok(SnapshotSeries::from(frames))
+    }
+
+    /// Retrieves the times, in seconds from the start, of the keyframes built by `build_index()`
+    pub fn keyframe_times(&self) -> Vec<f64> {
+        self.index.keys().map(|&n| self.events[n - 1].0).collect()
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Recording(width={}, height={}, duration={:.3}, command={})",
+            self.header.width,
+            self.header.height,
+            self.duration(),
+            self.header.command.as_ref().map(|c| format!("'{}'", c)).unwrap_or("None".to_string()),
+        )
+    }
+}
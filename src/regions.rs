@@ -0,0 +1,41 @@
+use crate::geometry::Bounds;
+use ndarray::Array2;
+
+/// Connected components of equal values, 4-connected: a matrix with the label of each cell's region,
+/// numbered from 0 in the order their first cells come row by row, and the bounding box of each region
+pub fn label_regions(values: &Array2<u32>) -> (Array2<u32>, Vec<Bounds>) {
+    let (rows, cols) = values.dim();
+    let mut labels = Array2::from_elem([rows, cols], u32::MAX);
+    let mut bounds: Vec<Bounds> = vec![];
+    let mut stack = vec![];
+
+    for row in 0..rows {
+        for col in 0..cols {
+            if labels[[row, col]] != u32::MAX {
+                continue;
+            }
+            let label = bounds.len() as u32;
+            let value = values[[row, col]];
+            let (mut top, mut left, mut bottom, mut right) = (row, col, row, col);
+            labels[[row, col]] = label;
+            stack.push((row, col));
+            while let Some((r, c)) = stack.pop() {
+                (top, left, bottom, right) = (top.min(r), left.min(c), bottom.max(r), right.max(c));
+                let neighbours = [
+                    (r.wrapping_sub(1), c),
+                    (r + 1, c),
+                    (r, c.wrapping_sub(1)),
+                    (r, c + 1),
+                ];
+                for (nr, nc) in neighbours {
+                    if nr < rows && nc < cols && labels[[nr, nc]] == u32::MAX && values[[nr, nc]] == value {
+                        labels[[nr, nc]] = label;
+                        stack.push((nr, nc));
+                    }
+                }
+            }
+            bounds.push((top, left, bottom, right));
+        }
+    }
+    (labels, bounds)
+}
@@ -0,0 +1,51 @@
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A scripted answer to a cursor position request (DSR 6), from Python:
+/// a 1-based `(row, col)` to report instead of the actual position, or a string sent verbatim
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+#[derive(Clone, Debug)]
+pub enum ScriptedReport {
+    Position((usize, usize)),
+    Raw(String),
+}
+
+/// How the term task answers cursor position requests: correctly unless scripted otherwise
+#[derive(Default)]
+pub struct CursorReports {
+    // answers for the next requests, None for no answer
+    #[cfg(feature = "python")]
+    script: VecDeque<Option<ScriptedReport>>,
+    /// Delay before each answer
+    pub delay: Duration,
+}
+
+/// Set by the Terminal, used by the term task
+pub type SharedCursorReports = Arc<Mutex<CursorReports>>;
+
+impl CursorReports {
+    #[cfg(feature = "python")]
+    pub fn script(&mut self, script: Vec<Option<ScriptedReport>>, delay: Duration) {
+        self.script = script.into();
+        self.delay = delay;
+    }
+
+    /// The answer to a request with the cursor at 0-based `row` and `col`, `private` for DECXCPR
+    /// (`CSI ? 6 n`), None if it is to go unanswered
+    pub fn answer(&mut self, row: usize, col: usize, private: bool) -> Option<String> {
+        let prefix = if private { "?" } else { "" };
+        #[cfg(feature = "python")]
+        match self.script.pop_front() {
+            None => {}
+            Some(None) => return None,
+            Some(Some(ScriptedReport::Position((row, col)))) => return Some(format!("\x1b[{}{};{}R", prefix, row, col)),
+            Some(Some(ScriptedReport::Raw(raw))) => return Some(raw),
+        }
+        Some(format!("\x1b[{}{};{}R", prefix, row + 1, col + 1))
+    }
+}
@@ -0,0 +1,59 @@
+// A throwaway HOME for the child, with the XDG base directories inside it, so programs under test
+// neither read nor write the dotfiles of whoever runs the tests. It is removed once the child is gone.
+
+use anyhow::Result;
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// the XDG variables set for the child, and where they point in the sandboxed HOME
+const XDG_DIRS: [(&str, &str); 5] = [
+    ("XDG_CONFIG_HOME", ".config"),
+    ("XDG_CACHE_HOME", ".cache"),
+    ("XDG_DATA_HOME", ".local/share"),
+    ("XDG_STATE_HOME", ".local/state"),
+    ("XDG_RUNTIME_DIR", ".run"),
+];
+
+// tells apart the homes of the Terminals of the process
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh HOME directory under the system's temporary directory, removed when dropped
+pub struct SandboxHome {
+    path: PathBuf,
+}
+
+impl SandboxHome {
+    pub fn create() -> Result<Self> {
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("numpty-home-{}-{}", process::id(), n));
+        // private, like XDG_RUNTIME_DIR must be, and failing rather than reusing what's there
+        DirBuilder::new().mode(0o700).create(&path)?;
+        let home = SandboxHome { path };
+        for (_, dir) in XDG_DIRS {
+            DirBuilder::new().mode(0o700).recursive(true).create(home.path.join(dir))?;
+        }
+        Ok(home)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SandboxHome {
+    fn drop(&mut self) {
+        _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Points the environment, which the child inherits, at `home`: HOME and the XDG base directories
+pub fn set_env(home: &Path) {
+    env::set_var("HOME", home);
+    for (name, dir) in XDG_DIRS {
+        env::set_var(name, home.join(dir));
+    }
+}
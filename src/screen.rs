@@ -0,0 +1,146 @@
+use crate::cursor::Cursor;
+use crate::escapes::{Event, Scanner};
+use crate::extended::{clone_vt, Extended};
+#[cfg(feature = "python")]
+use crate::geometry;
+use crate::modes::Modes;
+use crate::palette::Palette;
+use crate::protocol::Capture;
+use crate::snapshot::Snapshot;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+/// Output to feed a `Screen` with, text or raw bytes
+#[cfg(feature = "python")]
+#[derive(FromPyObject)]
+pub enum Data {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// A headless terminal screen fed directly, without a child process,
+/// e.g. to convert recordings into frame sequences
+#[cfg_attr(feature = "python", pyclass)]
+pub struct Screen {
+    vt: avt::Vt,
+    scanner: Scanner,
+    extended: Extended,
+    palette: Palette,
+    modes: Modes,
+    // incomplete UTF-8 sequence at the end of the last bytes fed
+    pending: Vec<u8>,
+}
+
+impl Clone for Screen {
+    fn clone(&self) -> Self {
+        Screen {
+            vt: clone_vt(&self.vt),
+            scanner: self.scanner.clone(),
+            extended: self.extended.clone(),
+            palette: self.palette.clone(),
+            modes: self.modes.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Screen {
+            vt: avt::Vt::builder().size(cols, rows).build(),
+            scanner: Scanner::new(),
+            extended: Extended::new(cols, rows),
+            palette: Palette::default(),
+            modes: Modes::default(),
+            pending: vec![],
+        }
+    }
+
+    /// The lines of the screen, without copying them into a snapshot
+    #[cfg(feature = "python")]
+    pub fn view(&self) -> &[avt::Line] {
+        self.vt.view()
+    }
+
+    pub fn feed_str(&mut self, text: &str) -> Vec<usize> {
+        for c in text.chars() {
+            let event = self.scanner.feed(c);
+            self.extended.feed(c, &event, self.scanner.in_sequence());
+            if let Some(Event::Osc(osc)) = &event {
+                // nobody to answer queries to
+                _ = self.palette.apply(osc);
+            }
+            if let Some(event) = &event {
+                self.modes.apply(event);
+            }
+        }
+        let mut rows = self.vt.feed_str(text).lines;
+        rows.extend(self.extended.flush());
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+
+    /// The current contents of the screen
+    pub fn snapshot(&self) -> Snapshot {
+        let extended = self.extended.view();
+        let capture = Capture {
+            lines: self.vt.view().to_vec(),
+            links: self.extended.links_in(&extended),
+            extended,
+            cursor: Cursor::new(&self.vt, self.modes.cursor_style()),
+            line_sizes: self.extended.line_sizes(),
+            alt_screen: self.modes.alt_screen(),
+        };
+        Snapshot::new(capture, self.palette.clone())
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Screen {
+    /// Create a Screen with `cols` and `rows`
+    #[new]
+    pub fn py_new(cols: usize, rows: usize) -> PyResult<Self> {
+        geometry::check(cols, rows).map_err(PyValueError::new_err)?;
+        Ok(Screen::new(cols, rows))
+    }
+
+    /// Feed output (a string, or bytes which may split UTF-8 sequences between calls) to the screen.
+    /// Returns a sorted list of the rows that changed, so only those need to be re-extracted.
+    pub fn feed(&mut self, data: Data) -> Vec<usize> {
+        let text = match data {
+            Data::Text(text) => text,
+            Data::Bytes(bytes) => {
+                self.pending.extend_from_slice(&bytes);
+                let valid = match std::str::from_utf8(&self.pending) {
+                    Ok(_) => self.pending.len(),
+                    // keep an incomplete sequence at the end for the next call
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    Err(_) => self.pending.len(),
+                };
+                let rest = self.pending.split_off(valid);
+                let text = String::from_utf8_lossy(&self.pending).into_owned();
+                self.pending = rest;
+                text
+            }
+        };
+        self.feed_str(&text)
+    }
+
+    /// Resize the screen to `cols` and `rows`, as a recorded resize event
+    pub fn resize(&mut self, cols: usize, rows: usize) -> PyResult<()> {
+        geometry::check(cols, rows).map_err(PyValueError::new_err)?;
+        self.vt.resize(cols, rows);
+        self.extended.resize(cols, rows);
+        Ok(())
+    }
+
+    /// Retrieves the current contents of the screen as a `Snapshot`.
+    #[pyo3(name = "snapshot")]
+    pub fn py_snapshot(&self) -> Snapshot {
+        self.snapshot()
+    }
+}
@@ -0,0 +1,49 @@
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use regex::Regex;
+
+/// Where a regular expression matched the text of a row
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    /// 0-based row
+    pub row: usize,
+    /// 0-based column of the first cell of the match
+    pub start: usize,
+    /// 0-based column after the last cell of the match, so `end - start` cells matched
+    pub end: usize,
+    pub text: String,
+}
+
+/// The matches of `regex` in the text of each row, top to bottom and left to right, not overlapping
+pub fn search_lines(lines: &[avt::Line], regex: &Regex) -> Vec<Match> {
+    let mut matches = vec![];
+    for (row, line) in lines.iter().enumerate() {
+        // a cell per character, so columns are char offsets
+        let text = line.text();
+        let col = |offset: usize| text[..offset].chars().count();
+        for m in regex.find_iter(&text) {
+            matches.push(Match {
+                row,
+                start: col(m.start()),
+                end: col(m.end()),
+                text: m.as_str().to_string(),
+            });
+        }
+    }
+    matches
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Match {
+    pub fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "Match(row={}, start={}, end={}, text={})",
+            self.row,
+            self.start,
+            self.end,
+            self.text.clone().into_pyobject(py)?.repr()?,
+        ))
+    }
+}
@@ -0,0 +1,702 @@
+// A child process in a headless pty and the emulator following its output, driven by the pty, term and
+// watchdog tasks on a runtime of its own: the engine under the Python `Terminal`, usable from Rust directly.
+
+use crate::activity::Activity;
+use crate::encoding::Codec;
+use crate::exit::ExitStatus;
+use crate::geometry;
+use crate::health::LastError;
+use crate::keys::{self, InputSeq, Newline};
+use crate::labels::Labels;
+use crate::metrics::{self, METRICS};
+use crate::modes::BRACKETED_PASTE;
+use crate::palette::Palette;
+use crate::protocol::{Buffer, Capture, Output, Reply, Req, Until, Wait, Waits};
+use crate::pty::{self, run_pty, CurrentChild, ExecError, Launch};
+#[cfg(feature = "recording")]
+use crate::recording::Recorder;
+use crate::sandbox::SandboxHome;
+use crate::snapshot::Snapshot;
+use crate::term::{run_term, Observed};
+use crate::watchdog::{run_watchdog, Callback};
+
+use anyhow::{anyhow, Result};
+use futures::channel::oneshot;
+use nix::sys::termios::{tcgetattr, InputFlags, LocalFlags};
+use nix::unistd::Pid;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+/// How a `Session` runs its command, `Config::new` filling in the defaults
+#[derive(Clone)]
+pub struct Config {
+    pub command: Vec<String>,
+    pub cols: usize,
+    pub rows: usize,
+    /// How many times to restart the child when it fails
+    pub restarts: usize,
+    /// Convert indexed colors with the configured palette, ignoring what the child redefines
+    pub fixed_palette: bool,
+    /// Measure the time from each input write to the output that follows
+    pub measure_echo: bool,
+    /// Directory to run the child in with core dumps enabled
+    pub core_dir: Option<PathBuf>,
+    /// File descriptors the child inherits besides stdin, stdout and stderr
+    pub keep_fds: Vec<i32>,
+    /// Give the child a throwaway HOME and XDG base directories, removed as it stops
+    pub sandbox_home: bool,
+    /// Path to record the session to in asciicast v2
+    pub record: Option<PathBuf>,
+    /// Most lines kept as they scroll off the top of the primary screen
+    pub scrollback: usize,
+    pub palette: Palette,
+    pub labels: Labels,
+    // None for UTF-8, legacy encodings need Python's codecs
+    pub(crate) codec: Option<Arc<Codec>>,
+}
+
+impl Config {
+    pub fn new(command: Vec<String>, cols: usize, rows: usize) -> Self {
+        Config {
+            command,
+            cols,
+            rows,
+            restarts: 0,
+            fixed_palette: false,
+            measure_echo: false,
+            core_dir: None,
+            keep_fds: vec![],
+            sandbox_home: false,
+            record: None,
+            scrollback: 10000,
+            palette: Palette::default(),
+            labels: Labels::default(),
+            codec: None,
+        }
+    }
+
+    /// Checks the configuration, returning what is wrong with it
+    pub fn check(&self) -> Result<(), String> {
+        geometry::check(self.cols, self.rows)?;
+        if self.command.is_empty() {
+            return Err("empty command".to_string());
+        }
+        if self.command.iter().any(|arg| arg.contains('\0')) {
+            return Err("command arguments can't contain NUL characters".to_string());
+        }
+        if let Some(fd) = self.keep_fds.iter().find(|&&fd| fd < 0) {
+            return Err(format!("invalid file descriptor in keep_fds: {}", fd));
+        }
+        if let Some(dir) = self.core_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            return Err(format!("core_dir is not a directory: {}", dir.display()));
+        }
+        if cfg!(not(feature = "recording")) && self.record.is_some() {
+            return Err("record needs numpty built with the recording feature".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Why a `Session` can't do what was asked, besides I/O errors and `ExecError`
+#[derive(Debug, PartialEq)]
+pub enum SessionError {
+    /// An argument or the configuration is invalid
+    Invalid(String),
+    NotStarted,
+    AlreadyStarted,
+    Closed,
+    /// The child didn't take input within the given ms
+    InputTimeout(u64),
+}
+
+impl std::error::Error for SessionError {}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::Invalid(message) => write!(f, "{}", message),
+            SessionError::NotStarted => write!(f, "not started"),
+            SessionError::AlreadyStarted => write!(f, "already started"),
+            SessionError::Closed => write!(f, "the session is closed"),
+            SessionError::InputTimeout(ms) => write!(f, "input not taken in {} ms, the child isn't reading", ms),
+        }
+    }
+}
+
+/// A child process running in a headless pseudo-terminal
+pub struct Session {
+    pub(crate) config: Config,
+    // None once closed
+    rt: Option<Runtime>,
+    // the tasks of the session, joined as it is closed
+    tasks: Vec<JoinHandle<()>>,
+    pub(crate) input_tx: Option<mpsc::Sender<Vec<u8>>>,
+    pub(crate) output_tx: Option<mpsc::WeakSender<Output>>,
+    pub(crate) req_tx: Option<mpsc::Sender<Req>>,
+    pub(crate) token: Option<CancellationToken>,
+    pub(crate) child: CurrentChild,
+    pub(crate) observed: Observed,
+    pub(crate) last_error: LastError,
+    pub(crate) activity: Option<Arc<Activity>>,
+    watchdog: Option<(Duration, Option<Callback>)>,
+    watchdog_token: Option<CancellationToken>,
+    hung: Arc<AtomicBool>,
+    // the sandboxed HOME of the last start
+    home: Option<PathBuf>,
+}
+
+impl Session {
+    /// A session for `config`, not started until `start()`
+    pub fn new(config: Config) -> Result<Self> {
+        config.check().map_err(SessionError::Invalid)?;
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(3)
+            .enable_all()
+            .build()?;
+        let observed = Observed {
+            palette: Arc::new(Mutex::new(config.palette.clone())),
+            labels: Arc::new(config.labels.clone()),
+            ..Observed::default()
+        };
+        Ok(Session {
+            config,
+            rt: Some(rt),
+            tasks: vec![],
+            input_tx: None,
+            output_tx: None,
+            req_tx: None,
+            token: None,
+            child: Arc::new(Mutex::new(None)),
+            observed,
+            last_error: Arc::new(Mutex::new(None)),
+            activity: None,
+            watchdog: None,
+            watchdog_token: None,
+            hung: Arc::new(AtomicBool::new(false)),
+            home: None,
+        })
+    }
+
+    /// Starts the child, failing with an `ExecError` if the command can't be run
+    pub fn start(&mut self) -> Result<()> {
+        if self.rt.is_none() {
+            return Err(SessionError::Closed.into());
+        }
+        if self.req_tx.is_some() {
+            return Err(SessionError::AlreadyStarted.into());
+        }
+        let started = self.do_start();
+        if let Err(e) = &started {
+            if e.is::<ExecError>() {
+                metrics::add(&METRICS.spawn_errors, 1);
+            }
+        }
+        started
+    }
+
+    fn do_start(&mut self) -> Result<()> {
+        let (input_tx, input_rx): (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>) =
+            mpsc::channel(1024);
+        let (output_tx, output_rx) = mpsc::channel(1024);
+        let (req_tx, req_rx) = mpsc::channel(1);
+        let (start_tx, start_rx) = oneshot::channel();
+
+        let Some(rt) = &self.rt else {
+            return Err(SessionError::Closed.into());
+        };
+        let config = &self.config;
+        let token = CancellationToken::new();
+        let activity = Arc::new(Activity::new(config.measure_echo));
+
+        let home = config.sandbox_home.then(SandboxHome::create).transpose()?;
+        let launch = Launch {
+            command: config.command.clone(),
+            cols: config.cols,
+            rows: config.rows,
+            restarts: config.restarts,
+            core_dir: config.core_dir.clone(),
+            keep_fds: config.keep_fds.clone(),
+            home: home.as_ref().map(|h| h.path().to_path_buf()),
+        };
+        let home_path = launch.home.clone();
+
+        #[cfg(feature = "recording")]
+        let recorder = match &config.record {
+            Some(path) => {
+                let encoding = config.codec.as_ref().map(|c| c.name()).unwrap_or("utf-8");
+                let labels = &self.observed.labels;
+                Some(Recorder::create(path, &config.command, config.cols, config.rows, encoding, labels)?)
+            }
+            None => None,
+        };
+
+        let weak_output_tx = output_tx.downgrade();
+        let pty = run_pty(
+            launch,
+            input_rx,
+            output_tx,
+            activity.clone(),
+            self.child.clone(),
+            start_tx,
+            token.clone(),
+        );
+        let last_error = self.last_error.clone();
+        let pty = rt.spawn(async move {
+            if let Err(e) = pty.await {
+                *last_error.lock().unwrap() = Some(e.to_string());
+            }
+            // the child is gone for good, and its HOME with it
+            drop(home);
+        });
+
+        let term = run_term(
+            config.cols,
+            config.rows,
+            config.scrollback,
+            output_rx,
+            req_rx,
+            input_tx.clone(),
+            activity.clone(),
+            self.observed.clone(),
+            config.codec.clone(),
+            #[cfg(feature = "recording")]
+            recorder,
+            #[cfg(feature = "recording")]
+            self.last_error.clone(),
+            token.clone(),
+        );
+        let term = rt.spawn(async move {
+            _ = term.await;
+        });
+        self.tasks = vec![pty, term];
+
+        self.input_tx = Some(input_tx);
+        self.output_tx = Some(weak_output_tx);
+        self.req_tx = Some(req_tx);
+        self.token = Some(token);
+        self.activity = Some(activity);
+        self.home = home_path;
+
+        let started = rt.block_on(async {
+            let outcome = start_rx.await;
+            match outcome {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err(anyhow!("could not communicate")),
+            }
+        });
+        if started.is_err() {
+            // the tasks have nothing to drive, and shouldn't count as a running session
+            self.cancel();
+        }
+        started?;
+        metrics::add(&METRICS.sessions_started, 1);
+        self.start_watchdog();
+        Ok(())
+    }
+
+    fn cancel(&self) {
+        if let Some(token) = &self.token {
+            token.cancel();
+        }
+    }
+
+    /// Stops the child, which isn't restarted
+    pub fn stop(&mut self) -> Result<()> {
+        if self.input_tx.is_none() {
+            return Err(self.not_started().into());
+        }
+        self.cancel();
+        Ok(())
+    }
+
+    /// Stops the child if running, without complaining if it isn't
+    #[cfg(feature = "python")]
+    pub(crate) fn stop_quietly(&mut self) {
+        self.cancel();
+    }
+
+    /// Stops the child and waits up to `timeout` for the tasks driving the session to finish, then shuts down
+    /// the runtime, releasing the pty and the threads. Returns whether the tasks finished in time.
+    pub fn close(&mut self, timeout: Duration) -> bool {
+        let Some(rt) = self.rt.take() else {
+            return true;
+        };
+        self.cancel();
+        let tasks = std::mem::take(&mut self.tasks);
+        let started = Instant::now();
+        let finished = rt.block_on(async {
+            tokio::time::timeout(timeout, futures::future::join_all(tasks)).await.is_ok()
+        });
+        // tasks that didn't finish are dropped at their next await
+        rt.shutdown_timeout(timeout.saturating_sub(started.elapsed()));
+        self.input_tx = None;
+        self.output_tx = None;
+        self.req_tx = None;
+        *self.child.lock().unwrap() = None;
+        finished
+    }
+
+    /// Whether `close()` has been called
+    pub fn is_closed(&self) -> bool {
+        self.rt.is_none()
+    }
+
+    /// Whether it has been started, and not stopped since
+    pub fn is_running(&self) -> bool {
+        self.token.as_ref().is_some_and(|token| !token.is_cancelled())
+    }
+
+    // what calls needing the session fail with before it starts, or once it is closed
+    pub(crate) fn not_started(&self) -> SessionError {
+        match self.rt {
+            Some(_) => SessionError::NotStarted,
+            None => SessionError::Closed,
+        }
+    }
+
+    /// Watch for the session hanging: no output, no input consumed and no change in the state of the child
+    /// for `timeout` flags it as `hung()` and calls `callback`, again if it hangs again after some activity.
+    /// None disables the watchdog.
+    pub fn set_watchdog(&mut self, watchdog: Option<(Duration, Option<Callback>)>) {
+        self.watchdog = watchdog;
+        self.start_watchdog();
+    }
+
+    fn start_watchdog(&mut self) {
+        if let Some(token) = self.watchdog_token.take() {
+            token.cancel();
+        }
+        self.hung.store(false, Ordering::Relaxed);
+        let (Some(rt), Some((timeout, callback)), Some(activity), Some(token)) =
+            (&self.rt, &self.watchdog, &self.activity, &self.token)
+        else {
+            return;
+        };
+        let watchdog_token = token.child_token();
+        let watchdog = rt.spawn(run_watchdog(
+            *timeout,
+            self.child.clone(),
+            activity.clone(),
+            self.hung.clone(),
+            callback.clone(),
+            watchdog_token.clone(),
+        ));
+        self.tasks.push(watchdog);
+        self.watchdog_token = Some(watchdog_token);
+    }
+
+    /// Whether the watchdog has flagged the session as hung
+    pub fn hung(&self) -> bool {
+        self.hung.load(Ordering::Relaxed)
+    }
+
+    /// The throwaway HOME of the child with `sandbox_home`, None if not started; removed once the child stops
+    pub fn home(&self) -> Option<&Path> {
+        self.home.as_deref()
+    }
+
+    /// Bytes of input the child hasn't read yet, whether still queued in the session or in the pty,
+    /// see `pty::unread_input`
+    pub fn unread_input(&self) -> Result<usize> {
+        let child = self.child.lock().unwrap();
+        let (Some(started), Some(activity)) = (child.as_ref(), &self.activity) else {
+            return Err(self.not_started().into());
+        };
+        Ok((activity.pending_input() as usize).saturating_add(pty::unread_input(&started.master)?))
+    }
+
+    /// Process id of the child, None if not started
+    pub fn pid(&self) -> Option<Pid> {
+        self.child.lock().unwrap().as_ref().map(|s| s.child)
+    }
+
+    /// How the child exited for good, None while it is running or being restarted
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.observed.exit_status.lock().unwrap().clone()
+    }
+
+    /// The exit status and final screen of every failure of the child
+    pub fn failures(&self) -> Vec<(ExitStatus, Snapshot)> {
+        self.observed.failures.lock().unwrap().clone()
+    }
+
+    pub fn labels(&self) -> &Labels {
+        &self.observed.labels
+    }
+
+    /// Sends a request to the term task and waits for its reply
+    pub(crate) fn ask<T>(&self, req: impl FnOnce(oneshot::Sender<T>) -> Req) -> Result<T> {
+        let (Some(rt), Some(req_tx)) = (&self.rt, &self.req_tx) else {
+            return Err(self.not_started().into());
+        };
+        rt.block_on(async {
+            let (reply_tx, reply_rx) = oneshot::channel();
+            req_tx.send(req(reply_tx)).await?;
+            Ok(reply_rx.await?)
+        })
+    }
+
+    /// Waits for `until`, replying with the screen in `buffer`
+    pub(crate) fn request(&self, until: Until, waits: Waits, at_frame: bool, buffer: Buffer) -> Result<Reply> {
+        let settling = until.settles();
+        let started = Instant::now();
+        let reply = self.ask(|reply| Req::Wait(Wait { until, waits, at_frame, buffer, reply }))?;
+        // don't really care about terminal if there was a launch
+        if let Some(e) = reply.error {
+            return Err(anyhow!(e));
+        }
+        if settling {
+            METRICS.settled(started.elapsed());
+        }
+        Ok(reply)
+    }
+
+    /// A snapshot of a capture, colors converted with the palette as the child sees it unless `fixed_palette`
+    pub(crate) fn new_snapshot(&self, capture: Capture) -> Snapshot {
+        let mut palette = self.observed.palette.lock().unwrap().clone();
+        if self.config.fixed_palette {
+            palette.reset();
+        }
+        Snapshot::new(capture, palette)
+    }
+
+    /// Waits for output to settle, for at most `wait_first` for some output to arrive and then until `wait_more`
+    /// passes without any more, giving up after `max_wait` if given, and captures the screen
+    pub fn settle(&self, wait_first: Duration, wait_more: Duration, max_wait: Option<Duration>) -> Result<Snapshot> {
+        let waits = Waits { first: Some(wait_first), more: Some(wait_more), max: max_wait };
+        let reply = self.request(Until::Settled, waits, false, Buffer::Active)?;
+        Ok(self.new_snapshot(reply.capture))
+    }
+
+    /// Captures the screen right away
+    pub fn capture(&self) -> Result<Snapshot> {
+        let capture = self.ask(|reply| Req::Snapshot(Buffer::Active, reply))?;
+        Ok(self.new_snapshot(capture))
+    }
+
+    /// Waits for at most `timeout` for `text` to appear anywhere on the screen, returning whether it did
+    /// and the screen as of then
+    pub fn wait_for_text(&self, text: &str, timeout: Duration) -> Result<(bool, Snapshot)> {
+        let waits = Waits { max: Some(timeout), ..Waits::default() };
+        let reply = self.request(Until::Text(text.to_string()), waits, false, Buffer::Active)?;
+        Ok((reply.reached, self.new_snapshot(reply.capture)))
+    }
+
+    /// Waits for `until`, for as long as `waits` allow, returning whether it was reached and the screen in `buffer`
+    /// as of then
+    pub fn wait_until(&self, until: Until, waits: Waits, buffer: Buffer) -> Result<(bool, Snapshot)> {
+        let reply = self.request(until, waits, false, buffer)?;
+        Ok((reply.reached, self.new_snapshot(reply.capture)))
+    }
+
+    /// Waits for the child to exit for good, for at most `timeout` if given, returning how it exited,
+    /// None if it was still running by then
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<Option<ExitStatus>> {
+        let waits = Waits { max: timeout, ..Waits::default() };
+        self.request(Until::Exited, waits, false, Buffer::Active)?;
+        Ok(self.exit_status())
+    }
+
+    /// The lines scrolled off the top of the primary screen, oldest first
+    pub fn history(&self) -> Result<Vec<avt::Line>> {
+        self.ask(Req::History)
+    }
+
+    /// The DEC private modes the child has currently set, sorted
+    pub fn modes(&self) -> Result<Vec<u16>> {
+        self.ask(Req::Modes)
+    }
+
+    /// Up to `bytes` of the most recent raw output of the child, escape sequences included
+    pub fn last_output(&self, bytes: usize) -> Result<Vec<u8>> {
+        self.ask(|reply| Req::RecentOutput(bytes, reply))
+    }
+
+    /// Resizes the terminal, the child gets a SIGWINCH
+    pub fn resize(&mut self, cols: usize, rows: usize) -> Result<()> {
+        geometry::check(cols, rows).map_err(SessionError::Invalid)?;
+        if let Some(ref started) = *self.child.lock().unwrap() {
+            pty::set_winsize(&started.master, cols, rows)?;
+        }
+        if self.req_tx.is_some() {
+            self.ask(|reply| Req::Resize { cols, rows, reply })?;
+        }
+        self.config.cols = cols;
+        self.config.rows = rows;
+        Ok(())
+    }
+
+    /// Input as UTF-8 to the encoding of the session
+    pub(crate) fn encode(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        match &self.config.codec {
+            Some(codec) => {
+                let encoded = codec.encode(&String::from_utf8_lossy(&data));
+                Ok(encoded.map_err(|e| SessionError::Invalid(e.to_string()))?)
+            }
+            None => Ok(data),
+        }
+    }
+
+    /// Input text to bytes for the child, with each `\n` sent as `newline`
+    pub(crate) fn input_bytes(&self, input: &str, newline: Newline) -> Result<Vec<u8>> {
+        let newline = match newline {
+            Newline::Auto => {
+                let child = self.child.lock().unwrap();
+                let Some(ref started) = *child else {
+                    return Err(self.not_started().into());
+                };
+                let termios = tcgetattr(&started.master)?;
+                newline.resolve(
+                    termios.input_flags.contains(InputFlags::ICRNL),
+                    termios.local_flags.contains(LocalFlags::ICANON),
+                )
+            }
+            newline => newline,
+        };
+        let seq = InputSeq::Standard(newline.translate(input));
+        // is the cursor always in this mode as the Vt is created?
+        let cursor_key_app_mode = true;
+        self.encode(keys::seqs_to_bytes(&[seq], cursor_key_app_mode))
+    }
+
+    /// Key names, as in the `keys()` of the Python `Terminal`, to bytes for the child
+    pub(crate) fn key_bytes(&self, keys: Vec<String>) -> Result<Vec<u8>> {
+        let encoding = *self.observed.key_encoding.lock().unwrap();
+        let seqs: Vec<InputSeq> = keys.into_iter().map(|k| keys::parse_key_with(k, encoding)).collect();
+        // is the cursor always in this mode as the Vt is created?
+        let cursor_key_app_mode = true;
+        self.encode(keys::seqs_to_bytes(&seqs, cursor_key_app_mode))
+    }
+
+    /// Queues input for the child, waiting for room at most `timeout` if given
+    pub fn send(&self, data: Vec<u8>, timeout: Option<Duration>) -> Result<()> {
+        let (Some(rt), Some(input_tx), Some(activity)) = (&self.rt, &self.input_tx, &self.activity) else {
+            return Err(self.not_started().into());
+        };
+        let len = data.len();
+        activity.queued(len);
+        let sent = rt.block_on(async {
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, input_tx.send(data)).await.ok(),
+                None => Some(input_tx.send(data).await),
+            }
+        });
+        if !matches!(sent, Some(Ok(()))) {
+            activity.unqueued(len);
+        }
+        match sent {
+            Some(sent) => Ok(sent?),
+            None => Err(SessionError::InputTimeout(timeout.unwrap_or_default().as_millis() as u64).into()),
+        }
+    }
+
+    /// Queues input for the child if there is room, returning whether there was
+    pub fn try_send(&self, data: Vec<u8>) -> Result<bool> {
+        let (Some(input_tx), Some(activity)) = (&self.input_tx, &self.activity) else {
+            return Err(self.not_started().into());
+        };
+        let len = data.len();
+        activity.queued(len);
+        let sent = input_tx.try_send(data);
+        if sent.is_err() {
+            activity.unqueued(len);
+        }
+        match sent {
+            Ok(()) => Ok(true),
+            Err(mpsc::error::TrySendError::Full(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Sends text, each `\n` as `newline`
+    pub fn input(&self, input: &str, newline: Newline, timeout: Option<Duration>) -> Result<()> {
+        self.send(self.input_bytes(input, newline)?, timeout)
+    }
+
+    /// Pastes `text` as a terminal does, newlines as CR: wrapped in `ESC[200~` and `ESC[201~` when the child has
+    /// enabled bracketed paste (mode 2004), with any end marker inside left out, and as plain input otherwise
+    pub fn paste(&self, text: &str, timeout: Option<Duration>) -> Result<()> {
+        let text = match self.modes()?.contains(&BRACKETED_PASTE) {
+            true => format!("\x1b[200~{}\x1b[201~", text.replace("\x1b[201~", "")),
+            false => text.to_string(),
+        };
+        self.input(&text, Newline::Cr, timeout)
+    }
+
+    /// Sends keys such as `"Enter"`, `"C-c"` or `"S-Up"`, text that isn't a key name as is
+    pub fn keys(&self, keys: Vec<String>, timeout: Option<Duration>) -> Result<()> {
+        if self.input_tx.is_none() {
+            return Err(self.not_started().into());
+        }
+        self.send(self.key_bytes(keys)?, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_are_counted_as_queued_input() {
+        let command = "printf '\\033]4;1;?\\007'; echo ready; sleep 2";
+        let config = Config::new(vec!["sh".into(), "-c".into(), command.into()], 80, 24);
+        let mut session = Session::new(config).unwrap();
+        session.start().unwrap();
+        let (found, _) = session.wait_for_text("ready", Duration::from_secs(5)).unwrap();
+        assert!(found);
+        // the palette answer, written to the pty but not read by the sleeping child
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(session.activity.as_ref().unwrap().pending_input(), 0);
+        assert!(session.unread_input().unwrap() < 64);
+        session.close(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn waits_until_a_condition() {
+        let command = "sleep 0.2; printf '\\033]0;done\\007'; sleep 2";
+        let config = Config::new(vec!["sh".into(), "-c".into(), command.into()], 80, 24);
+        let mut session = Session::new(config).unwrap();
+        session.start().unwrap();
+        let waits = Waits { max: Some(Duration::from_secs(5)), ..Waits::default() };
+        let until = Until::Title(regex::Regex::new("^done$").unwrap());
+        let (reached, _) = session.wait_until(until, waits, Buffer::Active).unwrap();
+        assert!(reached);
+        session.close(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn brackets_pastes_when_the_child_asks() {
+        let command = "printf '\\033[?2004h'; stty raw -echo; echo ready; head -c 15 | cat -v; sleep 2";
+        let config = Config::new(vec!["sh".into(), "-c".into(), command.into()], 80, 24);
+        let mut session = Session::new(config).unwrap();
+        session.start().unwrap();
+        assert!(session.wait_for_text("ready", Duration::from_secs(5)).unwrap().0);
+        session.paste("a\nb", None).unwrap();
+        let (found, _) = session.wait_for_text("^[[200~a^Mb^[[201~", Duration::from_secs(5)).unwrap();
+        assert!(found);
+        session.close(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn waits_for_the_child_to_exit() {
+        let config = Config::new(vec!["sh".into(), "-c".into(), "sleep 0.3; exit 3".into()], 80, 24);
+        let mut session = Session::new(config).unwrap();
+        session.start().unwrap();
+        assert!(session.wait(Some(Duration::from_millis(10))).unwrap().is_none());
+        let status = session.wait(Some(Duration::from_secs(5))).unwrap().unwrap();
+        assert_eq!(status.code, Some(3));
+        // once it has exited, waiting again returns right away
+        let (found, _) = session.wait_for_text("never", Duration::from_secs(5)).unwrap();
+        assert!(!found);
+        assert_eq!(session.wait(None).unwrap().unwrap().code, Some(3));
+        session.close(Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,37 @@
+use crate::color::{foreground, rgb32_from_avt, DEFAULT_RGB32};
+use crate::palette::Palette;
+
+/// The dominant foreground color of each row, packed as in `rgb32_from_lines`: the most common
+/// among its non-blank cells, the first to get there on ties. None for blank rows.
+pub fn dominant_foregrounds(lines: &[avt::Line], palette: &Palette, effective: bool) -> Vec<Option<u32>> {
+    let fg = foreground(effective, palette);
+    lines.iter()
+        .map(|l| {
+            let mut counts: Vec<(u32, usize)> = vec![];
+            for cell in l.cells().iter().filter(|c| !c.char().is_whitespace()) {
+                let color = fg(cell.pen()).map(|c| rgb32_from_avt(c, palette)).unwrap_or(DEFAULT_RGB32);
+                match counts.iter_mut().find(|(c, _)| *c == color) {
+                    Some((_, n)) => *n += 1,
+                    None => counts.push((color, 1)),
+                }
+            }
+            // max_by_key keeps the last of equal maxima
+            counts.iter().rev().max_by_key(|(_, n)| *n).map(|(c, _)| *c)
+        })
+        .collect()
+}
+
+/// Labels each row with the first label in `mapping` whose colors include its dominant foreground,
+/// `other` if none does. Blank rows get no label.
+pub fn classify_rows(dominant: &[Option<u32>], mapping: &[(String, Vec<u32>)], other: Option<&str>) -> Vec<Option<String>> {
+    dominant.iter()
+        .map(|d| {
+            let color = (*d)?;
+            mapping.iter()
+                .find(|(_, colors)| colors.contains(&color))
+                .map(|(label, _)| label.as_str())
+                .or(other)
+                .map(str::to_string)
+        })
+        .collect()
+}
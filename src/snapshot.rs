@@ -0,0 +1,398 @@
+#[cfg(feature = "python")]
+use crate::boxes::boxes_from_lines;
+use crate::cell::Cell;
+use crate::cursor::Cursor;
+#[cfg(feature = "export")]
+use crate::export::{write_animation, Format, Rasterizer};
+#[cfg(feature = "python")]
+use crate::geometry::Bounds;
+#[cfg(feature = "python")]
+use crate::ignore::Ignore;
+#[cfg(feature = "python")]
+use crate::lines::{chars_from_lines, continued_from_lines, indexedcolor_from_lines, links_from_lines};
+use crate::lines::hash_from_lines;
+#[cfg(feature = "python")]
+use crate::lines::{nfc_chars_from_lines, reading_text_from_lines, render_lines, rows_from_lines};
+#[cfg(feature = "python")]
+use crate::lines::{text_from_lines_with, TextOptions};
+use crate::palette::Palette;
+use crate::protocol::Capture;
+#[cfg(feature = "python")]
+use crate::table::table_from_lines;
+#[cfg(feature = "export")]
+use crate::theme::Theme;
+
+#[cfg(feature = "python")]
+use ndarray::{stack, Array2, Array3, ArrayView2, Axis};
+#[cfg(feature = "python")]
+use numpy::{PyArray1, PyArray2, PyArray3};
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyIndexError, PyValueError};
+#[cfg(all(feature = "python", feature = "export"))]
+use pyo3::exceptions::PyOSError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+#[cfg(feature = "python")]
+use pyo3::types::PyTuple;
+use std::collections::BTreeMap;
+#[cfg(feature = "export")]
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "python")]
+type StackedColorMatrices<'py> = (Bound<'py, PyArray3<u8>>, Bound<'py, PyArray3<bool>>);
+
+/// A capture of the terminal contents taken when the Terminal settled
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone)]
+pub struct Snapshot {
+    pub lines: Vec<avt::Line>,
+    /// Attributes avt doesn't keep, see `Extended`
+    pub extended: Vec<avt::Line>,
+    pub cursor: Cursor,
+    /// DEC line size of each row, `LINE_SINGLE` etc.
+    pub line_sizes: Vec<u8>,
+    /// URI of each hyperlink id in `extended`
+    pub links: BTreeMap<u16, String>,
+    pub alt_screen: bool,
+    pub timestamp: f64,
+    /// Colors as of the capture, for conversions
+    pub palette: Palette,
+}
+
+impl Snapshot {
+    pub fn new(capture: Capture, palette: Palette) -> Self {
+        let Capture { lines, extended, cursor, line_sizes, links, alt_screen } = capture;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        Snapshot {
+            lines,
+            extended,
+            cursor,
+            line_sizes,
+            links,
+            alt_screen,
+            timestamp,
+            palette,
+        }
+    }
+
+    /// The cell at `row` and `col` (0-based), None if out of range; with `effective` its colors
+    /// are those actually seen, see `color::foreground`
+    pub fn cell(&self, row: usize, col: usize, effective: bool) -> Option<Cell> {
+        Cell::new(&self.lines, &self.extended, (row, col), effective, &self.palette)
+    }
+
+    /// A stable hash of the cells, see `hash_from_lines`
+    pub fn screen_hash(&self) -> u64 {
+        hash_from_lines(&self.lines, &self.extended, &self.palette)
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        let rows = self.lines.len();
+        let cols = self.lines.first().map(|l| l.len()).unwrap_or(0);
+        (rows, cols)
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Snapshot {
+    /// Seconds since the UNIX epoch at which the snapshot was captured, like `time.time()`
+    #[getter]
+    pub fn timestamp(&self) -> f64 {
+        self.timestamp
+    }
+
+    /// The cursor as of the capture
+    #[getter]
+    pub fn cursor(&self) -> Cursor {
+        self.cursor.clone()
+    }
+
+    /// Whether the snapshot is of the alternate screen
+    #[getter]
+    pub fn alt_screen(&self) -> bool {
+        self.alt_screen
+    }
+
+    /// Retrieves a _rows_ x _cols_ `u32` matrix of UCS-4 (unicode) code points,
+    /// NFC-normalized with `normalize` like `Terminal.chars()`.
+    #[pyo3(signature = (*, normalize=false))]
+    pub fn chars<'py>(&self, py: Python<'py>, normalize: bool) -> Bound<'py, PyArray2<u32>> {
+        let chars = if normalize {
+            nfc_chars_from_lines(&self.lines)
+        } else {
+            chars_from_lines(&self.lines)
+        };
+        PyArray2::from_owned_array(py, chars)
+    }
+
+    /// Retrieves a text string with the text context of the snapshot, lines terminated by `\n`,
+    /// cleaned up or with controls shown with the same options as `Terminal.text()`
+    #[pyo3(signature = (*, strip_trailing=false, trim_blank_lines=false, join_wrapped=false, show_controls=false))]
+    pub fn text(&self, strip_trailing: bool, trim_blank_lines: bool, join_wrapped: bool, show_controls: bool) -> String {
+        let options = TextOptions { strip_trailing, trim_blank_lines, join_wrapped, show_controls };
+        text_from_lines_with(&self.lines, options)
+    }
+
+    /// Retrieves a list with the text of each row, like `Terminal.rows()`
+    #[pyo3(signature = (*, strip_trailing=false))]
+    pub fn rows(&self, strip_trailing: bool) -> Vec<String> {
+        rows_from_lines(&self.lines, strip_trailing)
+    }
+
+    /// Retrieves a _rows_ `bool` vector, True for soft-wrapped continuation rows, like `Terminal.continued_rows()`
+    pub fn continued_rows<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<bool>> {
+        PyArray1::from_owned_array(py, continued_from_lines(&self.lines))
+    }
+
+    /// Retrieves a _rows_ `u8` vector with the line size of each row, like `Terminal.line_sizes()`
+    pub fn line_sizes<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u8>> {
+        PyArray1::from_vec(py, self.line_sizes.clone())
+    }
+
+    /// Retrieves a tuple with a _rows_ x _cols_ `u16` matrix of hyperlink ids and a dict with their URIs,
+    /// like `Terminal.hyperlinks()`
+    pub fn hyperlinks<'py>(&self, py: Python<'py>) -> (Bound<'py, PyArray2<u16>>, BTreeMap<u16, String>) {
+        (PyArray2::from_owned_array(py, links_from_lines(&self.extended)), self.links.clone())
+    }
+
+    /// Retrieves a list of paragraphs of the text in human reading order: lines that wrapped are joined,
+    /// box-drawing and decoration (rules, borders) dropped and whitespace collapsed, for assertions on text
+    /// that looks fine on screen but is awkward cell by cell.
+    pub fn reading_text(&self) -> Vec<String> {
+        reading_text_from_lines(&self.lines)
+    }
+
+    /// Like `text()` but with the colors and the attributes (bold, italic, underline, inverse...) as SGR sequences.
+    /// Concealed text (SGR 8) is rendered concealed too, unless `reveal`.
+    #[pyo3(signature = (*, reveal=false))]
+    pub fn render(&self, reveal: bool) -> String {
+        render_lines(&self.lines, &self.extended, &self.palette, reveal)
+    }
+
+    /// Retrieves the snapshot as JSON, for tools other than Python and golden files, in the layout described in the
+    /// README: `cols`, `rows`, the `cells` row by row, the `cursor` and the `palette`
+    #[cfg(feature = "json")]
+    #[pyo3(name = "to_json")]
+    pub fn py_to_json(&self) -> String {
+        self.to_json()
+    }
+
+    /// A snapshot from JSON as written by `to_json()`
+    #[cfg(feature = "json")]
+    #[staticmethod]
+    #[pyo3(name = "from_json")]
+    pub fn py_from_json(json: &str) -> PyResult<Snapshot> {
+        Snapshot::from_json(json).map_err(PyValueError::new_err)
+    }
+
+    /// Retrieves the bounds of the rectangles drawn with box-drawing characters, like `Terminal.boxes()`
+    #[pyo3(signature = (*, panes=false))]
+    pub fn boxes(&self, panes: bool) -> Vec<Bounds> {
+        boxes_from_lines(&self.lines, panes)
+    }
+
+    /// Retrieves the rows of a table in `region`, like `Terminal.table()`
+    #[pyo3(signature = (region=None, *, separator=None, min_gap=1))]
+    pub fn table<'py>(
+        &self,
+        py: Python<'py>,
+        region: Option<Bounds>,
+        separator: Option<char>,
+        min_gap: usize,
+    ) -> PyResult<Vec<Bound<'py, PyTuple>>> {
+        let table = table_from_lines(&self.lines, region, separator, min_gap)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        table.into_iter().map(|row| PyTuple::new(py, row)).collect()
+    }
+
+    /// Retrieves a _rows_ x _cols_ `bool` matrix, True where the cell (character or pen) differs in `other`,
+    /// which must have the same shape. Cells in any of the `ignore_regions` (`(top, left, bottom, right)`,
+    /// inclusive) or matched by any of the `ignore_patterns` (regular expressions matched against the text
+    /// of each row, in either snapshot) don't count, e.g. clocks and timestamps.
+    #[pyo3(signature = (other, *, ignore_regions=None, ignore_patterns=None))]
+    pub fn diff<'py>(
+        &self,
+        py: Python<'py>,
+        other: PyRef<Snapshot>,
+        ignore_regions: Option<Vec<Bounds>>,
+        ignore_patterns: Option<Vec<String>>,
+    ) -> PyResult<Bound<'py, PyArray2<bool>>> {
+        if self.shape() != other.shape() {
+            return Err(PyValueError::new_err("snapshots have different shapes"));
+        }
+        let ignore = Ignore::new(ignore_regions, ignore_patterns)?;
+        Ok(PyArray2::from_owned_array(py, ignore.diff(&self.lines, &other.lines)))
+    }
+
+    /// Compares the text with a golden copy, `expected`, row by row, raising an AssertionError listing the rows
+    /// that differ. Trailing blanks don't count, missing rows at the end of `expected` are taken as blank,
+    /// and cells in `ignore_regions` or matched by `ignore_patterns` (as in `diff()`) are left out.
+    #[pyo3(signature = (expected, *, ignore_regions=None, ignore_patterns=None))]
+    pub fn assert_screen(
+        &self,
+        expected: &str,
+        ignore_regions: Option<Vec<Bounds>>,
+        ignore_patterns: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        Ignore::new(ignore_regions, ignore_patterns)?.assert_text(&self.lines, expected)
+    }
+}
+
+/// An ordered sequence of snapshots, for time-series analysis
+#[cfg(feature = "python")]
+#[pyclass]
+#[derive(Default)]
+pub struct SnapshotSeries {
+    frames: Vec<Snapshot>,
+}
+
+#[cfg(feature = "python")]
+impl From<Vec<Snapshot>> for SnapshotSeries {
+    fn from(frames: Vec<Snapshot>) -> Self {
+        SnapshotSeries { frames }
+    }
+}
+
+#[cfg(feature = "python")]
+impl SnapshotSeries {
+    fn check_shapes(&self) -> PyResult<(usize, usize)> {
+        let shape = self.frames.first().map(|f| f.shape()).unwrap_or((0, 0));
+        if self.frames.iter().any(|f| f.shape() != shape) {
+            return Err(PyValueError::new_err("snapshots have different shapes"));
+        }
+        Ok(shape)
+    }
+
+    fn stack2<T: Clone>(&self, matrices: Vec<Array2<T>>) -> PyResult<Array3<T>> {
+        let (rows, cols) = self.check_shapes()?;
+        if matrices.is_empty() {
+            return Ok(Array3::from_shape_vec([0, rows, cols], vec![]).unwrap());
+        }
+        let views: Vec<ArrayView2<T>> = matrices.iter().map(|m| m.view()).collect();
+        stack(Axis(0), &views).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn stack_indexedcolor<'py, F>(&self, py: Python<'py>, f: F) -> PyResult<StackedColorMatrices<'py>>
+    where
+        F: Fn(&avt::Pen) -> Option<avt::Color> + Copy,
+    {
+        let (colors, masks): (Vec<_>, Vec<_>) = self
+            .frames
+            .iter()
+            .map(|s| indexedcolor_from_lines(&s.lines, f))
+            .unzip();
+        Ok((
+            PyArray3::from_owned_array(py, self.stack2(colors)?),
+            PyArray3::from_owned_array(py, self.stack2(masks)?),
+        ))
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SnapshotSeries {
+    #[new]
+    pub fn py_new() -> Self {
+        SnapshotSeries::default()
+    }
+
+    /// Append a snapshot at the end of the series
+    pub fn append(&mut self, snapshot: PyRef<Snapshot>) {
+        self.frames.push(snapshot.clone());
+    }
+
+    pub fn __len__(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn __getitem__(&self, index: isize) -> PyResult<Snapshot> {
+        let len = self.frames.len() as isize;
+        let i = if index < 0 { index + len } else { index };
+        if i < 0 || i >= len {
+            return Err(PyIndexError::new_err("snapshot index out of range"));
+        }
+        Ok(self.frames[i as usize].clone())
+    }
+
+    /// Retrieves a `f64` vector with the capture timestamp of each frame, in seconds since the UNIX epoch
+    pub fn timestamps<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<f64>> {
+        let v: Vec<f64> = self.frames.iter().map(|f| f.timestamp).collect();
+        PyArray1::from_vec(py, v)
+    }
+
+    /// Retrieves a _frames_ x _rows_ x _cols_ `u32` matrix of UCS-4 (unicode) code points.
+    /// All frames must have the same shape.
+    pub fn stack_chars<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray3<u32>>> {
+        let chars = self.frames.iter().map(|s| chars_from_lines(&s.lines)).collect();
+        Ok(PyArray3::from_owned_array(py, self.stack2(chars)?))
+    }
+
+    /// Retrieves a tuple with a _frames_ x _rows_ x _cols_ `u8` matrix of foreground colors (0 if default)
+    /// and a corresponding mask, stacked like `foreground_indexedcolor()` of each frame.
+    pub fn stack_fg<'py>(&self, py: Python<'py>) -> PyResult<StackedColorMatrices<'py>> {
+        self.stack_indexedcolor(py, |pen| pen.foreground())
+    }
+
+    /// Retrieves a tuple with a _frames_ x _rows_ x _cols_ `u8` matrix of background colors (0 if default)
+    /// and a corresponding mask, stacked like `background_indexedcolor()` of each frame.
+    pub fn stack_bg<'py>(&self, py: Python<'py>) -> PyResult<StackedColorMatrices<'py>> {
+        self.stack_indexedcolor(py, |pen| pen.background())
+    }
+
+    /// Retrieves a list with one _rows_ x _cols_ `bool` matrix per pair of consecutive frames,
+    /// where an element is True if the cell (character or pen) changed from one frame to the next.
+    /// Cells in `ignore_regions` or matched by `ignore_patterns` don't count, see `Snapshot.diff()`.
+    #[pyo3(signature = (*, ignore_regions=None, ignore_patterns=None))]
+    pub fn diffs<'py>(
+        &self,
+        py: Python<'py>,
+        ignore_regions: Option<Vec<Bounds>>,
+        ignore_patterns: Option<Vec<String>>,
+    ) -> PyResult<Vec<Bound<'py, PyArray2<bool>>>> {
+        self.check_shapes()?;
+        let ignore = Ignore::new(ignore_regions, ignore_patterns)?;
+        let diffs = self
+            .frames
+            .windows(2)
+            .map(|w| ignore.diff(&w[0].lines, &w[1].lines))
+            .map(|diff| PyArray2::from_owned_array(py, diff))
+            .collect();
+        Ok(diffs)
+    }
+
+    /// Saves the series as an animated GIF (.gif) or APNG (.png or .apng), each frame drawn with
+    /// its effective colors and shown for as long as it lasted when captured, divided by `speed`
+    /// (the last one for a second). Drawn with the colors, font and cell size of `theme`,
+    /// by default the standard palette and the first of some common monospace fonts found installed.
+    /// Video formats such as webm are not supported, they take a video encoder that numpty doesn't bundle:
+    /// convert the GIF or APNG with ffmpeg.
+    #[cfg(feature = "export")]
+    #[pyo3(signature = (path, *, theme=None, speed=1.0))]
+    pub fn save_animation(
+        &self,
+        py: Python<'_>,
+        path: PathBuf,
+        theme: Option<Theme>,
+        speed: f64,
+    ) -> PyResult<()> {
+        if self.frames.is_empty() {
+            return Err(PyValueError::new_err("no frames"));
+        }
+        if speed <= 0.0 {
+            return Err(PyValueError::new_err("speed must be positive"));
+        }
+        let format = Format::from_path(&path).ok_or_else(|| {
+            PyValueError::new_err("unsupported format, use .gif, .png or .apng (and ffmpeg to convert to video)")
+        })?;
+        py.allow_threads(|| {
+            let mut rasterizer = Rasterizer::new(&theme.unwrap_or_default())?;
+            write_animation(&path, format, &self.frames, &mut rasterizer, speed)
+        })
+        .map_err(|e| PyOSError::new_err(e.to_string()))
+    }
+}
@@ -0,0 +1,85 @@
+// Why a command couldn't be run, worked out in the parent once exec has failed in the child,
+// so "not installed" can be told apart from "not executable".
+
+use crate::pty::ExecError;
+use nix::unistd::{access, AccessFlags};
+#[cfg(feature = "python")]
+use pyo3::create_exception;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyOSError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use std::env;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "python")]
+create_exception!(
+    numpty,
+    SpawnError,
+    PyOSError,
+    "The command could not be run: `errno` and `strerror` as for OSError, plus `program`, the directories \
+     `searched` in PATH, the `path` found (None if not found) and whether it was `executable`."
+);
+
+// what execvp searches when PATH isn't set
+const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+/// Where execvp looks for a program, and what it finds
+pub struct Lookup {
+    /// The PATH entries searched, empty for a program given as a path
+    pub searched: Vec<String>,
+    /// The first file found, an executable one if any
+    pub path: Option<PathBuf>,
+    pub executable: bool,
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.is_file() && access(path, AccessFlags::X_OK).is_ok()
+}
+
+pub fn lookup(program: &str) -> Lookup {
+    if program.contains('/') {
+        let path = Path::new(program);
+        return Lookup {
+            searched: vec![],
+            path: path.exists().then(|| path.to_path_buf()),
+            executable: is_executable(path),
+        };
+    }
+    let var = env::var("PATH").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+    // an empty entry is the current directory
+    let searched: Vec<String> = var.split(':').map(|d| if d.is_empty() { "." } else { d }.to_string()).collect();
+    let candidates: Vec<PathBuf> = searched.iter().map(|d| Path::new(d).join(program)).collect();
+    match candidates.iter().find(|p| is_executable(p)) {
+        Some(path) => Lookup { path: Some(path.clone()), executable: true, searched },
+        None => Lookup { path: candidates.into_iter().find(|p| p.exists()), executable: false, searched },
+    }
+}
+
+#[cfg(feature = "python")]
+/// A `SpawnError` for a failed exec of `program`
+pub fn spawn_error(py: Python<'_>, program: &str, e: &ExecError) -> PyErr {
+    let lookup = lookup(program);
+    let problem = match (&lookup.path, lookup.executable) {
+        (None, _) if lookup.searched.is_empty() => format!("{} doesn't exist", program),
+        (None, _) => format!("{} not found in PATH ({})", program, lookup.searched.join(":")),
+        (Some(path), false) => format!("{} is not executable", path.display()),
+        (Some(_), true) => format!("can't run {}", program),
+    };
+    let message = format!("{}: {}", problem, e.message);
+    let err = match e.errno {
+        Some(errno) => SpawnError::new_err((errno, message)),
+        None => SpawnError::new_err(message),
+    };
+    let value = err.value(py);
+    let attrs = || -> PyResult<()> {
+        value.setattr("program", program)?;
+        value.setattr("searched", lookup.searched)?;
+        value.setattr("path", lookup.path.map(|p| p.to_string_lossy().into_owned()))?;
+        value.setattr("executable", lookup.executable)
+    };
+    match attrs() {
+        Ok(()) => err,
+        Err(e) => e,
+    }
+}
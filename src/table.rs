@@ -0,0 +1,98 @@
+use crate::geometry::Bounds;
+use crate::lines::chars_from_lines;
+use anyhow::{anyhow, Result};
+
+// characters horizontal rules between table rows are made of
+fn is_rule(c: char) -> bool {
+    matches!(c, ' ' | '-' | '=' | '+' | '|' | ':' | '─'..='╿')
+}
+
+// runs of non-blank columns as (start, end), separated by at least `min_gap` columns
+// that are blank in every row
+fn columns(rows: &[Vec<char>], min_gap: usize) -> Vec<(usize, usize)> {
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let blank = |col: usize| rows.iter().all(|r| r.get(col).is_none_or(|c| c.is_whitespace()));
+
+    let mut columns: Vec<(usize, usize)> = vec![];
+    let mut col = 0;
+    while col < width {
+        if blank(col) {
+            col += 1;
+            continue;
+        }
+        let start = col;
+        let mut end = col;
+        let mut gap = 0;
+        while col < width && gap < min_gap {
+            if blank(col) {
+                gap += 1;
+            } else {
+                gap = 0;
+                end = col + 1;
+            }
+            col += 1;
+        }
+        columns.push((start, end));
+    }
+    columns
+}
+
+/// Parses the region of the screen as a table: one row per line, leaving out blank lines and
+/// horizontal rules, split into fields at `separator` characters if given (a leading or trailing
+/// one being a border), else at runs of at least `min_gap` columns blank in every line.
+/// Fields are stripped of surrounding whitespace.
+pub fn table_from_lines(
+    lines: &[avt::Line],
+    bounds: Option<Bounds>,
+    separator: Option<char>,
+    min_gap: usize,
+) -> Result<Vec<Vec<String>>> {
+    let chars = chars_from_lines(lines);
+    let (rows, cols) = chars.dim();
+    // an empty screen has an empty table, but no region inside it
+    if bounds.is_none() && (rows == 0 || cols == 0) {
+        return Ok(vec![]);
+    }
+    let (top, left, bottom, right) = bounds.unwrap_or((0, 0, rows.saturating_sub(1), cols.saturating_sub(1)));
+    if top > bottom || left > right || bottom >= rows || right >= cols {
+        return Err(anyhow!("region out of bounds"));
+    }
+
+    let region: Vec<Vec<char>> = (top..=bottom)
+        .map(|row| {
+            (left..=right)
+                .map(|col| char::from_u32(chars[[row, col]]).unwrap_or(' '))
+                .collect()
+        })
+        .filter(|r: &Vec<char>| !r.iter().all(|&c| is_rule(c)))
+        .collect();
+
+    let table = match separator {
+        Some(separator) => region
+            .iter()
+            .map(|r| {
+                let line: String = r.iter().collect();
+                let line = line.trim();
+                let line = line.strip_prefix(separator).unwrap_or(line);
+                let line = line.strip_suffix(separator).unwrap_or(line);
+                line.split(separator).map(|f| f.trim().to_string()).collect()
+            })
+            .collect(),
+        None => {
+            let columns = columns(&region, min_gap.max(1));
+            region
+                .iter()
+                .map(|r| {
+                    columns
+                        .iter()
+                        .map(|&(start, end)| {
+                            let field: String = r.iter().take(end).skip(start).collect();
+                            field.trim().to_string()
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    };
+    Ok(table)
+}
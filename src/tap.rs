@@ -0,0 +1,39 @@
+// Copies of the child's output for readers outside numpty, each through a pipe whose write end is kept
+// here. Reading the pty master itself would take the output away from the emulator.
+
+#[cfg(feature = "python")]
+use crate::nbio;
+#[cfg(feature = "python")]
+use nix::fcntl::OFlag;
+#[cfg(feature = "python")]
+use nix::unistd::pipe2;
+use std::fs::File;
+#[cfg(feature = "python")]
+use std::io;
+use std::io::Write;
+#[cfg(feature = "python")]
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+pub struct Taps(Arc<Mutex<Vec<File>>>);
+
+impl Taps {
+    /// A new tap, returning the read end of its pipe
+    #[cfg(feature = "python")]
+    pub fn open(&self) -> io::Result<OwnedFd> {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+        nbio::set_non_blocking(&write.as_raw_fd())?;
+        self.0.lock().unwrap().push(File::from(write));
+        Ok(read)
+    }
+
+    /// Copies `data` to every tap, closing those whose reader has gone or fallen behind by more than
+    /// the pipe can hold, so it reads end of file rather than output with a gap in it
+    pub fn write(&self, data: &[u8]) {
+        let mut taps = self.0.lock().unwrap();
+        if !taps.is_empty() {
+            taps.retain_mut(|tap| tap.write_all(data).is_ok());
+        }
+    }
+}
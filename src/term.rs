@@ -1,70 +1,482 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use tokio::task::JoinError;
-use tokio::time::{sleep, Duration, Instant};
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
 
-use crate::protocol::{Reply, Req};
+use crate::activity::Activity;
+use crate::bells::{rings, SharedBells};
+use crate::clipboard::SharedClipboard;
+use crate::cursor::Cursor;
+use crate::encoding::{Codec, Decoder};
+use crate::escapes::{printed_text, Event, Scanner};
+use crate::exit::{ExitStatus, Failures};
+use crate::extended::Extended;
+#[cfg(feature = "recording")]
+use crate::health::LastError;
+use crate::keys::{KeyEncoding, SharedKeyEncoding};
+use crate::labels::Labels;
+use crate::metrics::{self, Session, METRICS};
+use crate::modes::{cursor_visibility, ends_frame, switches_screen, Modes, SYNCHRONIZED_OUTPUT};
+use crate::palette::Palette;
+use crate::procfs;
+use crate::lines::{cells_in, text_from_lines};
+use crate::protocol::{Buffer, Capture, Output, Redraw, Reply, Req, Until, Wait, Waits};
+use crate::recent::{RecentOutput, RECENT_OUTPUT_CAPACITY};
+#[cfg(feature = "recording")]
+use crate::recording::Recorder;
+use crate::reports::SharedCursorReports;
+use crate::snapshot::Snapshot;
+use crate::tap::Taps;
+use crate::title::SharedTitles;
 
+/// What the term task observes of the child and shares with the Terminal
+#[derive(Clone, Default)]
+pub struct Observed {
+    pub failures: Failures,
+    /// How the child exited for good, not to be restarted
+    pub exit_status: Arc<Mutex<Option<ExitStatus>>>,
+    pub key_encoding: SharedKeyEncoding,
+    /// Whether the child has used synchronized output (DEC mode 2026)
+    pub synchronized_output: Arc<AtomicBool>,
+    pub palette: Arc<Mutex<Palette>>,
+    pub cursor_reports: SharedCursorReports,
+    /// Pipes the output is copied to, see `Terminal.read_fd()`
+    pub taps: Taps,
+    pub titles: SharedTitles,
+    pub bells: SharedBells,
+    pub clipboard: SharedClipboard,
+    /// What the Terminal is called, for log lines
+    pub labels: Arc<Labels>,
+}
+
+// how long settling at frame boundaries waits for a synchronized update to end,
+// like terminals do before drawing anyway
+const SYNCHRONIZED_UPDATE_TIMEOUT: Duration = Duration::from_secs(1);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// text kept for a request waiting on output, older text is dropped beyond this
+const MAX_SEEN_OUTPUT: usize = 1024 * 1024;
+
+// consecutive polls without output that must find the child blocked on input,
+// so output written just before blocking has had time to come through
+const BLOCKED_POLLS: u32 = 2;
+
+// the screen as it is
+fn capture(vt: &avt::Vt, extended: &Extended, modes: &Modes) -> Capture {
+    let extended_lines = extended.view();
+    Capture {
+        lines: vt.view().to_vec(),
+        links: extended.links_in(&extended_lines),
+        extended: extended_lines,
+        cursor: Cursor::new(vt, modes.cursor_style()),
+        line_sizes: extended.line_sizes(),
+        alt_screen: modes.alt_screen(),
+    }
+}
+
+// screens captured earlier that replies can be of
+#[derive(Default)]
+struct Saved {
+    // as of the last frame boundary
+    frame: Option<Capture>,
+    // the primary screen as the child switched to the alternate one
+    primary: Option<Capture>,
+    // the alternate screen as the child switched back from it
+    alternate: Option<Capture>,
+    // the scrollback of the primary screen as the child switched to the alternate one, which has none
+    history: Vec<avt::Line>,
+}
+
+impl Saved {
+    // the screen in `buffer` if the child isn't on it, as last seen, or blank if never
+    fn inactive(&self, buffer: Buffer, vt: &avt::Vt, modes: &Modes) -> Option<Capture> {
+        match (buffer, modes.alt_screen()) {
+            (Buffer::Primary, true) => self.primary.clone(),
+            (Buffer::Alternate, false) => Some(self.alternate.clone().unwrap_or_else(|| {
+                let (cols, rows) = vt.size();
+                let blank = capture(&avt::Vt::new(cols, rows), &Extended::new(cols, rows), &Modes::default());
+                Capture { alt_screen: true, ..blank }
+            })),
+            _ => None,
+        }
+    }
+
+    // the lines scrolled off the top of the primary screen, at most `scrollback` of them
+    fn history(&self, vt: &avt::Vt, modes: &Modes, scrollback: usize) -> Vec<avt::Line> {
+        let lines = if modes.alt_screen() { &self.history[..] } else { scrolled(vt) };
+        lines[lines.len().saturating_sub(scrollback)..].to_vec()
+    }
+}
+
+// the lines above the screen
+fn scrolled(vt: &avt::Vt) -> &[avt::Line] {
+    let lines = vt.lines();
+    &lines[..lines.len() - vt.size().1]
+}
+
+// the inactive screen if asked for, else the screen of the last complete frame if asked for and there's been one,
+// else the current one
+fn reply(
+    vt: &avt::Vt,
+    extended: &Extended,
+    modes: &Modes,
+    saved: &Saved,
+    wait: &Wait,
+    error: &Option<String>,
+    reached: bool,
+) -> Reply {
+    let capture = saved
+        .inactive(wait.buffer, vt, modes)
+        .or_else(|| saved.frame.clone().filter(|_| wait.at_frame))
+        .unwrap_or_else(|| capture(vt, extended, modes));
+    Reply {
+        capture,
+        reached,
+        error: error.clone(),
+    }
+}
+
+// whether a request waiting on a condition can be answered right away, `ready` being
+// whether the child has printed something or switched to the alternate screen
+fn satisfied(wait: &Wait, modes: &Modes, vt: &avt::Vt, ready: bool, title: Option<&str>) -> bool {
+    match &wait.until {
+        Until::Settled | Until::SettledIn { .. } | Until::Redrawn(_) => false,
+        Until::AltScreen(alt_screen) => modes.alt_screen() == *alt_screen,
+        Until::Ready => ready,
+        Until::BlockedOnInput(_) => false,
+        Until::Text(text) => text_from_lines(vt.view()).contains(text.as_str()),
+        Until::Changed(lines) => lines.as_ref().is_some_and(|lines| lines.as_slice() != vt.view()),
+        Until::Prompt(prompts) => {
+            let cursor = vt.cursor();
+            let line: String = vt.view()[cursor.row].chars().take(cursor.col).collect();
+            prompts.iter().any(|p| line.ends_with(p.as_str()))
+        }
+        Until::Output { regex, seen, .. } => regex.is_match(seen),
+        Until::Title(regex) => title.is_some_and(|title| regex.is_match(title)),
+        Until::Exited => false,
+    }
+}
+
+fn never() -> Instant {
+    Instant::now() + Duration::from_millis(9999999999)
+}
+
+// when a request waiting since `started`, with the last output since then at `output`, gives up
+// (or, when settling, is settled)
+fn give_up_at(wait: &Wait, started: Instant, output: Option<Instant>) -> Instant {
+    let waits = &wait.waits;
+    let limits = [
+        waits.max.map(|max| started + max),
+        match output {
+            None => waits.first.map(|first| started + first),
+            Some(output) => waits.more.map(|more| output + more),
+        },
+    ];
+    limits.into_iter().flatten().min().unwrap_or_else(never)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run_term(
-    cols: usize,
-    rows: usize,
-    mut output_rx: mpsc::Receiver<Vec<u8>>,
+    mut cols: usize,
+    mut rows: usize,
+    scrollback: usize,
+    mut output_rx: mpsc::Receiver<Output>,
     mut req_rx: mpsc::Receiver<Req>,
+    input_tx: mpsc::Sender<Vec<u8>>,
+    activity: Arc<Activity>,
+    observed: Observed,
+    codec: Option<Arc<Codec>>,
+    #[cfg(feature = "recording")] mut recorder: Option<Recorder>,
+    #[cfg(feature = "recording")] last_error: LastError,
     token: CancellationToken,
 ) -> Result<(), JoinError> {
     tokio::spawn(async move {
-        let mut maybe_waiting: Option<Req> = None;
-        let mut req_until = Instant::now() + Duration::from_millis(9999999999);
+        // counted as running until the task ends, or is dropped with the runtime
+        let _session = Session::new();
+        let mut maybe_waiting: Option<Wait> = None;
+        let mut req_until = never();
+        let mut req_started = Instant::now();
 
         let mut closed_output = false;
 
-        let mut vt = avt::Vt::builder().size(cols, rows).build();
+        let mut vt = avt::Vt::builder().size(cols, rows).scrollback_limit(scrollback).build();
+        let mut scanner = Scanner::new();
+        let mut extended = Extended::new(cols, rows);
+        // output is UTF-8 unless decoded with a codec
+        let mut decoder = codec.clone().map(Decoder::new);
+        let mut modes = Modes::default();
+        // whether the child has printed something or switched to the alternate screen
+        let mut ready = false;
+        let mut saved = Saved::default();
+        let mut last_output = Instant::now();
+        let mut recent = RecentOutput::new(RECENT_OUTPUT_CAPACITY);
         let error: Option<String> = None;
 
+        let mut poll = interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut blocked_polls = 0;
+
         let (_, mut never_rx) = mpsc::channel(1);
 
         loop {
             let now = Instant::now();
             let wait = req_until - now;
+            let polling = matches!(
+                maybe_waiting.as_ref().map(|w| &w.until),
+                Some(Until::BlockedOnInput(_))
+            );
 
             tokio::select! {
 
                 maybe_out = if closed_output {never_rx.recv()} else {output_rx.recv()} => {
                     match maybe_out {
-                        Some(data) => {
-                            vt.feed_str(&String::from_utf8_lossy(&data).to_string());
-
-                            // got output, unsettling, reset wait
-                            match &maybe_waiting {
-                                Some(waiting) => {
-                                    req_until = now + waiting.wait_more;
+                        Some(Output::Data(data)) => {
+                            recent.push(&data);
+                            metrics::add(&METRICS.output_bytes, data.len() as u64);
+                            observed.taps.write(&data);
+                            let text = match &mut decoder {
+                                Some(decoder) => Cow::Owned(decoder.decode(&data)),
+                                None => String::from_utf8_lossy(&data),
+                            };
+                            #[cfg(feature = "recording")]
+                            if let Some(Err(e)) = recorder.as_mut().map(|r| r.output(&text)) {
+                                *last_error.lock().unwrap() = Some(format!("recording stopped: {}", e));
+                                recorder = None;
+                            }
+                            let mut fed = 0;
+                            for (i, c) in text.char_indices() {
+                                let event = scanner.feed(c);
+                                if let Some(entering) = event.as_ref().and_then(|e| switches_screen(e, &modes)) {
+                                    // the screen being left, as it is before the sequence ending here
+                                    vt.feed_str(&text[fed..i]);
+                                    extended.flush();
+                                    fed = i;
+                                    let left = Some(capture(&vt, &extended, &modes));
+                                    if entering {
+                                        saved.primary = left;
+                                        saved.history = scrolled(&vt).to_vec();
+                                    } else {
+                                        saved.alternate = left;
+                                    }
+                                }
+                                extended.feed(c, &event, scanner.in_sequence());
+                                if !ready && !c.is_whitespace() && scanner.printed(c, &event) {
+                                    ready = true;
+                                }
+                                if let Some(Wait { until: Until::Output { seen, .. }, .. }) = &mut maybe_waiting {
+                                    if scanner.printed(c, &event) {
+                                        seen.push(c);
+                                    }
+                                }
+                                let Some(event) = event else {
+                                    continue;
+                                };
+                                if let Some(visual) = rings(&event, &modes) {
+                                    observed.bells.lock().unwrap().ring(visual);
+                                }
+                                modes.apply(&event);
+                                if let Event::Osc(osc) = &event {
+                                    observed.titles.lock().unwrap().apply(osc);
+                                    let clipboard = observed.clipboard.lock().unwrap().apply(osc);
+                                    let palette = observed.palette.lock().unwrap().apply(osc);
+                                    for answer in [clipboard, palette].into_iter().flatten() {
+                                        // counted as queued input like what the session sends, the pty task
+                                        // uncounts whatever it takes
+                                        let len = answer.len();
+                                        activity.queued(len);
+                                        if input_tx.try_send(answer.into_bytes()).is_err() {
+                                            // the child may be gone
+                                            activity.unqueued(len);
+                                        }
+                                    }
+                                }
+                                if let Event::Csi { prefix: prefix @ (None | Some('?')), params, intermediates, action: 'n' } = &event {
+                                    if params == "6" && intermediates.is_empty() {
+                                        // the cursor as of this request
+                                        let end = i + c.len_utf8();
+                                        vt.feed_str(&text[fed..end]);
+                                        extended.flush();
+                                        fed = end;
+                                        let cursor = vt.cursor();
+                                        let mut reports = observed.cursor_reports.lock().unwrap();
+                                        let answer = reports.answer(cursor.row, cursor.col.min(cols - 1), prefix.is_some());
+                                        if let Some(answer) = answer {
+                                            let (input_tx, delay) = (input_tx.clone(), reports.delay);
+                                            let activity = activity.clone();
+                                            tokio::spawn(async move {
+                                                sleep(delay).await;
+                                                let len = answer.len();
+                                                activity.queued(len);
+                                                if input_tx.send(answer.into_bytes()).await.is_err() {
+                                                    // the child may be gone
+                                                    activity.unqueued(len);
+                                                }
+                                            });
+                                        }
+                                    }
+                                }
+                                if let (Some(shown), Some(Wait { until: Until::Redrawn(redraw), .. })) =
+                                    (cursor_visibility(&event), &mut maybe_waiting)
+                                {
+                                    *redraw = redraw.cursor(shown);
+                                }
+                                if ends_frame(&event) {
+                                    // capture exactly at the boundary
+                                    let end = i + c.len_utf8();
+                                    vt.feed_str(&text[fed..end]);
+                                    extended.flush();
+                                    fed = end;
+                                    saved.frame = Some(capture(&vt, &extended, &modes));
                                 }
-                                None => {}
                             }
-                        }
-                        None => {
-                            closed_output = true;
+                            vt.feed_str(&text[fed..]);
+                            extended.flush();
+                            if let Some(Wait { until: Until::Output { seen, .. }, .. }) = &mut maybe_waiting {
+                                if seen.len() > MAX_SEEN_OUTPUT {
+                                    let mut cut = seen.len() - MAX_SEEN_OUTPUT;
+                                    while !seen.is_char_boundary(cut) {
+                                        cut += 1;
+                                    }
+                                    seen.drain(..cut);
+                                }
+                            }
+                            last_output = Instant::now();
+                            ready |= modes.alt_screen();
+                            if modes.is_set(SYNCHRONIZED_OUTPUT) {
+                                observed.synchronized_output.store(true, Ordering::Relaxed);
+                            }
+                            *observed.key_encoding.lock().unwrap() = modes.key_encoding();
+                            blocked_polls = 0;
+
+                            let title = observed.titles.lock().unwrap().current.clone();
                             match maybe_waiting.take() {
-                                Some(waiting) => {
-                                    let lines = vt.view().to_vec();
-                                    let answer = Reply{lines: lines, error: error.clone()};
+                                Some(waiting) if satisfied(&waiting, &modes, &vt, ready, title.as_deref()) => {
                                     // ignore failure, keep going until cancelled
+                                    let answer = reply(&vt, &extended, &modes, &saved, &waiting, &error, true);
                                     _ = waiting.reply.send(answer);
-                                    req_until = Instant::now() + Duration::from_millis(9999999999);
-                                    maybe_waiting = None
+                                    req_until = never();
+                                }
+                                Some(mut waiting) => {
+                                    // output elsewhere doesn't count when settling in a region
+                                    let unchanged = match &mut waiting.until {
+                                        Until::SettledIn { bounds, cells } => {
+                                            let now = cells_in(vt.view(), *bounds);
+                                            let unchanged = now == *cells;
+                                            *cells = now;
+                                            unchanged
+                                        }
+                                        _ => false,
+                                    };
+                                    if !unchanged {
+                                        // got output, unsettling, reset wait
+                                        req_until = give_up_at(&waiting, req_started, Some(last_output));
+                                    }
+                                    maybe_waiting = Some(waiting);
                                 }
                                 None => {}
                             }
                         }
+                        Some(Output::Exited { status, restarting }) => {
+                            if !restarting {
+                                *observed.exit_status.lock().unwrap() = Some(status.clone());
+                            }
+                            if status.failed() {
+                                metrics::add(&METRICS.failures, 1);
+                                let palette = observed.palette.lock().unwrap().clone();
+                                let snapshot = Snapshot::new(capture(&vt, &extended, &modes), palette);
+                                observed.failures.lock().unwrap().push((status, snapshot));
+                            }
+                            if restarting {
+                                metrics::add(&METRICS.restarts, 1);
+                                // the new child gets a new pty
+                                vt = avt::Vt::builder().size(cols, rows).scrollback_limit(scrollback).build();
+                                scanner = Scanner::new();
+                                extended = Extended::new(cols, rows);
+                                modes = Modes::default();
+                                ready = false;
+                                saved = Saved::default();
+                                observed.palette.lock().unwrap().reset();
+                                observed.titles.lock().unwrap().current = None;
+                                *observed.key_encoding.lock().unwrap() = KeyEncoding::Legacy;
+                            }
+                        }
+                        None => {
+                            closed_output = true;
+                            if let Some(waiting) = maybe_waiting.take() {
+                                let reached = waiting.until.reached_at_exit();
+                                // ignore failure, keep going until cancelled
+                                let answer = reply(&vt, &extended, &modes, &saved, &waiting, &error, reached);
+                                _ = waiting.reply.send(answer);
+                                req_until = never();
+                            }
+                        }
                     }
                 }
                 maybe_req = req_rx.recv() => {
                     match maybe_req {
-                        Some(req) => {
-                            let now = Instant::now();
+                        Some(Req::Wait(mut wait)) => {
+                            match &mut wait.until {
+                                Until::Changed(lines @ None) => *lines = Some(vt.view().to_vec()),
+                                Until::SettledIn { bounds, cells } => *cells = cells_in(vt.view(), *bounds),
+                                Until::Output { lookback, seen, .. } => {
+                                    let last = recent.last(*lookback);
+                                    let last = match &codec {
+                                        Some(codec) => Cow::Owned(Decoder::new(codec.clone()).decode(&last)),
+                                        None => String::from_utf8_lossy(&last),
+                                    };
+                                    *seen = printed_text(&last);
+                                }
+                                _ => {}
+                            }
                             // if there was another one it will be cancelled
-                            req_until = now + req.wait_first;
-                            maybe_waiting = Some(req);
+                            maybe_waiting = None;
+                            let title = observed.titles.lock().unwrap().current.clone();
+                            if satisfied(&wait, &modes, &vt, ready, title.as_deref()) {
+                                let answer = reply(&vt, &extended, &modes, &saved, &wait, &error, true);
+                                _ = wait.reply.send(answer);
+                                req_until = never();
+                            } else if closed_output {
+                                // the child is gone, nothing more is coming
+                                let reached = wait.until.reached_at_exit();
+                                let answer = reply(&vt, &extended, &modes, &saved, &wait, &error, reached);
+                                _ = wait.reply.send(answer);
+                                req_until = never();
+                            } else {
+                                req_started = Instant::now();
+                                req_until = give_up_at(&wait, req_started, None);
+                                blocked_polls = 0;
+                                maybe_waiting = Some(wait);
+                            }
+                        }
+                        Some(Req::Snapshot(buffer, reply)) => {
+                            let capture = saved.inactive(buffer, &vt, &modes).unwrap_or_else(|| capture(&vt, &extended, &modes));
+                            _ = reply.send(capture);
+                        }
+                        Some(Req::History(reply)) => {
+                            _ = reply.send(saved.history(&vt, &modes, scrollback));
+                        }
+                        Some(Req::Modes(reply)) => {
+                            _ = reply.send(modes.set_modes());
+                        }
+                        Some(Req::RecentOutput(bytes, reply)) => {
+                            _ = reply.send(recent.last(bytes));
+                        }
+                        Some(Req::Resize { cols: new_cols, rows: new_rows, reply }) => {
+                            (cols, rows) = (new_cols, new_rows);
+                            vt.resize(cols, rows);
+                            extended.resize(cols, rows);
+                            #[cfg(feature = "recording")]
+                            if let Some(Err(e)) = recorder.as_mut().map(|r| r.resize(cols, rows)) {
+                                *last_error.lock().unwrap() = Some(format!("recording stopped: {}", e));
+                                recorder = None;
+                            }
+                            // of a different size
+                            saved.frame = None;
+                            _ = reply.send(());
                         }
                         None => {
                             // channel has closed
@@ -77,18 +489,52 @@ pub async fn run_term(
                     break;
                 }
 
+                _ = poll.tick(), if polling => {
+                    if let Some(Wait { until: Until::BlockedOnInput(pid), .. }) = maybe_waiting {
+                        if procfs::blocked_on_input(pid) {
+                            blocked_polls += 1;
+                        } else {
+                            blocked_polls = 0;
+                        }
+                        if blocked_polls >= BLOCKED_POLLS {
+                            if let Some(waiting) = maybe_waiting.take() {
+                                let answer = reply(&vt, &extended, &modes, &saved, &waiting, &error, true);
+                                _ = waiting.reply.send(answer);
+                                req_until = never();
+                            }
+                        }
+                    }
+                }
+
                 _ = sleep(wait) =>{
-                    // settled
-                    match maybe_waiting.take() {
-                        Some(waiting) => {
-                            let lines = vt.view().to_vec();
-                            let answer = Reply{lines: lines, error: error.clone()};
-                            // ignore failure, keep going until cancelled
-                            _ = waiting.reply.send(answer);
-                            req_until = Instant::now() + Duration::from_millis(9999999999);
-                            maybe_waiting = None
+                    let max_hit = maybe_waiting
+                        .as_ref()
+                        .and_then(|w| w.waits.max)
+                        .is_some_and(|max| req_started.elapsed() >= max);
+                    let in_update = modes.is_set(SYNCHRONIZED_OUTPUT)
+                        && last_output.elapsed() < SYNCHRONIZED_UPDATE_TIMEOUT;
+                    match maybe_waiting {
+                        Some(Wait { ref until, at_frame: true, .. }) if until.settles() && in_update && !max_hit => {
+                            // not settled halfway through an update
+                            req_until = last_output + SYNCHRONIZED_UPDATE_TIMEOUT;
+                            continue;
                         }
-                        None => {}
+                        Some(Wait { until: Until::Redrawn(redraw), waits: Waits { max: Some(max), .. }, .. })
+                            if redraw != Redraw::Done && last_output > req_started && !max_hit =>
+                        {
+                            // quiet, but not settled until the cursor is shown again
+                            req_until = req_started + max;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    // settled, or timed out
+                    if let Some(waiting) = maybe_waiting.take() {
+                        let reached = waiting.until.settles() && !max_hit;
+                        // ignore failure, keep going until cancelled
+                        let answer = reply(&vt, &extended, &modes, &saved, &waiting, &error, reached);
+                        _ = waiting.reply.send(answer);
+                        req_until = never();
                     }
                 }
             }
@@ -0,0 +1,125 @@
+use crate::palette::Palette;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use rgb::RGB8;
+use std::path::PathBuf;
+
+fn rgb8([r, g, b]: [u8; 3]) -> RGB8 {
+    RGB8 { r, g, b }
+}
+
+/// How exporters draw snapshots: colors, font and cell geometry
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Colors for the first indexes, replacing the standard ones, None for the standard palette
+    pub palette: Option<Vec<[u8; 3]>>,
+    /// Default foreground color, None for palette color 7
+    pub foreground: Option<[u8; 3]>,
+    /// Default background color, None for palette color 0
+    pub background: Option<[u8; 3]>,
+    /// Path of a monospace TrueType font, None for the first of some common ones found installed
+    pub font: Option<PathBuf>,
+    /// Font size in pixels
+    pub font_size: f32,
+    /// Cell width in pixels, None for the advance of the font
+    pub cell_width: Option<usize>,
+    /// Cell height in pixels, None for the line height of the font times `line_spacing`
+    pub cell_height: Option<usize>,
+    /// Factor of the line height of the font for the cell height, text vertically centered
+    pub line_spacing: f32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            palette: None,
+            foreground: None,
+            background: None,
+            font: None,
+            font_size: 16.0,
+            cell_width: None,
+            cell_height: None,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+impl Theme {
+    /// The colors of a snapshot with the theme's in place of the standard ones,
+    /// keeping those the child redefined
+    pub fn apply(&self, palette: &Palette) -> Palette {
+        let mut themed = palette.clone();
+        for (i, color) in self.palette.iter().flatten().enumerate() {
+            if !palette.redefined(i as u8) {
+                themed.colors[i] = rgb8(*color);
+            }
+        }
+        if !palette.redefined_default(true) {
+            themed.foreground = self.foreground.map(rgb8).or(palette.foreground);
+        }
+        if !palette.redefined_default(false) {
+            themed.background = self.background.map(rgb8).or(palette.background);
+        }
+        themed
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl Theme {
+    /// Create a Theme, colors as `(r, g, b)` sequences with `palette` replacing up to 256 indexed
+    /// colors (e.g. the 16 of a color scheme, or `Terminal.palette()`)
+    #[new]
+    #[pyo3(signature = (*, palette=None, foreground=None, background=None, font=None, font_size=16.0, cell_width=None, cell_height=None, line_spacing=1.0))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn py_new(
+        palette: Option<Vec<[u8; 3]>>,
+        foreground: Option<[u8; 3]>,
+        background: Option<[u8; 3]>,
+        font: Option<PathBuf>,
+        font_size: f32,
+        cell_width: Option<usize>,
+        cell_height: Option<usize>,
+        line_spacing: f32,
+    ) -> PyResult<Self> {
+        if palette.as_ref().is_some_and(|p| p.len() > 256) {
+            return Err(PyValueError::new_err("palette has more than 256 colors"));
+        }
+        if !(font_size > 0.0 && line_spacing > 0.0) {
+            return Err(PyValueError::new_err("font_size and line_spacing must be positive"));
+        }
+        if cell_width == Some(0) || cell_height == Some(0) {
+            return Err(PyValueError::new_err("invalid cell size"));
+        }
+        Ok(Theme {
+            palette,
+            foreground,
+            background,
+            font,
+            font_size,
+            cell_width,
+            cell_height,
+            line_spacing,
+        })
+    }
+
+    pub fn __repr__(&self) -> String {
+        let color = |c: Option<[u8; 3]>| c.map_or("None".to_string(), |[r, g, b]| format!("({}, {}, {})", r, g, b));
+        let size = |s: Option<usize>| s.map_or("None".to_string(), |s| s.to_string());
+        format!(
+            "Theme(palette={}, foreground={}, background={}, font={}, font_size={}, \
+             cell_width={}, cell_height={}, line_spacing={})",
+            self.palette.as_ref().map_or("None".to_string(), |p| format!("<{} colors>", p.len())),
+            color(self.foreground),
+            color(self.background),
+            self.font.as_ref().map_or("None".to_string(), |f| format!("'{}'", f.display())),
+            self.font_size,
+            size(self.cell_width),
+            size(self.cell_height),
+            self.line_spacing,
+        )
+    }
+}
@@ -0,0 +1,37 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// changes kept, older ones are dropped beyond this
+const MAX_HISTORY: usize = 10000;
+
+/// The window title as set by the child with OSC 0 and OSC 2, and how it changed
+#[derive(Default)]
+pub struct Titles {
+    /// None until the child sets one, and again after it is restarted
+    pub current: Option<String>,
+    /// Every change as `(timestamp, title)`, timestamps in seconds since the epoch
+    pub history: VecDeque<(f64, String)>,
+}
+
+/// Updated by the term task, read by the Terminal
+pub type SharedTitles = Arc<Mutex<Titles>>;
+
+impl Titles {
+    /// Takes an OSC string, which may set the title
+    pub fn apply(&mut self, osc: &str) {
+        let (command, title) = osc.split_once(';').unwrap_or((osc, ""));
+        if command != "0" && command != "2" {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back((timestamp, title.to_string()));
+        self.current = Some(title.to_string());
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use crate::activity::Activity;
+use crate::metrics::{self, METRICS};
+use crate::procfs;
+use crate::pty::CurrentChild;
+
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Called when the session is flagged as hung, on a blocking thread
+pub type Callback = Arc<dyn Fn() + Send + Sync>;
+
+/// Flags the session as hung when there has been no output, no input consumed and no change
+/// in the child's process state for `timeout`, calling `callback` once for every time it happens.
+pub async fn run_watchdog(
+    timeout: Duration,
+    current: CurrentChild,
+    activity: Arc<Activity>,
+    hung: Arc<AtomicBool>,
+    callback: Option<Callback>,
+    token: CancellationToken,
+) {
+    let mut check = interval((timeout / 4).clamp(Duration::from_millis(1), MAX_CHECK_INTERVAL));
+    check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let child = |current: &CurrentChild| current.lock().unwrap().as_ref().map(|s| s.child);
+    let mut pid = child(&current);
+    let mut state = pid.and_then(procfs::state);
+    let mut state_changed = Instant::now();
+
+    loop {
+        tokio::select! {
+            _ = check.tick() => {
+                let new_pid = child(&current);
+                let new_state = new_pid.and_then(procfs::state);
+                if new_pid != pid || new_state != state {
+                    // a restart counts as a state change
+                    pid = new_pid;
+                    state = new_state;
+                    state_changed = Instant::now();
+                }
+                if matches!(state, None | Some('Z')) {
+                    // gone, not hung
+                    hung.store(false, Ordering::Relaxed);
+                    continue;
+                }
+
+                let quiet = activity
+                    .since_output()
+                    .min(activity.since_input())
+                    .min(state_changed.elapsed());
+
+                if quiet < timeout {
+                    hung.store(false, Ordering::Relaxed);
+                } else if !hung.swap(true, Ordering::Relaxed) {
+                    metrics::add(&METRICS.hangs, 1);
+                    if let Some(callback) = &callback {
+                        let callback = callback.clone();
+                        tokio::task::spawn_blocking(move || callback());
+                    }
+                }
+            }
+
+            _ = token.cancelled() => {
+                break;
+            }
+        }
+    }
+}